@@ -0,0 +1,105 @@
+#import bevy_pbr::mesh_functions as mesh_functions
+#import bevy_pbr::mesh_bindings mesh
+#import bevy_pbr::mesh_view_bindings view, fog
+#import bevy_pbr::mesh_view_types FOG_MODE_OFF
+#import bevy_pbr::pbr_functions as pbr_functions
+#import bevy_core_pipeline::tonemapping tone_mapping
+
+struct TerrainMaterial {
+    grass: vec4<f32>,
+    rock: vec4<f32>,
+    snow: vec4<f32>,
+    sand: vec4<f32>,
+    detail_scale: f32,
+    biome_tint: f32,
+};
+
+@group(1) @binding(0)
+var<uniform> material: TerrainMaterial;
+@group(1) @binding(1)
+var detail_texture: texture_2d<f32>;
+@group(1) @binding(2)
+var detail_sampler: sampler;
+
+// locations set in `TerrainMaterial::specialize()`
+struct Vertex {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) color: vec4<f32>,
+    @location(3) splat: vec4<f32>,
+};
+
+struct TerrainVertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) world_position: vec4<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) color: vec4<f32>,
+    @location(3) splat: vec4<f32>,
+};
+
+@vertex
+fn vertex(vertex: Vertex) -> TerrainVertexOutput {
+    var out: TerrainVertexOutput;
+    out.world_position = mesh_functions::mesh_position_local_to_world(mesh.model, vec4<f32>(vertex.position, 1.0));
+    out.position = mesh_functions::mesh_position_world_to_clip(out.world_position);
+    out.world_normal = mesh_functions::mesh_normal_local_to_world(vertex.normal);
+    out.color = vertex.color;
+    out.splat = vertex.splat;
+    return out;
+}
+
+// the detail texture projected along the three axes, blended by how much the
+// surface faces each of them. no stretching on cliffs, unlike the mesh uvs.
+fn triplanar_detail(world_position: vec3<f32>, normal: vec3<f32>) -> vec4<f32> {
+    var weights = pow(abs(normal), vec3(4.0));
+    weights = weights / (weights.x + weights.y + weights.z);
+    let p = world_position / material.detail_scale;
+    let x = textureSample(detail_texture, detail_sampler, p.zy);
+    let y = textureSample(detail_texture, detail_sampler, p.xz);
+    let z = textureSample(detail_texture, detail_sampler, p.xy);
+    return x * weights.x + y * weights.y + z * weights.z;
+}
+
+@fragment
+fn fragment(
+    in: TerrainVertexOutput,
+    @builtin(front_facing) is_front: bool,
+) -> @location(0) vec4<f32> {
+    let normal = normalize(in.world_normal);
+    let detail = triplanar_detail(in.world_position.xyz, normal);
+
+    // height blending: where layers mix, the one with more detail showing wins,
+    // so the edges follow the patterns instead of being a smooth fade
+    var splat = in.splat * (detail + vec4(0.25));
+    splat = pow(splat, vec4(4.0));
+    splat = splat / max(dot(splat, vec4(1.0)), 0.0001);
+
+    let shade = vec4(0.7) + 0.6 * detail;
+    var color = material.grass.rgb * shade.x * splat.x
+        + material.rock.rgb * shade.y * splat.y
+        + material.snow.rgb * shade.z * splat.z
+        + material.sand.rgb * shade.w * splat.w;
+    color = mix(color, color * in.color.rgb * 2.0, material.biome_tint);
+
+    var pbr_input = pbr_functions::pbr_input_new();
+    pbr_input.material.base_color = vec4(color, 1.0);
+    pbr_input.material.metallic = 0.0;
+    // wet sand and snow shine a bit, grass and rock don't
+    pbr_input.material.perceptual_roughness = 0.9 - 0.4 * (splat.z + splat.w);
+    pbr_input.frag_coord = in.position;
+    pbr_input.world_position = in.world_position;
+    pbr_input.world_normal = pbr_functions::prepare_world_normal(in.world_normal, false, is_front);
+    pbr_input.is_orthographic = view.projection[3].w == 1.0;
+    pbr_input.N = normalize(pbr_input.world_normal);
+    pbr_input.V = pbr_functions::calculate_view(in.world_position, pbr_input.is_orthographic);
+    pbr_input.flags = mesh.flags;
+    var output_color = pbr_functions::pbr(pbr_input);
+
+    if (fog.mode != FOG_MODE_OFF) {
+        output_color = pbr_functions::apply_fog(fog, output_color, in.world_position.xyz, view.world_position.xyz);
+    }
+#ifdef TONEMAP_IN_SHADER
+    output_color = tone_mapping(output_color, view.color_grading);
+#endif
+    return output_color;
+}
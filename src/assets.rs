@@ -6,6 +6,8 @@ use bevy_hanabi::prelude::*;
 use bevy_inspector_egui::prelude::InspectorOptions;
 use bevy_rapier3d::prelude::*;
 
+use crate::gameplay::WIND_EFFECT_PROPERTY;
+
 pub struct GameAssetsPlugin;
 impl Plugin for GameAssetsPlugin {
     fn build(&self, app: &mut App) {
@@ -23,6 +25,7 @@ impl Plugin for GameAssetsPlugin {
 pub struct BulletAssets {
     pub flying_effect: Handle<EffectAsset>,
     pub hit_effect: Handle<EffectAsset>,
+    pub splash_effect: Handle<EffectAsset>,
     pub mesh: Handle<Mesh>,
     pub material: Handle<StandardMaterial>,
     #[reflect(ignore)]
@@ -60,6 +63,20 @@ fn load_glb_scenes(mut scene_assets: ResMut<GameSceneAssets>, ass: Res<AssetServ
             scene_assets.scenes.insert(key, my_gltf);
         }
     }
+
+    for def in crate::props::PROP_LIBRARY {
+        let key = def.scene_key();
+        scene_assets
+            .scenes
+            .insert(key.clone(), ass.load(format!("{}#Scene0", key)));
+        if def.fracture_bounds.is_some() {
+            for key in [def.fracture_key(), def.damaged_key()] {
+                scene_assets
+                    .scenes
+                    .insert(key.clone(), ass.load(format!("{}#Scene0", key)));
+            }
+        }
+    }
 }
 
 pub const BULLET_SIZE: f32 = 0.05;
@@ -83,6 +100,65 @@ fn setup_bullet_assets(
 
     bullet_assets.flying_effect = effects.add(get_portal_effect());
     bullet_assets.hit_effect = effects.add(get_firework_effect());
+    bullet_assets.splash_effect = effects.add(get_splash_effect());
+}
+
+fn get_splash_effect() -> EffectAsset {
+    let mut color_gradient1 = Gradient::new();
+    color_gradient1.add_key(0.0, Vec4::new(2.0, 2.2, 2.5, 1.0));
+    color_gradient1.add_key(0.5, Vec4::new(0.8, 1.0, 1.2, 0.8));
+    color_gradient1.add_key(1.0, Vec4::new(0.5, 0.7, 0.9, 0.0));
+
+    let mut size_gradient1 = Gradient::new();
+    size_gradient1.add_key(0.0, Vec2::splat(0.4));
+    size_gradient1.add_key(0.3, Vec2::splat(1.0));
+    size_gradient1.add_key(1.0, Vec2::splat(0.2));
+
+    let writer = ExprWriter::new();
+
+    let lifetime = writer.lit(1.0).uniform(writer.lit(1.8)).expr();
+    let init_lifetime = SetAttributeModifier::new(Attribute::LIFETIME, lifetime);
+
+    let age = writer.lit(0.).expr();
+    let init_age = SetAttributeModifier::new(Attribute::AGE, age);
+
+    // the water falls back down, a bit faster than the firework sparks
+    let accel = writer.lit(Vec3::Y * -9.81).expr();
+    let update_accel = AccelModifier::new(accel);
+
+    let drag = writer.lit(1.).expr();
+    let update_drag = LinearDragModifier::new(drag);
+
+    // a ring on the water...
+    let init_pos = SetPositionCircleModifier {
+        center: writer.lit(Vec3::ZERO).expr(),
+        axis: writer.lit(Vec3::Y).expr(),
+        radius: writer.lit(1.5).expr(),
+        dimension: ShapeDimension::Volume,
+    };
+
+    // ...thrown away from a point under it, so it goes up in a column
+    let init_vel = SetVelocitySphereModifier {
+        center: writer.lit(Vec3::Y * -3.).expr(),
+        speed: (writer.rand(ScalarType::Float) * writer.lit(13.) + writer.lit(12.)).expr(),
+    };
+
+    EffectAsset::new(4096, Spawner::once(400.0.into(), true), writer.finish())
+        .with_name("splash")
+        .init(init_pos)
+        .init(init_vel)
+        .init(init_age)
+        .init(init_lifetime)
+        .update(update_drag)
+        .update(update_accel)
+        .render(ColorOverLifetimeModifier {
+            gradient: color_gradient1,
+        })
+        .render(SizeOverLifetimeModifier {
+            gradient: size_gradient1,
+            screen_space_size: false,
+        })
+        .render(BillboardModifier {})
 }
 
 fn get_firework_effect() -> EffectAsset {
@@ -108,13 +184,15 @@ fn get_firework_effect() -> EffectAsset {
     let lifetime = writer.lit(1.8).uniform(writer.lit(2.5)).expr();
     let init_lifetime = SetAttributeModifier::new(Attribute::LIFETIME, lifetime);
 
-    // Add constant downward acceleration to simulate gravity
-    let accel = writer.lit(Vec3::Y * -8.).expr();
-    let update_accel = AccelModifier::new(accel);
-
     // Add drag to make particles slow down a bit after the initial explosion
-    let drag = writer.lit(5.).expr();
-    let update_drag = LinearDragModifier::new(drag);
+    let drag = 5.;
+    let update_drag = LinearDragModifier::new(writer.lit(drag).expr());
+
+    // Add constant downward acceleration to simulate gravity, and the wind
+    // pushing against the drag, so the smoke drifts at the wind speed
+    let wind = writer.add_property(WIND_EFFECT_PROPERTY, Vec3::ZERO.into());
+    let accel = writer.lit(Vec3::Y * -8.) + writer.prop(wind) * writer.lit(drag);
+    let update_accel = AccelModifier::new(accel.expr());
 
     let init_pos = SetPositionSphereModifier {
         center: writer.lit(Vec3::ZERO).expr(),
@@ -181,8 +259,12 @@ fn get_portal_effect() -> EffectAsset {
     let init_lifetime = SetAttributeModifier::new(Attribute::LIFETIME, lifetime);
 
     // Add drag to make particles slow down a bit after the initial acceleration
-    let drag = writer.lit(1.5).expr();
-    let update_drag = LinearDragModifier::new(drag);
+    let drag = 1.5;
+    let update_drag = LinearDragModifier::new(writer.lit(drag).expr());
+
+    // the trail drifts at the wind speed, where the wind and the drag even out
+    let wind = writer.add_property(WIND_EFFECT_PROPERTY, Vec3::ZERO.into());
+    let update_wind = AccelModifier::new((writer.prop(wind) * writer.lit(drag)).expr());
 
     let mut module = writer.finish();
 
@@ -194,6 +276,7 @@ fn get_portal_effect() -> EffectAsset {
         .init(init_age)
         .init(init_lifetime)
         .update(update_drag)
+        .update(update_wind)
         .update(tangent_accel)
         .render(ColorOverLifetimeModifier {
             gradient: color_gradient1,
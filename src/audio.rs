@@ -41,10 +41,12 @@ pub struct PlaySpatialAudioEvent {
 }
 
 impl PlaySpatialAudioEvent {
-    const ALL_KEYS: [&'static str; 3] = [
+    const ALL_KEYS: [&'static str; 5] = [
         "explosion/canon_fire",
         "explosion/distant_boom",
         "explosion/close_explosion",
+        "explosion/hit_effect",
+        "explosion/explode_building",
     ];
     fn rand_speed() -> f32 {
         const SPEED_JITTER: f32 = 0.3;
@@ -88,6 +90,29 @@ impl PlaySpatialAudioEvent {
             playback_volume: 0.5,
         }
     }
+    pub fn water_splash(parent_ent: Entity) -> Self {
+        Self {
+            parent_ent,
+            asset_key: "explosion/hit_effect".to_string(),
+            randomize: true,
+            attach_to_parent: false,
+            sound_reach: 300.0,
+            // slower sounds wetter
+            playback_speed: Self::rand_speed() * 0.7,
+            playback_volume: 0.6,
+        }
+    }
+    pub fn explode_building(parent_ent: Entity) -> Self {
+        Self {
+            parent_ent,
+            asset_key: "explosion/explode_building".to_string(),
+            randomize: true,
+            attach_to_parent: false,
+            sound_reach: 600.0,
+            playback_speed: Self::rand_speed(),
+            playback_volume: 0.7,
+        }
+    }
 }
 
 #[derive(Reflect, Component, Default, InspectorOptions)]
@@ -0,0 +1,71 @@
+use bevy::prelude::Vec3;
+use game::terrain::{
+    height, heights, TerrainSettings, MOUNTAIN_HEIGHT, NOISE_BASE_FREQ, NOISE_SEED,
+};
+use game::terrain_export::refined_terrain;
+use std::time::Instant;
+
+/// how heights were sampled before batching: a 2x2 noise block per sample, 4 samples per vertex
+fn old_height(pos: &Vec3) -> f32 {
+    use simdnoise::NoiseBuilder;
+    let noise_single = |x: f32, y: f32, seed: i32| {
+        NoiseBuilder::fbm_2d_offset(x, 2, y, 2)
+            .with_seed(seed)
+            .generate_scaled(-1.0, 1.0)[0]
+    };
+    (1..=2)
+        .map(|i| {
+            let exp = 1.3_f32.powi(i);
+            let freq = NOISE_BASE_FREQ * exp;
+            (0..2)
+                .map(|j| noise_single(pos.x / freq, pos.z / freq, (j + 1) * NOISE_SEED + i))
+                .sum::<f32>()
+                * MOUNTAIN_HEIGHT
+                / exp
+        })
+        .sum::<f32>()
+        / 2.0
+}
+
+fn main() {
+    let count: usize = std::env::args()
+        .nth(1)
+        .map(|arg| {
+            arg.parse()
+                .expect("usage: bench_terrain_heights [point count]")
+        })
+        .unwrap_or(200_000);
+    let positions: Vec<Vec3> = (0..count)
+        .map(|i| Vec3::new((i % 1000) as f32 * 3.7, 0.0, (i / 1000) as f32 * 5.3))
+        .collect();
+
+    let start = Instant::now();
+    let old: f32 = positions.iter().map(old_height).sum();
+    let old_time = start.elapsed();
+
+    let start = Instant::now();
+    let single: f32 = positions.iter().map(height).sum();
+    let single_time = start.elapsed();
+
+    let start = Instant::now();
+    let batched: f32 = heights(&positions).iter().sum();
+    let batched_time = start.elapsed();
+
+    println!("{count} points");
+    println!("2x2 block per sample: {old_time:?} ({old})");
+    println!("height() per point:   {single_time:?} ({single})");
+    println!("heights() batched:    {batched_time:?} ({batched})");
+    println!(
+        "speedup: {:.1}x over the old sampling, {:.1}x over height()",
+        old_time.as_secs_f64() / batched_time.as_secs_f64(),
+        single_time.as_secs_f64() / batched_time.as_secs_f64()
+    );
+
+    // the split path: every level of the LOD around a probe, meshes included
+    let settings = TerrainSettings::default();
+    let start = Instant::now();
+    let meshes = refined_terrain(&settings, &[Vec3::ZERO], 2000.0);
+    let split_time = start.elapsed();
+    let tri_count: usize = meshes.iter().map(|mesh| mesh.triangle_count()).sum();
+    println!("split around a probe: {split_time:?} ({tri_count} triangles)");
+}
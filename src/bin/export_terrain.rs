@@ -0,0 +1,79 @@
+use bevy::prelude::Vec3;
+use game::terrain::TerrainSettings;
+use game::terrain_export::{refined_terrain, write_glb, write_obj, TerrainMesh};
+use std::fs::File;
+use std::io::BufWriter;
+use std::time::Instant;
+
+const USAGE: &str = "usage: export_terrain [--settings terrain.ron] [--probe x,y,z]... \
+                     [--radius meters] [--split] out.obj|out.glb...";
+
+fn parse_probe(arg: &str) -> Vec3 {
+    let coords: Vec<f32> = arg
+        .split(',')
+        .map(|c| c.trim().parse().expect(USAGE))
+        .collect();
+    let [x, y, z] = coords[..] else {
+        panic!("{}", USAGE);
+    };
+    Vec3::new(x, y, z)
+}
+
+/// Builds the planet without a window or gpu, refines it around the probes and
+/// writes the meshes out. `--split` keeps every base triangle as its own object,
+/// otherwise it's all one mesh.
+fn main() {
+    let mut settings = TerrainSettings::default();
+    let mut probes = vec![];
+    let mut radius = 1000.0;
+    let mut split = false;
+    let mut outputs = vec![];
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--settings" => {
+                let path = args.next().expect(USAGE);
+                let text = std::fs::read_to_string(&path).expect("can't read the settings");
+                settings = ron::from_str(&text).expect("bad terrain settings");
+            }
+            "--probe" => probes.push(parse_probe(&args.next().expect(USAGE))),
+            "--radius" => radius = args.next().expect(USAGE).parse().expect(USAGE),
+            "--split" => split = true,
+            "--help" | "-h" => {
+                println!("{USAGE}");
+                return;
+            }
+            _ if arg.ends_with(".obj") || arg.ends_with(".glb") => outputs.push(arg),
+            _ => panic!("unknown argument {arg:?}\n{USAGE}"),
+        }
+    }
+    if outputs.is_empty() {
+        panic!("{}", USAGE);
+    }
+
+    let start = Instant::now();
+    let mut meshes = refined_terrain(&settings, &probes, radius);
+    let triangles: usize = meshes.iter().map(|m| m.triangle_count()).sum();
+    println!(
+        "{:?} planet, {} base meshes, {} triangles in {:?}",
+        settings.PLANET_SHAPE,
+        meshes.len(),
+        triangles,
+        start.elapsed()
+    );
+    if !split {
+        meshes = vec![TerrainMesh::merged("terrain", &meshes)];
+    }
+
+    for path in outputs {
+        let mut out = BufWriter::new(File::create(&path).expect("can't create the output"));
+        if path.ends_with(".obj") {
+            write_obj(&mut out, &meshes)
+        } else {
+            write_glb(&mut out, &meshes)
+        }
+        .expect("can't write the output");
+        println!("wrote {path}");
+    }
+}
@@ -17,13 +17,12 @@ use bevy::{
 };
 
 use crate::audio::SpatialAudioListener;
-use crate::planet::TerrainSplitProbe;
 use crate::raycast::TerrainRaycastSet;
 use crate::terrain::PLANET_MAX_PLAY_RADIUS;
 
 // use std::collections::{vec_deque, VecDeque};
 use super::menu::UiMenuState;
-use super::terrain::height;
+use super::terrain::{apply_height, planet_shape, up};
 
 // use bevy::ecs::event::Events;
 use bevy::input::mouse::MouseWheel;
@@ -176,7 +175,6 @@ fn setup_flying_camera(mut commands: Commands) {
     let player = commands
         .spawn((
             FlyingCameraBundle { ..default() },
-            TerrainSplitProbe,
             Name::new("THE FLYING CAMERA"),
         ))
         .id();
@@ -279,7 +277,7 @@ fn rotate_camera_by_mouse(
     // capture movement events: wasd/scroll wheel
     {
         let mut velocity = Vec3::ZERO;
-        let _up = Vec3::Y;
+        let _up = up(&pivot_tr.translation);
         let _right = pivot_tr.right().normalize();
         let _forward = _up.cross(_right).normalize();
 
@@ -339,17 +337,20 @@ fn rotate_camera_by_mouse(
     camera_tr.rotation = Quat::from_axis_angle(Vec3::X, delta_state.pitch);
     pivot_tr.rotate_local_y(mouse_delta_yaw);
 
-    let _up = Vec3::Y;
+    let _up = up(&pivot_tr.translation);
     let _right = pivot_tr.right().normalize();
     let _forward = _up.cross(_right).normalize();
 
-    pivot_tr.translation.y = pivot_comp.camera_height + height(&pivot_tr.translation);
-    let mut _pos_xz = Vec3::new(pivot_tr.translation.x, 0.0, pivot_tr.translation.z);
-    let max_camera_xz = PLANET_MAX_PLAY_RADIUS;
-    if _pos_xz.length() > max_camera_xz {
-        _pos_xz = _pos_xz.normalize() * max_camera_xz;
-        pivot_tr.translation.x = _pos_xz.x;
-        pivot_tr.translation.z = _pos_xz.z;
+    pivot_tr.translation = apply_height(&pivot_tr.translation) + _up * pivot_comp.camera_height;
+    // the flat map has an edge; the globe can be flown all around
+    if !planet_shape().is_spherical() {
+        let mut _pos_xz = Vec3::new(pivot_tr.translation.x, 0.0, pivot_tr.translation.z);
+        let max_camera_xz = PLANET_MAX_PLAY_RADIUS;
+        if _pos_xz.length() > max_camera_xz {
+            _pos_xz = _pos_xz.normalize() * max_camera_xz;
+            pivot_tr.translation.x = _pos_xz.x;
+            pivot_tr.translation.z = _pos_xz.z;
+        }
     }
     let _target = pivot_tr.translation + _forward;
     pivot_tr.look_at(_target, _up);
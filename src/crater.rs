@@ -0,0 +1,173 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// side of the grid cells craters get sorted into, in meters
+const CRATER_CELL_SIZE: f32 = 32.0;
+
+type CellKey = [i32; 3];
+
+fn cell_key(pos: &Vec3) -> CellKey {
+    (*pos / CRATER_CELL_SIZE).floor().as_ivec3().to_array()
+}
+
+/// A bowl dug into the terrain.
+#[derive(Debug, Clone, Copy, PartialEq, Reflect, Serialize, Deserialize)]
+pub struct Crater {
+    /// on the zero-height planet surface, see `terrain::project_to_surface()`
+    pub center: Vec3,
+    /// meters
+    pub radius: f32,
+    /// meters, at the center
+    pub depth: f32,
+}
+
+impl Crater {
+    /// height added to the terrain at `surface`, a point on the zero-height planet surface.
+    /// smooth at the rim, so the normals don't crease
+    pub fn offset(&self, surface: &Vec3) -> f32 {
+        let t = surface.distance_squared(self.center) / (self.radius * self.radius);
+        if t >= 1.0 {
+            return 0.0;
+        }
+        -self.depth * (1.0 - t) * (1.0 - t)
+    }
+
+    /// could the crater change the terrain over the circle around `center`, both on
+    /// the zero-height planet surface
+    pub fn touches(&self, center: &Vec3, radius: f32) -> bool {
+        self.center.distance(*center) < self.radius + radius
+    }
+}
+
+/// All the craters on the planet, on top of the `TerrainGenerator` heights.
+/// Saved as a plain list, the lookup grid is rebuilt on load.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(from = "Vec<Crater>", into = "Vec<Crater>")]
+pub struct Craters {
+    craters: Vec<Crater>,
+    /// indices of the craters touching each cell
+    cells: BTreeMap<CellKey, Vec<usize>>,
+}
+
+impl Craters {
+    pub const fn new() -> Self {
+        Self {
+            craters: Vec::new(),
+            cells: BTreeMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, crater: Crater) {
+        let index = self.craters.len();
+        self.craters.push(crater);
+        let min = cell_key(&(crater.center - Vec3::splat(crater.radius)));
+        let max = cell_key(&(crater.center + Vec3::splat(crater.radius)));
+        for x in min[0]..=max[0] {
+            for y in min[1]..=max[1] {
+                for z in min[2]..=max[2] {
+                    self.cells.entry([x, y, z]).or_default().push(index);
+                }
+            }
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Crater> {
+        self.craters.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.craters.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.craters.is_empty()
+    }
+
+    /// height added to the terrain by all the craters at `surface`
+    pub fn offset(&self, surface: &Vec3) -> f32 {
+        if self.craters.is_empty() {
+            return 0.0;
+        }
+        let Some(cell) = self.cells.get(&cell_key(surface)) else {
+            return 0.0;
+        };
+        cell.iter().map(|i| self.craters[*i].offset(surface)).sum()
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+        std::fs::write(path, text)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        ron::from_str(&text)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))
+    }
+}
+
+impl From<Vec<Crater>> for Craters {
+    fn from(list: Vec<Crater>) -> Self {
+        let mut craters = Craters::new();
+        for crater in list {
+            craters.add(crater);
+        }
+        craters
+    }
+}
+
+impl From<Craters> for Vec<Crater> {
+    fn from(craters: Craters) -> Self {
+        craters.craters
+    }
+}
+
+#[cfg(test)]
+fn test_craters() -> Craters {
+    (0..50)
+        .map(|i| Crater {
+            center: Vec3::new(i as f32 * 13.7 - 300.0, 0.0, (i * i % 37) as f32 * 9.1),
+            radius: 5.0 + (i % 7) as f32 * 6.0,
+            depth: 1.0 + (i % 3) as f32,
+        })
+        .collect::<Vec<_>>()
+        .into()
+}
+
+#[test]
+fn test_crater_lookup_matches_all_craters() {
+    let craters = test_craters();
+    for i in 0..2000 {
+        let surface = Vec3::new(
+            (i % 50) as f32 * 14.3 - 350.0,
+            0.0,
+            (i / 50) as f32 * 9.7 - 20.0,
+        );
+        let expected: f32 = craters.iter().map(|c| c.offset(&surface)).sum();
+        assert!((craters.offset(&surface) - expected).abs() < 1e-4);
+    }
+
+    let crater = craters.iter().next().unwrap();
+    assert_eq!(crater.offset(&crater.center), -crater.depth);
+    assert_eq!(
+        crater.offset(&(crater.center + Vec3::X * crater.radius)),
+        0.0
+    );
+}
+
+#[test]
+fn test_craters_round_trip_through_ron() {
+    let craters = test_craters();
+    let text = ron::to_string(&craters).unwrap();
+    let loaded: Craters = ron::from_str(&text).unwrap();
+    assert_eq!(
+        loaded.iter().collect::<Vec<_>>(),
+        craters.iter().collect::<Vec<_>>()
+    );
+    let surface = craters.iter().nth(3).unwrap().center;
+    assert_eq!(loaded.offset(&surface), craters.offset(&surface));
+}
@@ -1,31 +1,59 @@
 use crate::assets::BULLET_SIZE;
 use crate::audio::PlaySpatialAudioEvent;
-use crate::gameplay::bullet_physics::{BULLET_DENSITY, BULLET_LINEAR_DAMPING, GRAVITY_SCALE};
-use crate::terrain::apply_height;
+use crate::crater::Crater;
+use crate::gameplay::bullet_physics::{
+    ProjectileState, BULLET_LINEAR_DAMPING, GRAVITY_MAGNITUDE, GRAVITY_SCALE, PROJECTILE_TIMESTEP,
+    WIND_ACCEL_PER_SPEED,
+};
+use crate::terrain::{altitude, apply_height, project_to_surface, up, water_altitude, water_depth};
 use bevy::prelude::*;
 use bevy_hanabi::prelude::*;
 use bevy_rapier3d::prelude::*;
-use rand::random;
 
-use crate::planet::TerrainSplitProbe;
+use crate::planet::{TerrainCraterEvent, TerrainSplitProbe};
 use crate::{assets::BulletAssets, gameplay::events::TankCommandEventType};
 
 use super::events::BulletHitEvent;
+use super::wind::Wind;
 use super::{bullet_physics::TANK_BULLET_SPEED_PER_POWER, events::TankCommandEvent, tank::Tank};
 use std::time::Duration;
 
 pub struct BulletPlugin;
 impl Plugin for BulletPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(PreUpdate, (delete_tombstones,))
-            .add_systems(Update, (shoot_bullet, capture_bullet_impact).chain())
-            .add_systems(PostUpdate, (on_bullet_impact,));
+        app.init_resource::<ShellClock>()
+            .add_systems(PreUpdate, (delete_tombstones,))
+            .add_systems(FixedUpdate, (fly_bullets, tick_shell_clock).chain())
+            .add_systems(Update, shoot_bullet)
+            .add_systems(PostUpdate, (on_bullet_impact, on_bullet_splash));
     }
 }
 
 #[derive(Reflect, Component, Debug)]
 pub struct Bullet {
     shooter: Entity,
+    /// where it is for real, the `Transform` follows
+    flight: ProjectileState,
+    /// `ShellClock::seconds()` it was aimed at, for the wind gusts
+    fired_at: f32,
+}
+
+/// The `FixedUpdate` steps flown so far, the clock the shells read the gusts on.
+/// Unlike the frame time, the same shot always gets the same gusts.
+#[derive(Resource, Default, Debug)]
+pub struct ShellClock {
+    steps: u64,
+}
+
+impl ShellClock {
+    /// seconds at the next step, the first one of a shell fired now
+    pub fn seconds(&self) -> f32 {
+        self.steps as f32 * PROJECTILE_TIMESTEP
+    }
+}
+
+fn tick_shell_clock(mut clock: ResMut<ShellClock>) {
+    clock.steps += 1;
 }
 
 #[derive(Reflect, Component, Debug)]
@@ -40,6 +68,10 @@ pub struct BulletExplodingEffectMarker;
 #[derive(Reflect, Component)]
 pub struct BulletHit {}
 
+/// went into the sea: splashes, no explosion, no crater
+#[derive(Reflect, Component)]
+pub struct BulletSplash;
+
 fn delete_tombstones(
     mut commands: Commands,
     mut q: Query<(Entity, &mut BulletTombstone)>,
@@ -54,6 +86,10 @@ fn delete_tombstones(
     }
 }
 
+/// meters, a bit bigger than the smallest terrain triangles so it shows up
+const CRATER_RADIUS: f32 = 15.0;
+const CRATER_DEPTH: f32 = 4.0;
+
 #[allow(clippy::type_complexity)]
 fn on_bullet_impact(
     mut commands: Commands,
@@ -72,6 +108,7 @@ fn on_bullet_impact(
     bullet_assets: Res<BulletAssets>,
     mut events: EventWriter<BulletHitEvent>,
     mut audio_events: EventWriter<PlaySpatialAudioEvent>,
+    mut crater_events: EventWriter<TerrainCraterEvent>,
 ) {
     for (bullet_ent, bullet_tr, bullet_vel, bullet_children, _bullet_hit, bullet) in hits.iter() {
         // send event with all data
@@ -80,40 +117,94 @@ fn on_bullet_impact(
             bullet_pos: bullet_tr.translation,
             tank_ent: bullet.shooter,
         });
-        // put the tombstone on the thing
-        let tombstone_ent = commands
-            .spawn((
-                BulletTombstone(Timer::new(Duration::from_secs(6), TimerMode::Once)),
-                SpatialBundle::from_transform(Transform::from_translation(bullet_tr.translation)),
-            ))
-            .insert(Name::new("Bullet TOMBSTONE"))
-            .insert(TerrainSplitProbe)
-            .id();
-        // move the flying effect to tombstone and stop it from emitting
-        for child in bullet_children.iter() {
-            if let Ok((effect_ent, mut effect)) = flying_effects.get_mut(*child) {
-                commands.entity(effect_ent).set_parent(tombstone_ent);
-                effect.set_active(false);
-            }
+        // dig a crater if it went off on the ground
+        if altitude(&bullet_tr.translation) < CRATER_RADIUS {
+            crater_events.send(TerrainCraterEvent::Add(Crater {
+                center: project_to_surface(&bullet_tr.translation),
+                radius: CRATER_RADIUS,
+                depth: CRATER_DEPTH,
+            }));
         }
-        // create explosion effect
-        commands
-            .spawn((
-                BulletExplodingEffectMarker,
-                ParticleEffectBundle {
-                    effect: ParticleEffect::new(bullet_assets.hit_effect.clone()),
-                    ..Default::default()
-                },
-            ))
-            .set_parent(tombstone_ent);
+        let tombstone_ent = bury_bullet(
+            &mut commands,
+            bullet_ent,
+            bullet_tr.translation,
+            bullet_children,
+            &mut flying_effects,
+            bullet_assets.hit_effect.clone(),
+        );
+        // keeps the crater detailed while the smoke clears
+        commands.entity(tombstone_ent).insert(TerrainSplitProbe {
+            radius: 100.0,
+            priority: 0.5,
+            ..default()
+        });
         // create audio effects: far boom + closer explosion
         audio_events.send(PlaySpatialAudioEvent::distant_boom(tombstone_ent));
         audio_events.send(PlaySpatialAudioEvent::close_explosion(tombstone_ent));
-        // finally, delete the bullet
-        commands.entity(bullet_ent).despawn_recursive();
     }
 }
 
+fn on_bullet_splash(
+    mut commands: Commands,
+    splashes: Query<(Entity, &Transform, &Children), (With<Bullet>, With<BulletSplash>)>,
+    mut flying_effects: Query<(Entity, &mut EffectSpawner), With<BulletFlyingEffectMarker>>,
+    bullet_assets: Res<BulletAssets>,
+    mut audio_events: EventWriter<PlaySpatialAudioEvent>,
+) {
+    for (bullet_ent, bullet_tr, bullet_children) in splashes.iter() {
+        let tombstone_ent = bury_bullet(
+            &mut commands,
+            bullet_ent,
+            bullet_tr.translation,
+            bullet_children,
+            &mut flying_effects,
+            bullet_assets.splash_effect.clone(),
+        );
+        audio_events.send(PlaySpatialAudioEvent::water_splash(tombstone_ent));
+    }
+}
+
+/// Swaps the bullet for a tombstone at `pos` that plays `effect`, and takes over
+/// the fading flying effect. Returns the tombstone, for the sounds.
+fn bury_bullet(
+    commands: &mut Commands,
+    bullet_ent: Entity,
+    pos: Vec3,
+    bullet_children: &Children,
+    flying_effects: &mut Query<(Entity, &mut EffectSpawner), With<BulletFlyingEffectMarker>>,
+    effect: Handle<EffectAsset>,
+) -> Entity {
+    // put the tombstone on the thing
+    let tombstone_ent = commands
+        .spawn((
+            BulletTombstone(Timer::new(Duration::from_secs(6), TimerMode::Once)),
+            SpatialBundle::from_transform(Transform::from_translation(pos)),
+        ))
+        .insert(Name::new("Bullet TOMBSTONE"))
+        .id();
+    // move the flying effect to tombstone and stop it from emitting
+    for child in bullet_children.iter() {
+        if let Ok((effect_ent, mut effect)) = flying_effects.get_mut(*child) {
+            commands.entity(effect_ent).set_parent(tombstone_ent);
+            effect.set_active(false);
+        }
+    }
+    // create explosion effect
+    commands
+        .spawn((
+            BulletExplodingEffectMarker,
+            ParticleEffectBundle {
+                effect: ParticleEffect::new(effect),
+                ..Default::default()
+            },
+        ))
+        .set_parent(tombstone_ent);
+    // finally, delete the bullet
+    commands.entity(bullet_ent).despawn_recursive();
+    tombstone_ent
+}
+
 fn shoot_bullet(
     mut commands: Commands,
     tanks: Query<(Entity, &Tank)>,
@@ -126,8 +217,6 @@ fn shoot_bullet(
             if event.event_type != TankCommandEventType::Fire {
                 continue;
             }
-            const SHOOT_ROTATION: f32 = 5.0;
-
             let fwd = tank.fire_direction.normalize();
             let quat = Quat::from_rotation_arc(Vec3::Z, fwd);
             let spawn_pos = tank.fire_origin;
@@ -149,35 +238,25 @@ fn shoot_bullet(
             //     ..Default::default()
             // };
 
-            const SHOOT_VEL_RELATIVE_ERR: f32 = 7.0 / 3000.0;
-            let (err_x, err_y, err_z): (f32, f32, f32) = random();
-            let linear_relative_err = Vec3::new(err_x, err_y, err_z) * 2.0 - Vec3::ONE;
-            let linear_relative_err = linear_relative_err.normalize() * SHOOT_VEL_RELATIVE_ERR;
-            let linear_vel = (fwd + linear_relative_err) * tank.power * TANK_BULLET_SPEED_PER_POWER;
+            // no muzzle error: the shell flies the preview, bit for bit
+            let linear_vel = fwd * tank.power * TANK_BULLET_SPEED_PER_POWER;
 
+            // no rigid body: `fly_bullets` moves it, rapier only tells what it hits
             let bullet_id = commands
                 .spawn((
                     Bullet {
                         shooter: tank_entity,
+                        flight: ProjectileState::launch(spawn_pos, linear_vel),
+                        fired_at: tank.aimed_at,
                     },
                     bullet_pbr_bundle,
                 ))
-                .insert(RigidBody::Dynamic)
-                .insert(GravityScale(GRAVITY_SCALE))
-                .insert(ColliderMassProperties::Density(BULLET_DENSITY))
-                .insert(bullet_assets.collider.clone())
-                .insert(Ccd::enabled())
-                .insert(Damping {
-                    linear_damping: BULLET_LINEAR_DAMPING,
-                    angular_damping: BULLET_LINEAR_DAMPING,
-                })
-                .insert(Velocity {
-                    linvel: linear_vel,
-                    angvel: quat * Vec3::new(0.0, 0.0, SHOOT_ROTATION),
-                })
-                .insert(ActiveEvents::COLLISION_EVENTS)
+                .insert(Velocity::linear(linear_vel))
                 .insert(Name::new("BULLET"))
-                .insert(TerrainSplitProbe)
+                .insert(TerrainSplitProbe {
+                    radius: 300.0,
+                    ..default()
+                })
                 .id();
 
             // bullet particle effect
@@ -197,36 +276,81 @@ fn shoot_bullet(
     }
 }
 
-fn capture_bullet_impact(
+/// m/s² on a shell at `state`, gravity towards the planet center and the wind.
+/// the aim preview flies with it too, so it lands where the shells do.
+pub fn shell_accel(wind: &Wind, state: &ProjectileState, fired_at: f32) -> Vec3 {
+    let gravity = -up(&state.pos) * GRAVITY_MAGNITUDE * GRAVITY_SCALE;
+    let wind = wind.velocity_at_time(&state.pos, fired_at + state.flight_time());
+    gravity + wind * WIND_ACCEL_PER_SPEED
+}
+
+/// Moves the bullets one `PROJECTILE_TIMESTEP` along their flight and casts the
+/// bullet along the step to find what it ran into.
+#[allow(clippy::type_complexity)]
+fn fly_bullets(
     mut commands: Commands,
-    mut collision_events: EventReader<CollisionEvent>,
-    bullet_query: Query<Entity, With<Bullet>>,
-    mut bullet_transform: Query<(Entity, &mut Transform), With<Bullet>>,
+    mut bullets: Query<
+        (Entity, &mut Bullet, &mut Transform, &mut Velocity),
+        (Without<BulletHit>, Without<BulletSplash>),
+    >,
+    bullet_assets: Res<BulletAssets>,
+    rapier: Res<RapierContext>,
+    wind: Res<Wind>,
 ) {
-    for collision_event in collision_events.iter() {
-        if let CollisionEvent::Started(col1, col2, _flags) = collision_event {
-            if bullet_query.contains(*col1) {
-                commands
-                    .entity(*col1)
-                    .insert(BulletHit {})
-                    .insert(Velocity::default());
-            }
-            if bullet_query.contains(*col2) {
-                commands
-                    .entity(*col2)
-                    .insert(BulletHit {})
-                    .insert(Velocity::default());
-            }
+    for (bullet_ent, mut bullet, mut bullet_tr, mut velocity) in bullets.iter_mut() {
+        let from = bullet.flight;
+        let to = from.step(
+            shell_accel(&wind, &from, bullet.fired_at),
+            BULLET_LINEAR_DAMPING,
+        );
+        bullet.flight = to;
+        velocity.linvel = to.vel;
+        bullet_tr.rotation = Quat::from_rotation_arc(Vec3::Z, to.vel.normalize());
+        let hit = rapier.cast_shape(
+            from.pos,
+            bullet_tr.rotation,
+            to.pos - from.pos,
+            &bullet_assets.collider,
+            1.0,
+            QueryFilter::default(),
+        );
+        bullet_tr.translation = match hit {
+            Some((_, toi)) => from.pos.lerp(to.pos, toi.toi),
+            None => to.pos,
+        };
+
+        if splash_if_in_water(&mut commands, bullet_ent, &mut bullet_tr) {
+            continue;
         }
-    }
-    for (bullet_ent, mut bullet_tr) in bullet_transform.iter_mut() {
-        let terrain_pos = apply_height(&bullet_tr.translation);
-        if terrain_pos.y > bullet_tr.translation.y {
-            bullet_tr.translation.y = terrain_pos.y + BULLET_SIZE;
-            commands
-                .entity(bullet_ent)
-                .insert(BulletHit {})
-                .insert(Velocity::default());
+        // under the terrain where its collider isn't built yet
+        if hit.is_none() && altitude(&bullet_tr.translation) < 0.0 {
+            bullet_tr.translation =
+                apply_height(&bullet_tr.translation) + up(&bullet_tr.translation) * BULLET_SIZE;
+        } else if hit.is_none() {
+            continue;
         }
+        commands
+            .entity(bullet_ent)
+            .insert(BulletHit {})
+            .insert(Velocity::default());
+    }
+}
+
+/// under the sea surface, over sea floor: moves the bullet up to the surface to splash there
+fn splash_if_in_water(
+    commands: &mut Commands,
+    bullet_ent: Entity,
+    bullet_tr: &mut Transform,
+) -> bool {
+    let pos = bullet_tr.translation;
+    let below_surface = -water_altitude(&pos);
+    if below_surface <= 0.0 || water_depth(&pos) <= 0.0 {
+        return false;
     }
+    bullet_tr.translation = pos + up(&pos) * below_surface;
+    commands
+        .entity(bullet_ent)
+        .insert(BulletSplash)
+        .insert(Velocity::default());
+    true
 }
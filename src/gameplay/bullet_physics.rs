@@ -1,20 +1,28 @@
-use bevy::{
-    log::info,
-    prelude::{Reflect, Vec2},
-};
-use std::{
-    cmp::Ordering,
-    f32::{consts::PI, INFINITY},
-};
+use bevy::prelude::{Quat, Reflect, Vec2, Vec3};
+use std::f32::consts::PI;
 
 pub const TANK_BULLET_SPEED_PER_POWER: f32 = 0.28;
 pub const GRAVITY_SCALE: f32 = 1.0;
 pub const GRAVITY_MAGNITUDE: f32 = 9.81;
-pub const BULLET_DENSITY: f32 = 100.0;
 pub const TANK_DENSITY: f32 = 1000.0;
 pub const BULLET_LINEAR_DAMPING: f32 = 0.0;
+/// m/s² pushing the shells along the wind, per m/s of wind speed
+pub const WIND_ACCEL_PER_SPEED: f32 = 0.1;
+
+/// seconds per step of the shells in flight, the aim preview takes the same steps
+pub const PROJECTILE_TIMESTEP: f32 = 1.0 / 60.0;
+/// steps after which a flight is given up on, two minutes
+pub const PROJECTILE_MAX_STEPS: u32 = 7200;
+/// seconds the intercept flight time may be off by, one step of the shell
+pub const INTERCEPT_TOLERANCE: f32 = PROJECTILE_TIMESTEP;
+/// guesses of the flight time before the intercept is given up on
+pub const INTERCEPT_MAX_ITERATIONS: usize = 12;
 
 pub const TRAJECTORY_POINTS: usize = 20;
+/// meters between the points of a trajectory checked against the ground
+pub const TRAJECTORY_CHECK_STEP: f32 = 4.0;
+/// meters around the muzzle and the target left unchecked, the arcs start and end at the ground
+pub const TRAJECTORY_CHECK_CLEARANCE: f32 = 3.0;
 
 #[derive(Clone, Debug, Reflect)]
 pub struct BulletSolution {
@@ -23,6 +31,8 @@ pub struct BulletSolution {
     pub speed: f32,
     pub power: f32,
     pub trajectory: Vec<Vec2>,
+    /// runs into the ground or a prop before the target
+    pub obstructed: bool,
     _next_iter_point: Vec2,
     _absolute_error: Vec2,
 }
@@ -33,19 +43,353 @@ pub struct BulletSolutions {
     pub err_sol: Option<BulletSolution>,
 }
 
-pub fn compute_ballistic_solution(range: f32, _y_diff: f32, max_speed: f32) -> BulletSolutions {
-    let alpha = BULLET_LINEAR_DAMPING;
+/// A `BulletSolution` in the tank's local frame: y up, the bearing turns from +Z
+/// towards +X, the muzzle at the origin.
+#[derive(Clone, Debug, Reflect)]
+pub struct BulletSolution3d {
+    pub bearing: f32,
+    pub elevation: f32,
+    pub power: f32,
+    pub speed: f32,
+    pub flight_time: f32,
+    pub trajectory: Vec<Vec3>,
+    /// runs into the ground or a prop before the target
+    pub obstructed: bool,
+}
+impl BulletSolution3d {
+    /// m/s the shell leaves the muzzle with, in the tank's local frame
+    pub fn launch_velocity(&self) -> Vec3 {
+        Vec3::new(
+            self.bearing.sin() * self.elevation.cos(),
+            self.elevation.sin(),
+            self.bearing.cos() * self.elevation.cos(),
+        ) * self.speed
+    }
+}
+
+#[derive(Clone, Debug, Reflect)]
+pub struct BulletSolutions3d {
+    pub chosen_sol: Option<BulletSolution3d>,
+    pub all_sol: Vec<BulletSolution3d>,
+    pub err_sol: Option<BulletSolution3d>,
+}
+
+/// The steady forces on the shell in flight, in the tank's local frame
+#[derive(Clone, Copy, Debug, PartialEq, Reflect)]
+pub struct FlightConditions {
+    /// m/s², gravity plus the wind and anything else pushing steadily
+    pub accel: Vec3,
+    /// rapier `linear_damping` of the shells
+    pub linear_damping: f32,
+}
+
+impl Default for FlightConditions {
+    fn default() -> Self {
+        Self {
+            accel: Vec3::NEG_Y * GRAVITY_MAGNITUDE * GRAVITY_SCALE,
+            linear_damping: BULLET_LINEAR_DAMPING,
+        }
+    }
+}
+
+impl FlightConditions {
+    /// the shells, with the wind pushing them by `wind_accel` on top of gravity
+    pub fn with_wind(wind_accel: Vec3) -> Self {
+        let calm = Self::default();
+        Self {
+            accel: calm.accel + wind_accel,
+            ..calm
+        }
+    }
+
+    /// plain gravity straight down and the usual damping, what the 2D solver assumes
+    pub fn is_calm(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// The 2D solver in the vertical plane of the target, under plain gravity: what
+/// `compute_ballistic_solution_3d()` comes down to without wind.
+pub fn compute_ballistic_solution(range: f32, y_diff: f32, max_speed: f32) -> BulletSolutions {
+    compute_damped_ballistic_solution(range, y_diff, max_speed, BULLET_LINEAR_DAMPING)
+}
+
+/// `compute_ballistic_solution()` for shells with the given rapier `linear_damping`
+pub fn compute_damped_ballistic_solution(
+    range: f32,
+    y_diff: f32,
+    max_speed: f32,
+    linear_damping: f32,
+) -> BulletSolutions {
     let gravity = GRAVITY_MAGNITUDE * GRAVITY_SCALE;
+    _compute_ballistic_solution(Vec2::new(range, y_diff), max_speed, gravity, linear_damping)
+}
+
+fn _compute_ballistic_solution(
+    pos: Vec2,
+    max_speed: f32,
+    gravity: f32,
+    linear_damping: f32,
+) -> BulletSolutions {
+    let alpha = linear_damping;
     let points: usize = TRAJECTORY_POINTS;
-    let pos = Vec2::new(range, _y_diff);
     if alpha > 0.0 {
-        // _compute_with_damping_many_iter(pos, speed, gravity, points, alpha)
-        panic!("todo!");
+        _compute_ballistic_solution_with_damping(pos, max_speed, gravity, points, alpha)
     } else {
         _compute_ballistic_solution_no_damping(pos, max_speed, gravity, points)
     }
 }
 
+/// Same as `compute_ballistic_solution()`, then the arcs are checked in flight time
+/// order with `obstructed`, which gets the `trajectory_check_points()` of an arc and
+/// tells if they run into something. The fastest clear arc gets chosen, the slower
+/// ones are not checked. With no clear arc, the fastest one is the error solution.
+pub fn compute_clear_ballistic_solution(
+    range: f32,
+    y_diff: f32,
+    max_speed: f32,
+    obstructed: impl FnMut(&[Vec2]) -> bool,
+) -> BulletSolutions {
+    choose_clear_solution(
+        compute_ballistic_solution(range, y_diff, max_speed),
+        obstructed,
+    )
+}
+
+fn choose_clear_solution(
+    mut solutions: BulletSolutions,
+    mut obstructed: impl FnMut(&[Vec2]) -> bool,
+) -> BulletSolutions {
+    if solutions.chosen_sol.is_none() {
+        return solutions;
+    }
+    solutions.chosen_sol = None;
+    for solution in solutions.all_sol.iter_mut() {
+        solution.obstructed = obstructed(&trajectory_check_points(&solution.trajectory));
+        if !solution.obstructed {
+            solutions.chosen_sol = Some(solution.clone());
+            break;
+        }
+    }
+    if solutions.chosen_sol.is_none() {
+        solutions.err_sol = solutions.all_sol.first().cloned();
+    }
+    solutions
+}
+
+/// Aims at `target` from the origin, both in the tank's local frame.
+/// With only steady forces the shell flies in a plane, like under plain gravity,
+/// just tilted: the frame gets turned until `conditions.accel` points straight
+/// down, the 2D solver does the work, and the arcs are turned back.
+pub fn compute_ballistic_solution_3d(
+    target: Vec3,
+    max_speed: f32,
+    conditions: &FlightConditions,
+) -> BulletSolutions3d {
+    let frame = TurnedFrame::new(target, conditions);
+    let solutions = if conditions.is_calm() {
+        // nothing to turn
+        compute_ballistic_solution(frame.target.x, frame.target.y, max_speed)
+    } else {
+        frame.solve(max_speed, conditions)
+    };
+    frame.lift_solutions(solutions)
+}
+
+/// `compute_ballistic_solution_3d()` checked like `compute_clear_ballistic_solution()`,
+/// `obstructed` gets the check points in the tank's local frame.
+pub fn compute_clear_ballistic_solution_3d(
+    target: Vec3,
+    max_speed: f32,
+    conditions: &FlightConditions,
+    mut obstructed: impl FnMut(&[Vec3]) -> bool,
+) -> BulletSolutions3d {
+    let frame = TurnedFrame::new(target, conditions);
+    let lifted_obstructed =
+        |points: &[Vec2]| obstructed(&points.iter().map(|p| frame.lift(*p)).collect::<Vec<_>>());
+    let solutions = if conditions.is_calm() {
+        compute_clear_ballistic_solution(
+            frame.target.x,
+            frame.target.y,
+            max_speed,
+            lifted_obstructed,
+        )
+    } else {
+        choose_clear_solution(frame.solve(max_speed, conditions), lifted_obstructed)
+    };
+    frame.lift_solutions(solutions)
+}
+
+/// the tank's local frame, turned so the steady forces point down, and the
+/// vertical plane of the target in it: the frame of the 2D solver
+struct TurnedFrame {
+    rotation: Quat,
+    bearing: f32,
+    target: Vec2,
+    gravity: f32,
+}
+
+impl TurnedFrame {
+    fn new(target: Vec3, conditions: &FlightConditions) -> Self {
+        let rotation = Quat::from_rotation_arc(conditions.accel.normalize(), Vec3::NEG_Y);
+        let turned = rotation * target;
+        Self {
+            rotation,
+            bearing: turned.x.atan2(turned.z),
+            target: Vec2::new(Vec2::new(turned.x, turned.z).length(), turned.y),
+            gravity: conditions.accel.length(),
+        }
+    }
+
+    fn solve(&self, max_speed: f32, conditions: &FlightConditions) -> BulletSolutions {
+        _compute_ballistic_solution(
+            self.target,
+            max_speed,
+            self.gravity,
+            conditions.linear_damping,
+        )
+    }
+
+    /// a point of the 2D plane back in the tank's local frame
+    fn lift(&self, point: Vec2) -> Vec3 {
+        self.rotation.inverse()
+            * Vec3::new(
+                point.x * self.bearing.sin(),
+                point.y,
+                point.x * self.bearing.cos(),
+            )
+    }
+
+    fn lift_solution(&self, solution: &BulletSolution) -> BulletSolution3d {
+        let direction = self.lift(Vec2::from_angle(solution.elevation));
+        BulletSolution3d {
+            bearing: direction.x.atan2(direction.z),
+            elevation: direction.y.clamp(-1.0, 1.0).asin(),
+            power: solution.power,
+            speed: solution.speed,
+            flight_time: solution.flight_time,
+            trajectory: solution.trajectory.iter().map(|p| self.lift(*p)).collect(),
+            obstructed: solution.obstructed,
+        }
+    }
+
+    fn lift_solutions(&self, solutions: BulletSolutions) -> BulletSolutions3d {
+        BulletSolutions3d {
+            chosen_sol: solutions.chosen_sol.map(|s| self.lift_solution(&s)),
+            all_sol: solutions
+                .all_sol
+                .iter()
+                .map(|s| self.lift_solution(s))
+                .collect(),
+            err_sol: solutions.err_sol.map(|s| self.lift_solution(&s)),
+        }
+    }
+}
+
+/// A shell in flight, moved by `step()` only: no frame time and no rapier, so
+/// the same launch and the same pushes give the same flight, bit for bit.
+#[derive(Clone, Copy, Debug, PartialEq, Reflect)]
+pub struct ProjectileState {
+    pub pos: Vec3,
+    pub vel: Vec3,
+    /// `PROJECTILE_TIMESTEP` steps since the launch
+    pub steps: u32,
+}
+
+impl ProjectileState {
+    pub fn launch(pos: Vec3, vel: Vec3) -> Self {
+        Self { pos, vel, steps: 0 }
+    }
+
+    /// seconds since the launch
+    pub fn flight_time(&self) -> f32 {
+        self.steps as f32 * PROJECTILE_TIMESTEP
+    }
+
+    /// one step pushed by `accel`, integrated like rapier does: the velocity
+    /// first, then the damping, then the position with the new velocity
+    pub fn step(&self, accel: Vec3, linear_damping: f32) -> Self {
+        let dt = PROJECTILE_TIMESTEP;
+        let vel = (self.vel + accel * dt) / (1.0 + dt * linear_damping);
+        Self {
+            pos: self.pos + vel * dt,
+            vel,
+            steps: self.steps + 1,
+        }
+    }
+}
+
+/// The flight from `state` on, with `accel` asked for the push at each step,
+/// until `landed` or `PROJECTILE_MAX_STEPS`. The first and the landing states are in.
+pub fn simulate_projectile(
+    state: ProjectileState,
+    linear_damping: f32,
+    mut accel: impl FnMut(&ProjectileState) -> Vec3,
+    mut landed: impl FnMut(&ProjectileState) -> bool,
+) -> Vec<ProjectileState> {
+    let mut flight = vec![state];
+    let mut state = state;
+    while !landed(&state) && state.steps < PROJECTILE_MAX_STEPS {
+        state = state.step(accel(&state), linear_damping);
+        flight.push(state);
+    }
+    flight
+}
+
+/// Where to shoot to meet a moving target: the point `position_at(t)` the target
+/// is at when the shell flying `flight_time_to(point)` seconds gets there.
+/// Guesses the flight time by the secant method, starting from a still target.
+/// Returns the point and the flight time, the last `flight_time_to` call was for
+/// it; `None` when some point can't be reached or the guesses don't settle.
+pub fn compute_intercept(
+    mut position_at: impl FnMut(f32) -> Vec3,
+    mut flight_time_to: impl FnMut(Vec3) -> Option<f32>,
+) -> Option<(Vec3, f32)> {
+    // the flight time is a fixed point of `guess`: zero error at the intercept
+    let mut guess = |time: f32| {
+        let point = position_at(time);
+        flight_time_to(point).map(|flight_time| (point, flight_time, flight_time - time))
+    };
+    let (mut time, mut error) = (0.0, guess(0.0)?.2);
+    let mut next_time = error;
+    for _ in 0..INTERCEPT_MAX_ITERATIONS {
+        let (point, flight_time, next_error) = guess(next_time)?;
+        if next_error.abs() < INTERCEPT_TOLERANCE {
+            return Some((point, flight_time));
+        }
+        let slope = (next_error - error) / (next_time - time);
+        (time, error) = (next_time, next_error);
+        next_time = if slope.abs() > f32::EPSILON {
+            (time - error / slope).max(0.0)
+        } else {
+            flight_time
+        };
+    }
+    None
+}
+
+/// The trajectory, with points at most `TRAJECTORY_CHECK_STEP` apart, without the
+/// ones closer than `TRAJECTORY_CHECK_CLEARANCE` to the muzzle or the target.
+pub fn trajectory_check_points(trajectory: &[Vec2]) -> Vec<Vec2> {
+    let (Some(start), Some(end)) = (trajectory.first(), trajectory.last()) else {
+        return vec![];
+    };
+    let away_from_ends = |point: &Vec2| {
+        point.distance(*start) > TRAJECTORY_CHECK_CLEARANCE
+            && point.distance(*end) > TRAJECTORY_CHECK_CLEARANCE
+    };
+    trajectory
+        .windows(2)
+        .flat_map(|pair| {
+            let steps = (pair[0].distance(pair[1]) / TRAJECTORY_CHECK_STEP)
+                .ceil()
+                .max(1.0) as usize;
+            (0..steps).map(move |i| pair[0].lerp(pair[1], i as f32 / steps as f32))
+        })
+        .filter(away_from_ends)
+        .collect()
+}
+
 fn _compute_ballistic_solution_no_damping(
     pos: Vec2,
     max_speed: f32,
@@ -80,6 +424,7 @@ fn _compute_ballistic_solution_no_damping(
             trajectory,
             speed,
             power: speed / TANK_BULLET_SPEED_PER_POWER,
+            obstructed: false,
             _absolute_error: abs_err,
             _next_iter_point: Vec2::new(range, y_diff) + abs_err,
         }
@@ -129,7 +474,120 @@ fn _compute_ballistic_solution_no_damping(
     //         }
     //     }
     // };
-    let (ok1, _, _) = base_angles(range, max_speed, y_diff);
+    solve_over_speeds(
+        max_speed,
+        points,
+        |speed| base_angles(range, speed, y_diff),
+        make_solution,
+    )
+}
+
+/// With linear damping `alpha`, rapier integrates dv/dt = g - alpha * v, so
+/// x(t) = vx / alpha * (1 - e^(-alpha t))
+/// y(t) = (vy + g / alpha) / alpha * (1 - e^(-alpha t)) - g t / alpha
+/// No closed form for the elevation, so it's shot for: the height at `range` has a
+/// single peak over the elevations, the low and high solutions are on either side.
+fn _compute_ballistic_solution_with_damping(
+    pos: Vec2,
+    max_speed: f32,
+    gravity: f32,
+    points: usize,
+    alpha: f32,
+) -> BulletSolutions {
+    let (range, y_diff) = (pos.x, pos.y);
+
+    let compute_point = |elevation: f32, speed: f32, time: f32| {
+        let t_exp = (1.0 - (-alpha * time).exp()) / alpha;
+        Vec2::new(
+            elevation.cos() * speed * t_exp,
+            (elevation.sin() * speed + gravity / alpha) * t_exp - gravity * time / alpha,
+        )
+    };
+    // time to fly `range`, None if the drag stops the shell before
+    let time_to_range = |elevation: f32, speed: f32| {
+        let reach = 1.0 - alpha * range / (elevation.cos() * speed);
+        (reach > 0.0).then(|| -reach.ln() / alpha)
+    };
+    let make_solution = |elevation: f32, speed: f32, points: usize| {
+        // short of the range, draw it until it's back down, about
+        let flight_time = time_to_range(elevation, speed)
+            .unwrap_or_else(|| (2.0 * elevation.sin() * speed / gravity).max(0.0));
+        let trajectory: Vec<_> = (0..points)
+            .map(|i| {
+                let time = flight_time * (i as f32) / (points as f32 - 1.0);
+                compute_point(elevation, speed, time)
+            })
+            .collect();
+        let abs_err = Vec2::new(range, y_diff) - trajectory[trajectory.len() - 1];
+
+        BulletSolution {
+            elevation,
+            flight_time,
+            trajectory,
+            speed,
+            power: speed / TANK_BULLET_SPEED_PER_POWER,
+            obstructed: false,
+            _absolute_error: abs_err,
+            _next_iter_point: Vec2::new(range, y_diff) + abs_err,
+        }
+    };
+    // above (positive) or under the target when passing its range
+    let miss = |elevation: f32, speed: f32| match time_to_range(elevation, speed) {
+        Some(time) => compute_point(elevation, speed, time).y - y_diff,
+        None => f32::NEG_INFINITY,
+    };
+    const ITERATIONS: usize = 60;
+    let base_angles = |speed: f32| {
+        // the drag stops it before the range past this elevation, either way
+        let max_elevation = (alpha * range / speed).min(1.0).acos();
+        // golden section for the highest pass
+        let (mut lo, mut hi) = (-max_elevation, max_elevation);
+        let ratio = (5.0_f32.sqrt() - 1.0) / 2.0;
+        for _ in 0..ITERATIONS {
+            let a = hi - (hi - lo) * ratio;
+            let b = lo + (hi - lo) * ratio;
+            if miss(a, speed) < miss(b, speed) {
+                lo = a;
+            } else {
+                hi = b;
+            }
+        }
+        let top = (lo + hi) / 2.0;
+        if miss(top, speed) < 0.0 {
+            return (false, 0.0, 0.0);
+        }
+        // bisect from the peak towards each end
+        let bisect = |mut under: f32, mut over: f32| {
+            for _ in 0..ITERATIONS {
+                let mid = (under + over) / 2.0;
+                if miss(mid, speed) < 0.0 {
+                    under = mid;
+                } else {
+                    over = mid;
+                }
+            }
+            (under + over) / 2.0
+        };
+        (
+            true,
+            bisect(-max_elevation, top),
+            bisect(max_elevation, top),
+        )
+    };
+
+    solve_over_speeds(max_speed, points, base_angles, make_solution)
+}
+
+/// Both solutions at `N_SPEEDS` speeds up to `max_speed`, shortest flight first.
+/// `base_angles(speed)` gives the low and high elevations that reach the target,
+/// if any, and `make_solution(elevation, speed, points)` draws the arc.
+fn solve_over_speeds(
+    max_speed: f32,
+    points: usize,
+    base_angles: impl Fn(f32) -> (bool, f32, f32),
+    make_solution: impl Fn(f32, f32, usize) -> BulletSolution,
+) -> BulletSolutions {
+    let (ok1, _, _) = base_angles(max_speed);
     if !ok1 {
         return BulletSolutions {
             chosen_sol: None,
@@ -141,7 +599,7 @@ fn _compute_ballistic_solution_no_damping(
 
     let mut trajectories: Vec<_> = (1..=N_SPEEDS)
         .map(|i| (max_speed) * i as f32 / N_SPEEDS as f32)
-        .map(|speed| (speed, base_angles(range, speed, y_diff)))
+        .map(|speed| (speed, base_angles(speed)))
         .filter(|s| s.1 .0)
         .flat_map(|(speed, (_, _ang1, _ang2))| {
             let t = (
@@ -159,155 +617,347 @@ fn _compute_ballistic_solution_no_damping(
     }
 }
 
-// fn _compute_with_damping_many_iter(
-//     pos: Vec2,
-//     speed: f32,
-//     gravity: f32,
-//     points: usize,
-//     alpha: f32,
-// ) -> BulletSolutions {
-//     let mut sol0 = _compute_ballistic_solution_with_damping(pos, speed, gravity, points, alpha);
-//     let mut lows: Vec<Option<BulletSolution>> = vec![sol0.low_sol.clone()];
-//     let mut highs: Vec<Option<BulletSolution>> = vec![sol0.high_sol.clone()];
-//     for _ in 0..5 {
-//         if let Some(low) = sol0.low_sol {
-//             let next = _compute_ballistic_solution_with_damping(
-//                 low._next_iter_point,
-//                 speed,
-//                 gravity,
-//                 points,
-//                 alpha,
-//             );
-//             lows.push(next.low_sol.clone());
-//             sol0.low_sol = next.low_sol;
-//             sol0.err_sol = sol0.err_sol.or(next.err_sol);
-//         }
-//         if let Some(high) = sol0.high_sol {
-//             let next = _compute_ballistic_solution_with_damping(
-//                 high._next_iter_point,
-//                 speed,
-//                 gravity,
-//                 points,
-//                 alpha,
-//             );
-//             highs.push(next.high_sol.clone());
-//             sol0.high_sol = next.high_sol;
-//             sol0.err_sol = sol0.err_sol.or(next.err_sol);
-//         }
-//     }
-//     let cmp = |a: &Option<BulletSolution>, b: &Option<BulletSolution>| {
-//         let va = if let Some(x) = a {
-//             x.trajectory.last().unwrap().distance(pos)
-//         } else {
-//             9999999.9_f32
-//         };
-//         let vb = if let Some(x) = b {
-//             x.trajectory.last().unwrap().distance(pos)
-//         } else {
-//             9999999.9_f32
-//         };
-//         if va < vb {
-//             Ordering::Less
-//         } else if va > vb {
-//             Ordering::Greater
-//         } else {
-//             Ordering::Equal
-//         }
-//     };
-//     highs.sort_by(cmp);
-//     lows.sort_by(cmp);
-//     sol0.low_sol = lows[0].clone();
-//     sol0.high_sol = highs[0].clone();
-//     sol0
-// }
-
-// fn _compute_ballistic_solution_with_damping(
-//     pos: Vec2,
-//     speed: f32,
-//     gravity: f32,
-//     points: usize,
-//     alpha: f32,
-// ) -> BulletSolutions {
-//     let (range, y_diff) = (pos.x, pos.y);
-//     let make_solution = |elevation: f32, points: usize| {
-//         let speed_x = elevation.cos() * speed;
-//         let speed_y = elevation.sin() * speed;
-//         let mut flight_time = range / speed_x;
-//         flight_time = flight_time / ((1.0 - (-alpha * flight_time).exp()) / alpha / flight_time);
-//         let compute_point = |time: f32| {
-//             // https://www.lehman.edu/faculty/dgaranin/Mathematical_Physics/Mathematical_physics-10-Differential_equations.pdf
-//             // page 15
-//             let t_exp = (1.0 - (-alpha * time).exp()) / alpha;
-//             Vec2::new(
-//                 speed_x * t_exp,
-//                 (speed_y + gravity / alpha) * t_exp - gravity * time / alpha,
-//             )
-//         };
-//         flight_time *= range / compute_point(flight_time).x;
-//         flight_time *= range / compute_point(flight_time).x;
-//         flight_time *= range / compute_point(flight_time).x;
-//         let trajectory: Vec<_> = (0..points)
-//             .map(|i| {
-//                 let time = flight_time * (i as f32) / (points as f32 - 1.0);
-//                 compute_point(time)
-//             })
-//             .collect();
-//         let abs_err = Vec2::new(range, y_diff) - trajectory[trajectory.len() - 1];
-
-//         BulletSolution {
-//             elevation,
-//             flight_time,
-//             trajectory,
-//             _absolute_error: abs_err,
-//             _next_iter_point: Vec2::new(range, y_diff) + abs_err * 0.7,
-//         }
-//     };
-//     // https://math.stackexchange.com/questions/3019313/finding-projectile-angle-with-different-elevation-when-velocity-and-range-are-kn
-//     // \theta = \arctan \left( \frac{v_0^2 \pm \sqrt{v_0^4 - g(gx_f^2+2y_fv_0^2)}}{gx_f} \right)
-//     let base_angles = |range: f32, y_diff: f32| {
-//         let v0_sq = speed * speed;
-//         let par = gravity * range.powi(2) + 2.0 * y_diff * v0_sq;
-//         let sub_radical = v0_sq * v0_sq - gravity * par;
-//         if sub_radical < 0.0 {
-//             (false, 0.0, 0.0)
-//         } else {
-//             let radical = (sub_radical).sqrt();
-//             let _ang2 = ((v0_sq + radical) / (gravity * range)).atan();
-//             let _ang1 = ((v0_sq - radical) / (gravity * range)).atan();
-//             (true, _ang1, _ang2)
-//         }
-//     };
-//     let (ok, mut _ang1, mut _ang2) = base_angles(range, y_diff);
-//     if !ok {
-//         return BulletSolutions {
-//             low_sol: None,
-//             high_sol: None,
-//             err_sol: Some(make_solution(PI / 4.0, points)),
-//         };
-//     }
-
-//     // https://www.lehman.edu/faculty/dgaranin/Mathematical_Physics/Mathematical_physics-10-Differential_equations.pdf
-//     // δθi = αL / (v0 * Cos[2*ϑi] * Cos[ϑi])  * (gL / 3 v0^2)
-//     let correct_angle = |ang: f32, range: f32| {
-//         let t1 = (alpha * range) / (speed * ang.cos() * (2.0 * ang).cos());
-//         let t2 = (gravity * range) / (3.0 * speed * speed);
-//         t1 * t2
-//     };
-//     _ang1 += correct_angle(_ang1, range);
-//     _ang2 += correct_angle(_ang2, range);
-//     if _ang1 > _ang2 {
-//         return BulletSolutions {
-//             low_sol: None,
-//             high_sol: None,
-//             err_sol: Some(make_solution(PI / 4.0, points)),
-//         };
-//     }
-//     BulletSolutions {
-//         low_sol: Some(make_solution(_ang1, points)),
-//         high_sol: Some(make_solution(_ang2, points)),
-//         err_sol: None,
-//     }
-// }
-
-// // TODO compute with lienar damping
-// // https://www.lehman.edu/faculty/dgaranin/Mathematical_Physics/Mathematical_physics-10-Differential_equations.pdf
+#[cfg(test)]
+fn hill(height: f32, center: f32, width: f32) -> impl Fn(f32) -> f32 {
+    move |x: f32| height * (-((x - center) / width).powi(2)).exp()
+}
+
+#[test]
+fn test_clear_solution_flies_over_the_hill() {
+    let max_speed = TANK_BULLET_SPEED_PER_POWER * 1000.0;
+    let ground = hill(60.0, 250.0, 40.0);
+    let over_ground = |points: &[Vec2]| points.iter().any(|p| p.y < ground(p.x));
+
+    // the fastest arc skims the ground, right into the hill
+    let unchecked = compute_ballistic_solution(500.0, 0.0, max_speed);
+    let fastest = unchecked.chosen_sol.unwrap();
+    assert!(over_ground(&trajectory_check_points(&fastest.trajectory)));
+
+    let checked = compute_clear_ballistic_solution(500.0, 0.0, max_speed, over_ground);
+    let chosen = checked.chosen_sol.expect("some arc goes over the hill");
+    assert!(checked.err_sol.is_none());
+    assert!(!chosen.obstructed);
+    assert!(chosen.flight_time > fastest.flight_time);
+    assert!(chosen.trajectory.iter().all(|p| p.y >= ground(p.x) - 1e-3));
+    // the faster ones are flagged
+    let flagged: Vec<_> = checked
+        .all_sol
+        .iter()
+        .take_while(|s| s.flight_time < chosen.flight_time)
+        .collect();
+    assert!(!flagged.is_empty() && flagged.iter().all(|s| s.obstructed));
+}
+
+#[test]
+fn test_clear_solution_on_flat_ground_and_behind_a_wall() {
+    let max_speed = TANK_BULLET_SPEED_PER_POWER * 1000.0;
+    // flat ground: the fastest arc, same as without checks
+    let flat = compute_clear_ballistic_solution(500.0, 0.0, max_speed, |points| {
+        points.iter().any(|p| p.y < 0.0)
+    });
+    let unchecked = compute_ballistic_solution(500.0, 0.0, max_speed);
+    assert_eq!(
+        flat.chosen_sol.unwrap().elevation,
+        unchecked.chosen_sol.unwrap().elevation
+    );
+
+    // a cliff higher than any arc: no solution, the fastest arc is the error
+    let wall = hill(10_000.0, 250.0, 10.0);
+    let blocked = compute_clear_ballistic_solution(500.0, 0.0, max_speed, |points| {
+        points.iter().any(|p| p.y < wall(p.x))
+    });
+    assert!(blocked.chosen_sol.is_none());
+    assert!(blocked.all_sol.iter().all(|s| s.obstructed));
+    let err = blocked.err_sol.unwrap();
+    assert_eq!(err.flight_time, blocked.all_sol[0].flight_time);
+
+    // the muzzle and the target are left out, the points are close together
+    let points = trajectory_check_points(&blocked.all_sol[0].trajectory);
+    assert!(points
+        .windows(2)
+        .all(|p| p[0].distance(p[1]) <= TRAJECTORY_CHECK_STEP + 1e-3));
+    assert!(points
+        .iter()
+        .all(|p| p.length() > TRAJECTORY_CHECK_CLEARANCE
+            && p.distance(Vec2::new(500.0, 0.0)) > TRAJECTORY_CHECK_CLEARANCE));
+}
+
+/// Steps the shell like rapier does, gravity then damping then the move, and
+/// returns how close it passes to `target`.
+#[cfg(test)]
+fn simulate_miss(solution: &BulletSolution, target: Vec2, linear_damping: f32) -> f32 {
+    let dt = 1.0 / 60.0;
+    let gravity = Vec2::new(0.0, -GRAVITY_MAGNITUDE * GRAVITY_SCALE);
+    let mut vel = Vec2::new(solution.elevation.cos(), solution.elevation.sin()) * solution.speed;
+    let mut pos = Vec2::ZERO;
+    let mut miss = f32::INFINITY;
+    while pos.x < target.x * 2.0 && pos.y > target.y - 1000.0 {
+        vel = (vel + gravity * dt) / (1.0 + dt * linear_damping);
+        let next = pos + vel * dt;
+        // closest point of the step to the target
+        let along =
+            ((target - pos).dot(next - pos) / (next - pos).length_squared()).clamp(0.0, 1.0);
+        miss = miss.min(pos.lerp(next, along).distance(target));
+        pos = next;
+    }
+    miss
+}
+
+#[test]
+fn test_damped_solutions_land_on_the_target() {
+    let max_speed = TANK_BULLET_SPEED_PER_POWER * 1000.0;
+    for linear_damping in [0.01, 0.05, 0.2] {
+        for target in [
+            Vec2::new(500.0, 0.0),
+            Vec2::new(800.0, 60.0),
+            Vec2::new(300.0, -40.0),
+        ] {
+            let solutions =
+                compute_damped_ballistic_solution(target.x, target.y, max_speed, linear_damping);
+            assert!(solutions.err_sol.is_none());
+            assert!(!solutions.all_sol.is_empty());
+            for solution in solutions.all_sol.iter() {
+                let miss = simulate_miss(solution, target, linear_damping);
+                assert!(
+                    miss < 1.0,
+                    "damping {} target {:?} speed {} elevation {} misses by {}",
+                    linear_damping,
+                    target,
+                    solution.speed,
+                    solution.elevation,
+                    miss
+                );
+                let end = *solution.trajectory.last().unwrap();
+                assert!(end.distance(target) < 0.1);
+            }
+        }
+    }
+
+    // the drag keeps it short of max_speed / damping
+    let short = compute_damped_ballistic_solution(3000.0, 0.0, max_speed, 0.2);
+    assert!(short.chosen_sol.is_none());
+    let err = short.err_sol.unwrap();
+    assert!(err.flight_time.is_finite() && err.trajectory.iter().all(|p| p.is_finite()));
+
+    // next to no drag, next to the same aim
+    let damped = compute_damped_ballistic_solution(500.0, 0.0, max_speed, 1e-4);
+    let undamped = compute_damped_ballistic_solution(500.0, 0.0, max_speed, 0.0);
+    let (damped, undamped) = (damped.chosen_sol.unwrap(), undamped.chosen_sol.unwrap());
+    assert!((damped.elevation - undamped.elevation).abs() < 1e-3);
+    assert!((damped.flight_time - undamped.flight_time).abs() < 1e-2);
+}
+
+#[test]
+fn test_3d_solution_without_wind_is_the_2d_one() {
+    let max_speed = TANK_BULLET_SPEED_PER_POWER * 1000.0;
+    let target = Vec3::new(300.0, 20.0, 400.0);
+    let flat = compute_ballistic_solution(500.0, 20.0, max_speed);
+    let solutions = compute_ballistic_solution_3d(target, max_speed, &FlightConditions::default());
+    assert_eq!(flat.all_sol.len(), solutions.all_sol.len());
+    for (flat, solution) in flat.all_sol.iter().zip(solutions.all_sol.iter()) {
+        assert!((solution.bearing - 300.0_f32.atan2(400.0)).abs() < 1e-5);
+        assert!((solution.elevation - flat.elevation).abs() < 1e-5);
+        assert_eq!(solution.flight_time, flat.flight_time);
+        assert_eq!(solution.power, flat.power);
+        assert!(solution.trajectory[0].length() < 1e-3);
+        assert!(solution.trajectory.last().unwrap().distance(target) < 0.1);
+    }
+}
+
+/// Steps the shell like rapier does in the tank's local frame, and returns how
+/// close it passes to `target`.
+#[cfg(test)]
+fn simulate_miss_3d(
+    solution: &BulletSolution3d,
+    target: Vec3,
+    conditions: &FlightConditions,
+) -> f32 {
+    let dt = 1.0 / 60.0;
+    let (bearing, elevation) = (solution.bearing, solution.elevation);
+    let mut vel = Vec3::new(
+        bearing.sin() * elevation.cos(),
+        elevation.sin(),
+        bearing.cos() * elevation.cos(),
+    ) * solution.speed;
+    let mut pos = Vec3::ZERO;
+    let mut miss = f32::INFINITY;
+    for _ in 0..(solution.flight_time * 2.0 / dt) as usize {
+        vel = (vel + conditions.accel * dt) / (1.0 + dt * conditions.linear_damping);
+        let next = pos + vel * dt;
+        let along =
+            ((target - pos).dot(next - pos) / (next - pos).length_squared()).clamp(0.0, 1.0);
+        miss = miss.min(pos.lerp(next, along).distance(target));
+        pos = next;
+    }
+    miss
+}
+
+#[test]
+fn test_3d_solutions_land_on_the_target_in_the_wind() {
+    let max_speed = TANK_BULLET_SPEED_PER_POWER * 1000.0;
+    let target = Vec3::new(-200.0, 30.0, 550.0);
+    for linear_damping in [0.0, 0.05] {
+        for wind_accel in [
+            Vec3::new(0.0, 0.0, 1.5),
+            Vec3::new(0.0, 0.0, -1.5),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(-0.7, 0.2, 0.9),
+        ] {
+            let conditions = FlightConditions {
+                linear_damping,
+                ..FlightConditions::with_wind(wind_accel)
+            };
+            let solutions = compute_ballistic_solution_3d(target, max_speed, &conditions);
+            assert!(solutions.err_sol.is_none());
+            assert!(!solutions.all_sol.is_empty());
+            for solution in solutions.all_sol.iter() {
+                let miss = simulate_miss_3d(solution, target, &conditions);
+                assert!(
+                    miss < 1.0,
+                    "damping {} wind {:?} speed {} misses by {}",
+                    linear_damping,
+                    wind_accel,
+                    solution.speed,
+                    miss
+                );
+                assert!(solution.speed <= max_speed);
+                assert!(solution.trajectory.last().unwrap().distance(target) < 0.1);
+            }
+        }
+    }
+
+    // a crosswind from the left: the gun turns left into it, the arc bends back
+    let calm = compute_ballistic_solution_3d(target, max_speed, &FlightConditions::default());
+    let crosswind = compute_ballistic_solution_3d(
+        target,
+        max_speed,
+        &FlightConditions::with_wind(Vec3::X * -1.0),
+    );
+    let (calm, crosswind) = (calm.chosen_sol.unwrap(), crosswind.chosen_sol.unwrap());
+    assert!(crosswind.bearing > calm.bearing);
+
+    // the obstruction check gets the 3D points, past the muzzle and short of the target
+    let ridge = |p: &Vec3| p.z > 200.0 && p.z < 400.0 && p.y < 60.0;
+    let clear = compute_clear_ballistic_solution_3d(
+        target,
+        max_speed,
+        &FlightConditions::with_wind(Vec3::X * -1.0),
+        |points| {
+            assert!(points
+                .iter()
+                .all(|p| p.length() > TRAJECTORY_CHECK_CLEARANCE
+                    && p.distance(target) > TRAJECTORY_CHECK_CLEARANCE));
+            points.iter().any(ridge)
+        },
+    );
+    let chosen = clear.chosen_sol.expect("some arc goes over the ridge");
+    assert!(clear.all_sol[0].obstructed);
+    assert!(chosen.flight_time > clear.all_sol[0].flight_time);
+    assert!(!chosen.trajectory.iter().any(ridge));
+}
+
+#[test]
+fn test_projectile_flight_is_deterministic_and_lands_on_the_target() {
+    let max_speed = TANK_BULLET_SPEED_PER_POWER * 1000.0;
+    let target = Vec3::new(-200.0, 30.0, 550.0);
+    for linear_damping in [0.0, 0.05] {
+        let conditions = FlightConditions {
+            linear_damping,
+            ..FlightConditions::with_wind(Vec3::new(-0.7, 0.2, 0.9))
+        };
+        let solutions = compute_ballistic_solution_3d(target, max_speed, &conditions);
+        for solution in solutions.all_sol.iter() {
+            let launch = ProjectileState::launch(Vec3::ZERO, solution.launch_velocity());
+            let flight = simulate_projectile(
+                launch,
+                linear_damping,
+                |_| conditions.accel,
+                |state| state.flight_time() > solution.flight_time,
+            );
+            let miss = flight
+                .windows(2)
+                .map(|pair| {
+                    let (from, to) = (pair[0].pos, pair[1].pos);
+                    let along = ((target - from).dot(to - from) / (to - from).length_squared())
+                        .clamp(0.0, 1.0);
+                    from.lerp(to, along).distance(target)
+                })
+                .fold(f32::INFINITY, f32::min);
+            assert!(miss < 1.0, "speed {} misses by {}", solution.speed, miss);
+
+            // stepped one at a time like the bullets, same states to the last bit
+            let mut state = launch;
+            for expected in flight.iter().skip(1) {
+                state = state.step(conditions.accel, linear_damping);
+                assert_eq!(state, *expected);
+            }
+        }
+    }
+
+    // it gives up on the shells that never land
+    let lost = simulate_projectile(
+        ProjectileState::launch(Vec3::ZERO, Vec3::Y),
+        0.0,
+        |_| Vec3::ZERO,
+        |_| false,
+    );
+    assert_eq!(lost.len() as u32, PROJECTILE_MAX_STEPS + 1);
+}
+
+#[test]
+fn test_intercept_meets_the_moving_target() {
+    let max_speed = TANK_BULLET_SPEED_PER_POWER * 1000.0;
+    let conditions = FlightConditions::with_wind(Vec3::new(-0.7, 0.2, 0.9));
+    let start = Vec3::new(-200.0, 30.0, 550.0);
+    // driving away to the side and speeding up, then climbing a hill
+    let position_at = |time: f32| {
+        let pos = start + Vec3::new(6.0, 0.0, 4.0) * time + Vec3::new(0.5, 0.0, 0.3) * time * time;
+        pos + Vec3::Y * (pos.x + 200.0).max(0.0) * 0.4
+    };
+    // the fast arc and the high one: the high one flies long and drifts with the target
+    for arc in [0, 1] {
+        let solve = |point: Vec3| {
+            let solutions = compute_ballistic_solution_3d(point, max_speed, &conditions);
+            solutions.all_sol.get(arc).cloned()
+        };
+        let (point, flight_time) =
+            compute_intercept(position_at, |point| solve(point).map(|s| s.flight_time))
+                .expect("the target is in range");
+        assert!((flight_time - solve(point).unwrap().flight_time).abs() < 1e-6);
+        let there = position_at(flight_time);
+        assert!(
+            there.distance(point) < 1.0,
+            "arc {} misses by {}",
+            arc,
+            there.distance(point)
+        );
+        assert!(point.distance(start) > 10.0);
+
+        // the shell goes through there, within a step of the target getting there
+        let flight = simulate_projectile(
+            ProjectileState::launch(Vec3::ZERO, solve(point).unwrap().launch_velocity()),
+            conditions.linear_damping,
+            |_| conditions.accel,
+            |state| state.flight_time() > flight_time + PROJECTILE_TIMESTEP,
+        );
+        let miss = flight
+            .windows(2)
+            .map(|pair| {
+                let (from, to) = (pair[0].pos, pair[1].pos);
+                let along =
+                    ((there - from).dot(to - from) / (to - from).length_squared()).clamp(0.0, 1.0);
+                from.lerp(to, along).distance(there)
+            })
+            .fold(f32::INFINITY, f32::min);
+        assert!(miss < 1.0, "arc {} shell misses by {}", arc, miss);
+    }
+
+    // out of range, there's nothing to aim at
+    let fleeing = |time: f32| start + Vec3::Z * 5000.0 * (1.0 + time);
+    let solve = |point: Vec3| {
+        compute_ballistic_solution_3d(point, max_speed, &conditions)
+            .chosen_sol
+            .map(|s| s.flight_time)
+    };
+    assert!(compute_intercept(fleeing, solve).is_none());
+}
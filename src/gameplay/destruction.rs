@@ -0,0 +1,148 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use rand::random;
+
+use crate::assets::GameSceneAssets;
+use crate::audio::PlaySpatialAudioEvent;
+use crate::props::{damage_prop, wreck_prop, Prop, PropCells, Wreck, PROP_LIBRARY};
+use crate::terrain::{planet_shape, up};
+
+use super::bullet_physics::GRAVITY_MAGNITUDE;
+use super::events::BulletHitEvent;
+
+pub struct DestructionPlugin;
+impl Plugin for DestructionPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Debris>()
+            .add_systems(PreUpdate, on_prop_hit)
+            .add_systems(Update, (arm_debris, settle_debris, debris_radial_gravity));
+    }
+}
+
+/// damage of a shell going off right on a prop
+const BULLET_DAMAGE: f32 = 100.0;
+/// meters from the prop the damage fades out at
+const PROP_DAMAGE_DISTANCE: f32 = 20.0;
+/// m/s of the debris thrown by the blast that brings a prop down
+const DEBRIS_SPEED: f32 = 12.0;
+/// seconds the debris flies and tumbles before it freezes into rubble
+const DEBRIS_SETTLE_TIME: f32 = 8.0;
+
+/// A piece of a wreck, flying until the timer runs out.
+#[derive(Reflect, Component, Debug)]
+pub struct Debris(Timer);
+
+fn on_prop_hit(
+    mut commands: Commands,
+    mut props_query: Query<(Entity, &GlobalTransform, &mut Prop)>,
+    mut events: EventReader<BulletHitEvent>,
+    scene_assets: Res<GameSceneAssets>,
+    mut props: ResMut<PropCells>,
+    mut audio_events: EventWriter<PlaySpatialAudioEvent>,
+) {
+    for event in events.iter() {
+        for (prop_ent, prop_tr, mut prop) in props_query.iter_mut() {
+            let def = &PROP_LIBRARY[prop.def];
+            if prop.hit_points <= 0.0 || def.fracture_bounds.is_none() {
+                continue;
+            }
+            let reach = PROP_DAMAGE_DISTANCE + def.half_extents().length() * 2.0;
+            if prop_tr.translation().distance(event.bullet_pos) > reach {
+                continue;
+            }
+            let dist = def.distance_to(&prop_tr.compute_transform(), event.bullet_pos);
+            if dist >= PROP_DAMAGE_DISTANCE {
+                continue;
+            }
+            let was_damaged = prop.is_damaged();
+            prop.hit_points -= BULLET_DAMAGE * (1.0 - dist / PROP_DAMAGE_DISTANCE);
+            if prop.hit_points > 0.0 {
+                if !was_damaged && prop.is_damaged() {
+                    damage_prop(&mut commands, &scene_assets, prop_ent, &prop);
+                }
+            } else {
+                info!("destroyed {} at {:?}", def.file, prop_tr.translation());
+                wreck_prop(
+                    &mut commands,
+                    &scene_assets,
+                    &mut props,
+                    prop_ent,
+                    &prop,
+                    event.bullet_pos,
+                );
+                audio_events.send(PlaySpatialAudioEvent::explode_building(prop_ent));
+            }
+        }
+    }
+}
+
+/// The pieces of a wreck showed up: gives each a body and a collider of its
+/// own, together so rapier doesn't hang the collider on the prop, and lets them fly.
+#[allow(clippy::type_complexity)]
+fn arm_debris(
+    mut commands: Commands,
+    pieces: Query<(Entity, &Handle<Mesh>, &GlobalTransform), Added<Handle<Mesh>>>,
+    parents: Query<&Parent>,
+    wrecks: Query<(&Wreck, &GlobalTransform)>,
+    meshes: Res<Assets<Mesh>>,
+) {
+    for (piece_ent, mesh, piece_tr) in pieces.iter() {
+        let Some((wreck, wreck_tr)) = parents
+            .iter_ancestors(piece_ent)
+            .find_map(|ent| wrecks.get(ent).ok())
+        else {
+            continue;
+        };
+        let Some(collider) = meshes
+            .get(mesh)
+            .and_then(|mesh| Collider::from_bevy_mesh(mesh, &ComputedColliderShape::ConvexHull))
+        else {
+            warn!("no collider for wreck piece {:?}", piece_ent);
+            continue;
+        };
+        let linvel = match wreck.blast {
+            Some(blast) => {
+                let away = (piece_tr.translation() - blast).normalize_or_zero()
+                    + up(&wreck_tr.translation());
+                away.normalize_or_zero() * DEBRIS_SPEED * (0.5 + 0.5 * random::<f32>())
+            }
+            None => Vec3::ZERO,
+        };
+        commands.entity(piece_ent).insert((
+            RigidBody::Dynamic,
+            collider,
+            Velocity::linear(linvel),
+            Debris(Timer::from_seconds(DEBRIS_SETTLE_TIME, TimerMode::Once)),
+        ));
+    }
+}
+
+/// debris that flew long enough stays where it lies, as rubble
+fn settle_debris(
+    mut commands: Commands,
+    mut debris: Query<(Entity, &mut Debris)>,
+    time: Res<Time>,
+) {
+    for (piece_ent, mut timer) in debris.iter_mut() {
+        if timer.0.tick(time.delta()).finished() {
+            commands
+                .entity(piece_ent)
+                .remove::<Debris>()
+                .insert(RigidBody::Fixed);
+        }
+    }
+}
+
+/// rapier pulls the debris down on -Y, on the sphere swap that for a pull to the planet center
+fn debris_radial_gravity(
+    mut debris: Query<(&GlobalTransform, &mut Velocity), With<Debris>>,
+    time: Res<Time>,
+) {
+    if !planet_shape().is_spherical() {
+        return;
+    }
+    let gravity_dv = GRAVITY_MAGNITUDE * time.delta_seconds();
+    for (piece_tr, mut velocity) in debris.iter_mut() {
+        velocity.linvel += (Vec3::Y - up(&piece_tr.translation())) * gravity_dv;
+    }
+}
@@ -15,6 +15,8 @@ pub enum TankCommandEventType {
     MoveRight,
 
     AimAtPoint(Vec3),
+    /// aim where the tank will be when the shell gets there
+    AimAtTank(Entity),
     Fire,
 }
 
@@ -1,21 +1,28 @@
 mod bullet;
 mod bullet_physics;
+mod destruction;
 mod events;
 mod minimap;
 mod tank;
 mod tank_ai;
 mod tank_kbd_shortcuts;
 mod tank_ui;
+mod wind;
 
 use self::bullet::BulletPlugin;
+use self::destruction::DestructionPlugin;
 use self::events::*;
 use self::minimap::MinimapPlugin;
 use self::tank::TankPlugin;
 use self::tank_ai::TankAiPlugin;
 use self::tank_kbd_shortcuts::KeyboardShortcutsPlugin;
 use self::tank_ui::TankUiPlugin;
+use self::wind::WindPlugin;
 use bevy::prelude::*;
 
+pub use self::bullet_physics::PROJECTILE_TIMESTEP;
+pub use self::wind::WIND_EFFECT_PROPERTY;
+
 pub struct GameplayPlugin;
 impl Plugin for GameplayPlugin {
     fn build(&self, app: &mut App) {
@@ -23,9 +30,11 @@ impl Plugin for GameplayPlugin {
             .add_event::<BulletHitEvent>()
             .add_plugins(TankPlugin)
             .add_plugins(BulletPlugin)
+            .add_plugins(DestructionPlugin)
             .add_plugins(KeyboardShortcutsPlugin)
             .add_plugins(TankUiPlugin)
             .add_plugins(TankAiPlugin)
+            .add_plugins(WindPlugin)
             .add_plugins(MinimapPlugin);
     }
 }
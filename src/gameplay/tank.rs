@@ -6,7 +6,11 @@ use crate::{
     gameplay::bullet_physics::{GRAVITY_MAGNITUDE, TANK_DENSITY},
     menu::mouse_not_over_menu,
     planet::TerrainSplitProbe,
-    terrain::{apply_height, height},
+    props::PropCollider,
+    terrain::{
+        altitude, apply_height, heights, normal, project_to_surface, up, water_altitude,
+        water_depth,
+    },
     utils::cap_2pi,
 };
 use core::f32::consts::PI;
@@ -15,10 +19,15 @@ use std::{collections::VecDeque, time::Duration};
 use smart_default::SmartDefault;
 
 use super::{
+    bullet::{shell_accel, ShellClock},
     bullet_physics::{
-        compute_ballistic_solution, BulletSolutions, GRAVITY_SCALE, TANK_BULLET_SPEED_PER_POWER,
+        compute_ballistic_solution_3d, compute_clear_ballistic_solution_3d, compute_intercept,
+        simulate_projectile, BulletSolutions3d, FlightConditions, ProjectileState,
+        BULLET_LINEAR_DAMPING, GRAVITY_SCALE, TANK_BULLET_SPEED_PER_POWER,
+        TRAJECTORY_CHECK_CLEARANCE, WIND_ACCEL_PER_SPEED,
     },
     events::{BulletHitEvent, TankCommandEvent, TankCommandEventType},
+    wind::Wind,
 };
 
 use bevy_spatial::{kdtree::KDTree3, AutomaticUpdate, SpatialAccess, TransformMode};
@@ -83,21 +92,63 @@ pub struct Tank {
     pub fire_direction: Vec3,
     pub fire_origin: Vec3,
 
-    pub fire_solutions: Option<BulletSolutions>,
+    pub fire_solutions: Option<BulletSolutions3d>,
+    /// the aimed shot flown by the same integrator as the shells, world space
+    pub fire_preview: Vec<Vec3>,
+    /// `ShellClock::seconds()` at the last aim: the shells fired on it get the
+    /// gusts from then, the same as the preview
+    pub aimed_at: f32,
     pub has_sol: bool,
 }
 
+/// m/s² of acceleration the lead believes at most, past that it's the history jittering
+const TANK_MAX_LEAD_ACCEL: f32 = 4.0;
+
 impl Tank {
-    pub fn estimate_future_position(&self, seconds_future: f32) -> Vec3 {
-        if self.last_positions.len() <= 2 {
-            return Vec3::ZERO;
+    /// where it is and where it's going, from the `last_positions`: standing still
+    /// until there are a few of them
+    pub fn estimate_motion(&self) -> Option<TankMotion> {
+        let positions = &self.last_positions;
+        let (pos, _) = *positions.back()?;
+        if positions.len() <= 2 {
+            return Some(TankMotion {
+                pos,
+                vel: Vec3::ZERO,
+                accel: Vec3::ZERO,
+            });
         }
-        let total_time: f32 = self.last_positions.iter().map(|x| x.1).sum();
-        let p1 = self.last_positions.front().expect("wtf?").0;
-        let p2 = self.last_positions.back().expect("wtf?").0;
+        // the average velocity over each half of the history, and the change between them
+        let velocity_between = |first: usize, last: usize| {
+            let time: f32 = positions.range(first + 1..=last).map(|x| x.1).sum();
+            let time = time.max(f32::EPSILON);
+            ((positions[last].0 - positions[first].0) / time, time)
+        };
+        let half = positions.len() / 2;
+        let (early_vel, early_time) = velocity_between(0, half);
+        let (late_vel, late_time) = velocity_between(half, positions.len() - 1);
+        let accel = ((late_vel - early_vel) / ((early_time + late_time) * 0.5))
+            .clamp_length_max(TANK_MAX_LEAD_ACCEL);
+        Some(TankMotion {
+            pos,
+            vel: late_vel + accel * late_time * 0.5,
+            accel,
+        })
+    }
+}
 
-        let speed = (p2 - p1) / total_time;
-        p2 + speed * seconds_future
+/// a tank driving on, as far as the others can tell
+#[derive(Clone, Copy, Debug)]
+pub struct TankMotion {
+    pub pos: Vec3,
+    pub vel: Vec3,
+    pub accel: Vec3,
+}
+
+impl TankMotion {
+    /// where it gets to in `seconds`, keeping to the ground up and down the hills
+    pub fn position_at(&self, seconds: f32) -> Vec3 {
+        let ahead = self.pos + self.vel * seconds + self.accel * seconds * seconds * 0.5;
+        apply_height(&ahead) + up(&ahead) * altitude(&self.pos)
     }
 }
 
@@ -134,36 +185,152 @@ fn on_tank_hit(
     }
 }
 
+/// some of the points are under the terrain
+fn arc_hits_terrain(points: &[Vec3]) -> bool {
+    points
+        .iter()
+        .zip(heights(points))
+        .any(|(pos, height)| (*pos - project_to_surface(pos)).dot(up(pos)) < height)
+}
+
+/// a prop collider stands between two consecutive points, other than the `targets`
+fn arc_hits_props(
+    rapier: &RapierContext,
+    prop_colliders: &Query<(), With<PropCollider>>,
+    targets: &[Entity],
+    points: &[Vec3],
+) -> bool {
+    let is_prop = |ent| prop_colliders.contains(ent) && !targets.contains(&ent);
+    let filter = QueryFilter::only_fixed().predicate(&is_prop);
+    points.windows(2).any(|pair| {
+        rapier
+            .cast_ray(pair[0], pair[1] - pair[0], 1.0, true, filter)
+            .is_some()
+    })
+}
+
+/// the preview gives up on shots flying this many times longer than the solver said
+const FIRE_PREVIEW_MAX_FLIGHT_FACTOR: f32 = 1.5;
+
+/// Flies a shell fired now from `origin` at `vel` like `fly_bullets` would, minus
+/// the props, until it goes under the ground or the sea.
+fn preview_shot(wind: &Wind, origin: Vec3, vel: Vec3, fired_at: f32, max_time: f32) -> Vec<Vec3> {
+    let flight = simulate_projectile(
+        ProjectileState::launch(origin, vel),
+        BULLET_LINEAR_DAMPING,
+        |state| shell_accel(wind, state, fired_at),
+        |state| {
+            altitude(&state.pos) < 0.0
+                || (water_depth(&state.pos) > 0.0 && water_altitude(&state.pos) < 0.0)
+                || state.flight_time() > max_time
+        },
+    );
+    flight.iter().map(|state| state.pos).collect()
+}
+
 fn control_tank_aim(
-    mut tank_q: Query<(&mut Tank, &Transform), With<Tank>>,
+    mut tank_q: Query<(&mut Tank, &Transform, Option<&PlayerControlledTank>), With<Tank>>,
     mut tank_command_events: EventReader<TankCommandEvent>,
+    rapier: Res<RapierContext>,
+    prop_colliders: Query<(), With<PropCollider>>,
+    wind: Res<Wind>,
+    clock: Res<ShellClock>,
 ) {
     for event in tank_command_events.iter() {
-        if let TankCommandEventType::AimAtPoint(aim_pos) = event.event_type {
-            if let Ok((mut tank, _tank_tr)) = tank_q.get_mut(event.tank_entity) {
-                let _tank_pos = tank.fire_origin; //  &tank_tr.translation;
-                                                  // let _tank_pos = apply_height(&_tank_pos);
-                let diff = aim_pos - _tank_pos;
-                let bearing = diff.x.atan2(diff.z);
-                // compute elevation ignoring Y diff
-                // https://qph.cf2.quoracdn.net/main-qimg-9aa63a48016d31489787c9c36f138c79
-                let range = Vec2::new(diff.x, diff.z).length();
-                tank.bearing = bearing;
-
-                let solutions =
-                    compute_ballistic_solution(range, diff.y, TANK_BULLET_SPEED_PER_POWER * 1000.0);
-                tank.fire_solutions = Some(solutions.clone());
-                if let Some(s) = solutions.chosen_sol {
-                    tank.elevation = s.elevation;
-                    tank.power = s.power;
-                    tank.has_sol = true;
-                } else if let Some(s) = solutions.err_sol {
-                    tank.elevation = s.elevation;
-                    tank.power = s.power;
-                    tank.has_sol = false;
-                } else {
-                    panic!("solution generator did not return err_sol");
-                }
+        let (aim_pos, target_motion) = match event.event_type {
+            TankCommandEventType::AimAtPoint(aim_pos) => (aim_pos, None),
+            TankCommandEventType::AimAtTank(target_ent) => {
+                let Some(motion) = tank_q
+                    .get(target_ent)
+                    .ok()
+                    .and_then(|(target, ..)| target.estimate_motion())
+                else {
+                    continue;
+                };
+                (motion.pos, Some(motion))
+            }
+            _ => continue,
+        };
+        if let Ok((mut tank, _tank_tr, player)) = tank_q.get_mut(event.tank_entity) {
+            let _tank_pos = tank.fire_origin; //  &tank_tr.translation;
+                                              // let _tank_pos = apply_height(&_tank_pos);
+
+            // work in the tank's local frame, where the ground under it is flat and Y is up
+            let rotation = surface_rotation(&_tank_pos);
+            let wind_accel = rotation.inverse()
+                * wind.aim_velocity_at(&_tank_pos, player.is_some())
+                * WIND_ACCEL_PER_SPEED;
+
+            let conditions = FlightConditions::with_wind(wind_accel);
+            let max_speed = TANK_BULLET_SPEED_PER_POWER * 1000.0;
+            let to_local = |p: Vec3| rotation.inverse() * (p - _tank_pos);
+
+            // the arcs into hills and buildings are no good
+            let to_world = |p: &Vec3| rotation * *p + _tank_pos;
+            let solve_at = |aim_pos: Vec3| {
+                // the props around the aim point are the target, not in the way
+                let mut targets = vec![];
+                rapier.intersections_with_shape(
+                    aim_pos,
+                    Quat::IDENTITY,
+                    &Collider::ball(TRAJECTORY_CHECK_CLEARANCE),
+                    QueryFilter::only_fixed(),
+                    |ent| {
+                        targets.push(ent);
+                        true
+                    },
+                );
+                compute_clear_ballistic_solution_3d(
+                    to_local(aim_pos),
+                    max_speed,
+                    &conditions,
+                    |points| {
+                        let points: Vec<Vec3> = points.iter().map(to_world).collect();
+                        arc_hits_terrain(&points)
+                            || arc_hits_props(&rapier, &prop_colliders, &targets, &points)
+                    },
+                )
+            };
+            // a moving tank gets led: the aim goes where it is when the shell gets there.
+            // the lead is worked out on the fastest arc, only the arcs to the point it
+            // comes to get checked for hills and buildings. out of reach by then, aim
+            // where it is now
+            let led = target_motion.and_then(|motion| {
+                compute_intercept(
+                    |seconds| motion.position_at(seconds),
+                    |point| {
+                        compute_ballistic_solution_3d(to_local(point), max_speed, &conditions)
+                            .chosen_sol
+                            .map(|s| s.flight_time)
+                    },
+                )
+            });
+            let solutions = solve_at(led.map_or(aim_pos, |(point, _)| point));
+            tank.fire_solutions = Some(solutions.clone());
+            tank.aimed_at = clock.seconds();
+            if let Some(s) = &solutions.chosen_sol {
+                tank.bearing = s.bearing;
+                tank.elevation = s.elevation;
+                tank.power = s.power;
+                tank.has_sol = true;
+            } else if let Some(s) = &solutions.err_sol {
+                tank.bearing = s.bearing;
+                tank.elevation = s.elevation;
+                tank.power = s.power;
+                tank.has_sol = false;
+            } else {
+                panic!("solution generator did not return err_sol");
+            }
+            // only the player looks at it, the AI fires right away
+            tank.fire_preview.clear();
+            if let (Some(s), Some(_)) = (solutions.chosen_sol.as_ref(), player) {
+                tank.fire_preview = preview_shot(
+                    &wind,
+                    _tank_pos,
+                    rotation * s.launch_velocity(),
+                    tank.aimed_at,
+                    s.flight_time * FIRE_PREVIEW_MAX_FLIGHT_FACTOR,
+                );
             }
         }
     }
@@ -176,39 +343,39 @@ fn debug_line_strip(gizmos: &mut Gizmos, trajectory: &[Vec3], color: &Color) {
     }
 }
 fn debug_show_tank_aim(tanks: Query<(&Transform, &Tank)>, mut gizmos: Gizmos) {
-    let mut draw_trajectory = |traj: &Vec<Vec2>, pos, bearing: f32, color| {
-        let traj_3d: Vec<Vec3> = traj
-            .iter()
-            .map(|v2| Vec3::new(v2.x * bearing.sin(), v2.y, v2.x * bearing.cos()) + pos)
-            .collect();
+    let mut draw_trajectory = |traj: &Vec<Vec3>, pos, turn: Quat, color| {
+        let rotation = surface_rotation(&pos) * turn;
+        let traj_3d: Vec<Vec3> = traj.iter().map(|p| rotation * *p + pos).collect();
         // gizmos.linestrip(traj_3d, color);
         debug_line_strip(&mut gizmos, &traj_3d, &color);
     };
     for (_tank_tr, tank) in tanks.iter() {
         let tank_pos = tank.fire_origin; // tank_tr.translation;
         if let Some(solutions) = &tank.fire_solutions {
+            // follow the gun when it gets turned by hand after aiming
+            let aimed = solutions.chosen_sol.as_ref().or(solutions.err_sol.as_ref());
+            let turn =
+                Quat::from_rotation_y(tank.bearing - aimed.map_or(tank.bearing, |s| s.bearing));
             for solution in solutions.all_sol.iter() {
-                draw_trajectory(&solution.trajectory, tank_pos, tank.bearing, Color::GRAY);
+                let color = if solution.obstructed {
+                    Color::MAROON
+                } else {
+                    Color::GRAY
+                };
+                draw_trajectory(&solution.trajectory, tank_pos, turn, color);
             }
 
             if let Some(solution) = &solutions.chosen_sol {
-                draw_trajectory(
-                    &solution.trajectory,
-                    tank_pos,
-                    tank.bearing,
-                    Color::YELLOW_GREEN,
-                );
+                draw_trajectory(&solution.trajectory, tank_pos, turn, Color::YELLOW_GREEN);
             }
 
             if let Some(solution) = &solutions.err_sol {
-                draw_trajectory(
-                    &solution.trajectory,
-                    tank_pos,
-                    tank.bearing,
-                    Color::ORANGE_RED,
-                );
+                draw_trajectory(&solution.trajectory, tank_pos, turn, Color::ORANGE_RED);
             }
         }
+        if tank.fire_preview.len() > 1 {
+            debug_line_strip(&mut gizmos, &tank.fire_preview, &Color::WHITE);
+        }
     }
 }
 fn control_tank_mvmt(
@@ -288,8 +455,16 @@ fn control_tank_mvmt(
         tank_data.body_orientation += -_delta_turn;
         tank_data.body_orientation = cap_2pi(tank_data.body_orientation);
 
-        tank_data.move_direction = Quat::from_rotation_y(tank_data.body_orientation) * -Vec3::Z;
-        tank_transform.rotation = Quat::from_rotation_y(tank_data.body_orientation);
+        let tank_up = up(&tank_transform.translation);
+        let tank_surface_rotation = surface_rotation(&tank_transform.translation);
+        tank_controller.up = tank_up;
+
+        // sit flat on the ground, tilted by the slope
+        let ground_tilt = Quat::from_rotation_arc(tank_up, normal(&tank_transform.translation));
+        let body_rotation =
+            ground_tilt * tank_surface_rotation * Quat::from_rotation_y(tank_data.body_orientation);
+        tank_data.move_direction = body_rotation * -Vec3::Z;
+        tank_transform.rotation = body_rotation;
 
         // elevation
         tank_data.elevation += _delta_elev;
@@ -299,20 +474,27 @@ fn control_tank_mvmt(
         tank_data.power = tank_data.power.clamp(0.0, 1000.0);
         let elevation = tank_data.elevation;
 
-        tank_controller.translation = Some(tank_transform.forward() * _delta_adv);
+        let step = tank_transform.forward() * _delta_adv;
+        let wading = wading_speed(
+            water_depth(&tank_transform.translation),
+            water_depth(&(tank_transform.translation + step)),
+        );
+        tank_controller.translation = Some(step * wading);
 
         const GIZMO_FIRE_LEN: f32 = 10.0;
         const GIZMO_EMPTY_RADIUS: f32 = 2.0;
-        tank_data.fire_direction = Quat::from_rotation_y(tank_data.bearing) * Vec3::Z;
         tank_data.fire_direction =
-            (tank_data.fire_direction * elevation.cos() + Vec3::Y * elevation.sin()).normalize();
+            tank_surface_rotation * Quat::from_rotation_y(tank_data.bearing) * Vec3::Z;
+        tank_data.fire_direction =
+            (tank_data.fire_direction * elevation.cos() + tank_up * elevation.sin()).normalize();
 
-        let gizmo_origin = tank_transform.translation + Vec3::Y * 0.3;
+        let gizmo_origin = tank_transform.translation + tank_up * 0.3;
         let gizmo_fire_src = gizmo_origin + tank_data.fire_direction * GIZMO_EMPTY_RADIUS;
         let gizmo_fire_end =
             gizmo_origin + tank_data.fire_direction * (GIZMO_FIRE_LEN + GIZMO_EMPTY_RADIUS);
-        let gizmo_blue_proj_src = Vec3::new(gizmo_fire_src.x, gizmo_origin.y, gizmo_fire_src.z);
-        let gizmo_blue_proj_end = Vec3::new(gizmo_fire_end.x, gizmo_origin.y, gizmo_fire_end.z);
+        let flatten = |p: Vec3| p - tank_up * (p - gizmo_origin).dot(tank_up);
+        let gizmo_blue_proj_src = flatten(gizmo_fire_src);
+        let gizmo_blue_proj_end = flatten(gizmo_fire_end);
         tank_data.fire_origin = gizmo_fire_src;
 
         gizmos.line(gizmo_fire_src, gizmo_fire_end, Color::RED);
@@ -326,6 +508,29 @@ fn control_tank_mvmt(
     }
 }
 
+/// meters of water the tanks still drive through, slower the deeper it gets
+const TANK_MAX_WADING_DEPTH: f32 = 1.5;
+/// speed left at the deepest water the tanks can drive through
+const TANK_WADING_MIN_SPEED: f32 = 0.3;
+
+/// speed multiplier for driving from `depth_from` meters of water into `depth_to`.
+/// going deeper than the tanks can wade stops them, getting out always works.
+fn wading_speed(depth_from: f32, depth_to: f32) -> f32 {
+    if depth_to <= 0.0 {
+        return 1.0;
+    }
+    if depth_to > TANK_MAX_WADING_DEPTH && depth_to > depth_from {
+        return 0.0;
+    }
+    let t = (depth_to / TANK_MAX_WADING_DEPTH).min(1.0);
+    1.0 + (TANK_WADING_MIN_SPEED - 1.0) * t
+}
+
+/// rotates the flat-world frame (Y up) so that Y follows the local `up()`
+pub fn surface_rotation(pos: &Vec3) -> Quat {
+    Quat::from_rotation_arc(Vec3::Y, up(pos))
+}
+
 fn rand_float(max_abs: f32) -> f32 {
     max_abs * (rand::random::<f32>() * 2.0 - 1.0)
 }
@@ -362,11 +567,12 @@ fn tank_setup(mut commands: Commands, scene_assets: Res<GameSceneAssets>) {
 
     for i in 0..TANK_SPAWN_COUNT {
         let get_pos = || {
-            apply_height(&rand_vec3(TANK_SPAWN_POS_MAX_SPREAD)) + Vec3::Y * (collider_size + 1.0)
+            let ground = apply_height(&rand_vec3(TANK_SPAWN_POS_MAX_SPREAD));
+            ground + up(&ground) * (collider_size + 1.0)
         };
         let mut tank_spawn_pos = get_pos();
-        while !added_positions.is_empty()
-            && added_positions
+        while water_depth(&tank_spawn_pos) > 0.0
+            || added_positions
                 .iter()
                 .any(|other| other.distance(tank_spawn_pos) < TANK_SPAWN_POS_MIN_SPREAD)
         {
@@ -390,7 +596,6 @@ fn tank_setup(mut commands: Commands, scene_assets: Res<GameSceneAssets>) {
                 tank_controller.clone(),
                 tank_collider.clone(), // tank_model,
             ))
-            .insert(TerrainSplitProbe)
             .id();
         commands
             .spawn((tank_model, Name::new("Tank Model")))
@@ -405,27 +610,49 @@ fn tank_setup(mut commands: Commands, scene_assets: Res<GameSceneAssets>) {
             commands
                 .entity(tank_id)
                 .insert(PlayerControlledTank)
+                .insert(TerrainSplitProbe {
+                    radius: 5000.0,
+                    priority: 2.0,
+                    ..default()
+                })
                 .insert(Name::new(format!("Player Tank ({})", i)));
         } else {
+            // only needs solid ground under the tracks
             commands
                 .entity(tank_id)
                 .insert(super::tank_ai::AiControlledTank::new())
+                .insert(TerrainSplitProbe {
+                    radius: 300.0,
+                    priority: 0.5,
+                    ..default()
+                })
                 .insert(Name::new(format!("AI Tank ({})", i)));
         }
     }
 }
 
+#[allow(clippy::type_complexity)]
 fn tank_gravity_update(
-    mut tanks: Query<(&mut KinematicCharacterController, &mut TankGravity), With<TankGravity>>,
+    mut tanks: Query<
+        (
+            &mut KinematicCharacterController,
+            &mut TankGravity,
+            &Transform,
+        ),
+        With<TankGravity>,
+    >,
     time: Res<Time>,
 ) {
     let dt = time.delta_seconds();
-    for (mut tank_transform, mut gravity) in tanks.iter_mut() {
+    for (mut tank_transform, mut gravity, transform) in tanks.iter_mut() {
         if !gravity.is_grounded {
             gravity.fall_time += dt;
             tank_transform.translation = Some(
                 tank_transform.translation.unwrap_or_default()
-                    + -Vec3::Y * gravity.fall_time * GRAVITY_SCALE * GRAVITY_MAGNITUDE,
+                    + -up(&transform.translation)
+                        * gravity.fall_time
+                        * GRAVITY_SCALE
+                        * GRAVITY_MAGNITUDE,
             );
         }
     }
@@ -445,13 +672,14 @@ fn read_tank_gravity_result(
 fn tank_fix_above_terrain(mut transforms: Query<&mut Transform, With<TankGravity>>) {
     for mut transform in transforms.iter_mut() {
         const RESET_BELOW: f32 = 10.0;
-        let terrain_height = height(&transform.translation);
-        if transform.translation.y < terrain_height - RESET_BELOW {
+        let obj_altitude = altitude(&transform.translation);
+        if obj_altitude < -RESET_BELOW {
             warn!(
-                "SHIT FELL UNDER TERRAIN: terrain_height={}  obj_Y={}",
-                terrain_height, transform.translation.y
+                "SHIT FELL UNDER TERRAIN: pos={}  obj_altitude={}",
+                transform.translation, obj_altitude
             );
-            transform.translation.y = terrain_height + RESET_BELOW * 3.;
+            transform.translation = apply_height(&transform.translation)
+                + up(&transform.translation) * RESET_BELOW * 3.;
         }
     }
 }
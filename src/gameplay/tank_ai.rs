@@ -91,7 +91,7 @@ fn tank_auto_fire(
 fn tank_auto_aim(
     mut ai_tanks: Query<(Entity, &mut AiControlledTank, &Tank, &GlobalTransform)>,
     mut events: EventWriter<TankCommandEvent>,
-    potential_targets: Query<(Entity, &Tank), With<Tank>>,
+    potential_targets: Query<Entity, With<Tank>>,
     target_tank_tree: Res<KDTree3<Tank>>,
 ) {
     for (ai_tank_entity, mut ai_tank, ai_tank_common, ai_transform) in ai_tanks.iter_mut() {
@@ -107,19 +107,10 @@ fn tank_auto_aim(
                     < AI_TARGET_SWITCH_INTERVAL + ai_tank.since_target_switch_jitter
             {
                 // if the previous aim event was successful
-                if let Some(solution) = ai_tank_common.fire_solutions.clone() {
+                if let Some(solution) = &ai_tank_common.fire_solutions {
                     if solution.err_sol.is_none() {
-                        // aim at it again
-
-                        let travel_time = solution.chosen_sol.expect("wtf?").flight_time;
-
-                        let target_position = potential_targets
-                            .get(target_ent)
-                            .expect("wtf?")
-                            .1
-                            .estimate_future_position(travel_time);
-                        let event_type =
-                            super::events::TankCommandEventType::AimAtPoint(target_position);
+                        // aim at it again, leading it
+                        let event_type = super::events::TankCommandEventType::AimAtTank(target_ent);
                         events.send(TankCommandEvent {
                             tank_entity: ai_tank_entity,
                             event_type,
@@ -148,10 +139,10 @@ fn tank_auto_aim(
             continue;
         }
         targets.shuffle(&mut rand::thread_rng());
-        let (target_position, target_ent) = targets[0];
+        let (_target_position, target_ent) = targets[0];
         ai_tank.target = Some(target_ent);
 
-        let event_type = super::events::TankCommandEventType::AimAtPoint(target_position);
+        let event_type = super::events::TankCommandEventType::AimAtTank(target_ent);
         events.send(TankCommandEvent {
             tank_entity: ai_tank_entity,
             event_type,
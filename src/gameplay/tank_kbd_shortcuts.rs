@@ -1,9 +1,11 @@
 use bevy::prelude::*;
+use bevy_spatial::{kdtree::KDTree3, SpatialAccess};
 
 use crate::{
     camera_flying::{FlyingCameraInputState, FlyingCameraPivot},
     menu::mouse_not_over_menu,
     raycast::TerrainRaycastResult,
+    terrain::up,
 };
 
 use super::{
@@ -14,7 +16,9 @@ use super::{
 pub struct KeyboardShortcutsPlugin;
 impl Plugin for KeyboardShortcutsPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(PreUpdate, (read_keys_for_player_tank_control,))
+        app.init_resource::<AimAssist>()
+            .register_type::<AimAssist>()
+            .add_systems(PreUpdate, (read_keys_for_player_tank_control,))
             .add_systems(
                 Update,
                 (
@@ -25,6 +29,16 @@ impl Plugin for KeyboardShortcutsPlugin {
     }
 }
 
+/// clicking next to a tank leads it like the AI does, instead of aiming at the ground
+#[derive(Reflect, Resource, Default)]
+#[reflect(Resource)]
+pub struct AimAssist {
+    pub lead_targets: bool,
+}
+
+/// meters from a tank a click leads it from, when `AimAssist` is on
+const AIM_ASSIST_RADIUS: f32 = 12.0;
+
 #[allow(clippy::type_complexity)]
 fn center_camera_on_player_tank(
     mut camera_pivot: Query<(&mut Transform, &mut FlyingCameraPivot), With<FlyingCameraPivot>>,
@@ -40,9 +54,11 @@ fn center_camera_on_player_tank(
     if keys.just_pressed(KeyCode::F1) {
         if let Ok((mut camera_transform, mut camera_pivot)) = camera_pivot.get_single_mut() {
             if let Ok((player_tank, _tank_data)) = player_tank.get_single() {
-                camera_transform.translation =
-                    player_tank.translation + Vec3::new(camera_height, 0.0, camera_height);
-                camera_transform.look_at(player_tank.translation, Vec3::Y);
+                let tank_up = up(&player_tank.translation);
+                camera_transform.translation = player_tank.translation
+                    + Quat::from_rotation_arc(Vec3::Y, tank_up)
+                        * Vec3::new(camera_height, 0.0, camera_height);
+                camera_transform.look_at(player_tank.translation, tank_up);
                 camera_state.pitch = -0.3;
                 camera_pivot.camera_height = camera_height;
             }
@@ -54,7 +70,12 @@ fn read_keys_for_player_tank_control(
     keys: Res<Input<KeyCode>>,
     mut tank_command_events: EventWriter<TankCommandEvent>,
     tank_query: Query<Entity, With<PlayerControlledTank>>,
+    mut aim_assist: ResMut<AimAssist>,
 ) {
+    if keys.just_pressed(KeyCode::L) {
+        aim_assist.lead_targets = !aim_assist.lead_targets;
+        info!("aim assist leads the targets: {}", aim_assist.lead_targets);
+    }
     if let Ok(tank_entity) = tank_query.get_single() {
         if keys.just_pressed(KeyCode::Space) {
             tank_command_events.send(TankCommandEvent {
@@ -91,13 +112,29 @@ fn aim_tank_on_click(
     mut tank_command_events: EventWriter<TankCommandEvent>,
     tank_query: Query<Entity, With<PlayerControlledTank>>,
     terrain_raycast: Res<TerrainRaycastResult>,
+    aim_assist: Res<AimAssist>,
+    tank_tree: Res<KDTree3<Tank>>,
 ) {
     if mouse.pressed(MouseButton::Left) {
         if let Ok(tank_entity) = tank_query.get_single() {
             if let Some(intersection) = &terrain_raycast.intersection {
                 let pos = intersection.position();
+                let target = tank_tree
+                    .nearest_neighbour(pos)
+                    .filter(|(target_pos, _)| target_pos.distance(pos) < AIM_ASSIST_RADIUS)
+                    .and_then(|(_, target)| target)
+                    .filter(|target| aim_assist.lead_targets && *target != tank_entity);
+                // leading a tank is a whole intercept solve, once per click.
+                // held on it after, the lead stays
+                let event_type = match target {
+                    Some(target) if mouse.just_pressed(MouseButton::Left) => {
+                        TankCommandEventType::AimAtTank(target)
+                    }
+                    Some(_) => return,
+                    None => TankCommandEventType::AimAtPoint(pos),
+                };
                 tank_command_events.send(TankCommandEvent {
-                    event_type: TankCommandEventType::AimAtPoint(pos),
+                    event_type,
                     tank_entity,
                 })
             }
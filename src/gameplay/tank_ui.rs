@@ -1,9 +1,13 @@
+use std::f32::consts::PI;
+
 use bevy::prelude::*;
 
 use crate::menu::{egui_ui_system, mouse_is_over_menu, UiMarkHoverBundle, UiMarkMouseOverMenu};
+use crate::utils::cap_2pi;
 
 use super::events::{TankCommandEvent, TankCommandEventType};
 use super::tank::{PlayerControlledTank, Tank};
+use super::wind::Wind;
 
 pub struct TankUiPlugin;
 impl Plugin for TankUiPlugin {
@@ -88,11 +92,22 @@ enum TankUILabel {
     PowerLevel,
     Bearing,
     Elevation,
+    Wind,
+}
+
+/// where the wind blows, seen from behind the gun: up is a tailwind. the bearing
+/// grows to the left, so the arrows go round counter-clockwise.
+const WIND_ARROWS: [&str; 8] = ["↑", "↖", "←", "↙", "↓", "↘", "→", "↗"];
+
+fn wind_arrow(wind_direction: f32, bearing: f32) -> &'static str {
+    let sector = (cap_2pi(wind_direction - bearing) / (PI / 4.0)).round() as i32;
+    WIND_ARROWS[sector.rem_euclid(8) as usize]
 }
 
 fn update_labels(
     mut query: Query<(&mut Text, &TankUILabel), With<TankUILabel>>,
     tank: Query<&Tank, With<PlayerControlledTank>>,
+    wind: Res<Wind>,
 ) {
     if let Ok(tank) = tank.get_single() {
         for (mut text, _type) in &mut query {
@@ -106,6 +121,13 @@ fn update_labels(
                 TankUILabel::PowerLevel => {
                     text.sections[0].value = format!("{}", tank.power.round())
                 }
+                TankUILabel::Wind => {
+                    text.sections[0].value = format!(
+                        "{} {}",
+                        wind_arrow(wind.direction, tank.bearing),
+                        wind.speed().round()
+                    )
+                }
             }
         }
     }
@@ -141,6 +163,7 @@ pub fn build_tank_control_ui(commands: &mut Commands, asset_server: &Res<AssetSe
         "Bearing",
         TankUILabel::Bearing,
     );
+    build_tank_info_row(root, commands, &font, "Wind", TankUILabel::Wind);
 
     // FIRE BUTTON
     let color = Color::rgba(0.9, 0.9, 0.9, 0.9);
@@ -229,6 +252,61 @@ fn build_tank_control_row(
         ..default()
     };
 
+    build_tank_row(
+        parent_id,
+        commands,
+        font,
+        title,
+        comp_value_label,
+        tank_row_cell(33.0, JustifyContent::End),
+        |row, text_style| {
+            // === Minus Button ===
+            row.spawn((button_style.clone(), comp_minus))
+                .insert(UiMarkMouseOverMenu)
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section("-", text_style.clone()));
+                });
+            // === Plus Button ===
+            row.spawn((button_style.clone(), comp_plus))
+                .insert(UiMarkMouseOverMenu)
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section("+", text_style.clone()));
+                });
+        },
+    )
+}
+
+/// a row like `build_tank_control_row()`, only the title and the value, no buttons
+fn build_tank_info_row(
+    parent_id: Entity,
+    commands: &mut Commands,
+    font: &Handle<Font>,
+    title: &str,
+    comp_value_label: impl Component,
+) -> Entity {
+    // the value goes where the buttons would be
+    build_tank_row(
+        parent_id,
+        commands,
+        font,
+        title,
+        comp_value_label,
+        tank_row_cell(66.0, JustifyContent::Center),
+        |_, _| {},
+    )
+}
+
+/// the title, the value in `value_cell`, then whatever `add_rest` puts in the row
+fn build_tank_row(
+    parent_id: Entity,
+    commands: &mut Commands,
+    font: &Handle<Font>,
+    title: &str,
+    comp_value_label: impl Component,
+    value_cell: NodeBundle,
+    add_rest: impl FnOnce(&mut ChildBuilder, &TextStyle),
+) -> Entity {
+    let color = Color::rgba(0.9, 0.9, 0.9, 0.9);
     let text_style = TextStyle {
         font: font.clone(),
         font_size: 27.0,
@@ -266,61 +344,44 @@ fn build_tank_control_row(
                 ))
                 .with_children(|row| {
                     // === TITLE ===
-                    row.spawn((NodeBundle {
-                        style: Style {
-                            width: Val::Percent(33.0),
-                            height: Val::Percent(90.0),
-                            align_items: AlignItems::Center,
-                            justify_content: JustifyContent::Start,
-                            ..default()
-                        },
-                        ..default()
-                    },))
+                    row.spawn((tank_row_cell(33.0, JustifyContent::Start),))
                         .with_children(|cell| {
                             cell.spawn(TextBundle::from_section(title, text_style.clone()));
                         });
 
                     // === Value ===
-                    row.spawn((NodeBundle {
-                        style: Style {
-                            width: Val::Percent(33.0),
-                            height: Val::Percent(90.0),
-                            align_items: AlignItems::Center,
-                            justify_content: JustifyContent::End,
-                            ..default()
-                        },
-                        ..default()
-                    },))
-                        .with_children(|cell| {
-                            cell.spawn((
-                                comp_value_label,
-                                TextBundle::from_section("666", text_style.clone()),
-                            ));
-                        });
-                    // === Minus Button ===
-                    row.spawn((button_style.clone(), comp_minus))
-                        .insert(UiMarkMouseOverMenu)
-                        .with_children(|parent| {
-                            parent.spawn(TextBundle::from_section("-", text_style.clone()));
-                        });
-                    // === Plus Button ===
-                    row.spawn((button_style.clone(), comp_plus))
-                        .insert(UiMarkMouseOverMenu)
-                        .with_children(|parent| {
-                            parent.spawn(TextBundle::from_section("+", text_style.clone()));
-                        });
+                    row.spawn((value_cell,)).with_children(|cell| {
+                        cell.spawn((
+                            comp_value_label,
+                            TextBundle::from_section("666", text_style.clone()),
+                        ));
+                    });
+                    add_rest(row, &text_style);
                 });
         })
         .id()
 }
 
+fn tank_row_cell(width: f32, justify_content: JustifyContent) -> NodeBundle {
+    NodeBundle {
+        style: Style {
+            width: Val::Percent(width),
+            height: Val::Percent(90.0),
+            align_items: AlignItems::Center,
+            justify_content,
+            ..default()
+        },
+        ..default()
+    }
+}
+
 fn build_tank_control_root(commands: &mut Commands) -> Entity {
     commands
         .spawn((
             NodeBundle {
                 style: Style {
                     width: Val::Px(300.0),
-                    height: Val::Px(150.0),
+                    height: Val::Px(190.0),
                     flex_direction: FlexDirection::Column,
                     align_items: AlignItems::Center,
                     justify_content: JustifyContent::Center,
@@ -0,0 +1,168 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+use bevy_hanabi::prelude::*;
+use bevy_inspector_egui::prelude::*;
+use rand::random;
+use smart_default::SmartDefault;
+
+use crate::terrain::altitude;
+
+use super::bullet::{BulletExplodingEffectMarker, BulletFlyingEffectMarker};
+use super::tank::surface_rotation;
+
+pub struct WindPlugin;
+impl Plugin for WindPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Wind>()
+            .register_type::<Wind>()
+            .add_systems(Startup, randomize_wind)
+            .add_systems(PreUpdate, blow_wind_gusts)
+            .add_systems(Update, drift_effects_in_wind);
+    }
+}
+
+/// how much of the wind the aim solver corrects for, when the player clicks to aim
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Reflect, Default)]
+pub enum WindDifficulty {
+    /// all of it, as it blows at `reference_altitude` with the gust of the moment.
+    /// the shell lands close to the click, not on it: the wind changes with the
+    /// altitude and the gusts along the flight
+    #[default]
+    Easy,
+    /// half of the steady wind, the player finishes the correction
+    Normal,
+    /// none, reading the wind indicator is up to the player
+    Hard,
+}
+
+#[derive(Reflect, Resource, InspectorOptions, SmartDefault)]
+#[reflect(Resource, InspectorOptions)]
+pub struct Wind {
+    /// radians, where the wind blows to: from +Z towards +X, like the tank bearing
+    #[default(0.0)]
+    #[inspector(min = -3.15, max = 3.15)]
+    pub direction: f32,
+
+    /// m/s at `reference_altitude`, without the gusts
+    #[default(6.0)]
+    #[inspector(min = 0.0, max = 30.0)]
+    pub strength: f32,
+
+    /// m/s the gusts add on top of `strength`, at their strongest
+    #[default(4.0)]
+    #[inspector(min = 0.0, max = 30.0)]
+    pub gust_strength: f32,
+
+    /// seconds from one gust to the next
+    #[default(9.0)]
+    #[inspector(min = 1.0, max = 60.0)]
+    pub gust_period: f32,
+
+    /// meters above the ground where the wind blows at `strength`, about the top of the arcs
+    #[default(100.0)]
+    #[inspector(min = 1.0, max = 1000.0)]
+    pub reference_altitude: f32,
+
+    /// the wind grows with the altitude as `(altitude / reference_altitude) ^ altitude_exponent`
+    #[default(0.14)]
+    #[inspector(min = 0.0, max = 1.0)]
+    pub altitude_exponent: f32,
+
+    pub difficulty: WindDifficulty,
+
+    /// m/s of gust blowing right now
+    #[reflect(ignore)]
+    gust: f32,
+}
+
+/// the `Vec3` property of the bullet effects with the wind velocity, in m/s
+pub const WIND_EFFECT_PROPERTY: &str = "wind";
+/// the wind speed at altitude stops growing at this many times `strength`
+const WIND_MAX_ALTITUDE_FACTOR: f32 = 2.0;
+/// the wind never dies down completely close to the ground
+const WIND_MIN_ALTITUDE_FACTOR: f32 = 0.2;
+/// strongest wind a match can start with, in m/s
+const WIND_MAX_RANDOM_STRENGTH: f32 = 12.0;
+
+impl Wind {
+    /// m/s of wind at `pos`, gusts included
+    pub fn velocity_at(&self, pos: &Vec3) -> Vec3 {
+        self.velocity_with_gust(pos, self.gust)
+    }
+
+    /// `velocity_at()` with the gust blowing at `seconds` of the `ShellClock`, for the
+    /// shells: it's the same for the same shot, whatever the frame rate
+    pub fn velocity_at_time(&self, pos: &Vec3, seconds: f32) -> Vec3 {
+        self.velocity_with_gust(pos, self.gust_at(seconds))
+    }
+
+    /// m/s of gust `seconds` into the game: two waves out of step, so the gusts
+    /// don't all come alike
+    pub fn gust_at(&self, seconds: f32) -> f32 {
+        let phase = seconds * 2.0 * PI / self.gust_period;
+        let swell = (phase.sin() * (phase * 0.37 + 1.0).sin()).max(0.0);
+        self.gust_strength * swell
+    }
+
+    /// m/s of wind blowing over the ground at `pos`, at `reference_altitude`
+    pub fn steady_velocity_at(&self, pos: &Vec3) -> Vec3 {
+        self.velocity_over(pos, self.strength)
+    }
+
+    /// the wind the aim solver corrects for at the muzzle. the AI tanks don't get
+    /// the difficulty, they read the steady wind and miss a bit in the gusts.
+    pub fn aim_velocity_at(&self, pos: &Vec3, is_player: bool) -> Vec3 {
+        if !is_player {
+            return self.steady_velocity_at(pos);
+        }
+        match self.difficulty {
+            WindDifficulty::Easy => self.velocity_over(pos, self.strength + self.gust),
+            WindDifficulty::Normal => self.steady_velocity_at(pos) * 0.5,
+            WindDifficulty::Hard => Vec3::ZERO,
+        }
+    }
+
+    /// m/s of steady wind and gust blowing right now, for the indicator
+    pub fn speed(&self) -> f32 {
+        self.strength + self.gust
+    }
+
+    fn velocity_with_gust(&self, pos: &Vec3, gust: f32) -> Vec3 {
+        let profile = (altitude(pos).max(0.0) / self.reference_altitude)
+            .powf(self.altitude_exponent)
+            .clamp(WIND_MIN_ALTITUDE_FACTOR, WIND_MAX_ALTITUDE_FACTOR);
+        self.velocity_over(pos, (self.strength + gust) * profile)
+    }
+
+    fn velocity_over(&self, pos: &Vec3, speed: f32) -> Vec3 {
+        surface_rotation(pos) * Quat::from_rotation_y(self.direction) * Vec3::Z * speed
+    }
+}
+
+fn randomize_wind(mut wind: ResMut<Wind>) {
+    wind.direction = (random::<f32>() * 2.0 - 1.0) * PI;
+    wind.strength = random::<f32>() * WIND_MAX_RANDOM_STRENGTH;
+}
+
+fn blow_wind_gusts(mut wind: ResMut<Wind>, time: Res<Time>) {
+    wind.gust = wind.gust_at(time.elapsed_seconds());
+}
+
+/// the smoke trails and explosions drift with the wind, at the wind speed
+#[allow(clippy::type_complexity)]
+fn drift_effects_in_wind(
+    mut effects: Query<
+        (&GlobalTransform, &mut CompiledParticleEffect),
+        Or<(
+            With<BulletFlyingEffectMarker>,
+            With<BulletExplodingEffectMarker>,
+        )>,
+    >,
+    wind: Res<Wind>,
+) {
+    for (effect_tr, mut effect) in effects.iter_mut() {
+        let velocity = wind.velocity_at(&effect_tr.translation());
+        effect.set_property(WIND_EFFECT_PROPERTY, velocity.into());
+    }
+}
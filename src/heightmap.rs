@@ -0,0 +1,279 @@
+use std::io;
+use std::path::Path;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use smart_default::SmartDefault;
+
+use crate::terrain::{heights, project_to_surface, MOUNTAIN_HEIGHT, PLANET_MAX_PLAY_RADIUS};
+
+/// how the heightmap goes together with the generator heights
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Reflect, Default, Serialize, Deserialize)]
+pub enum HeightmapBlend {
+    /// the heightmap instead of the noise, faded into it at the edge of the disk
+    #[default]
+    Replace,
+    /// the heightmap on top of the noise
+    Add,
+}
+
+/// What the pixels of a heightmap mean. Saved next to the image, as `<name>.ron`.
+#[derive(Debug, Clone, Copy, PartialEq, Reflect, Serialize, Deserialize, SmartDefault)]
+#[serde(default)]
+pub struct HeightmapSettings {
+    /// meters at black
+    #[default(0.0)]
+    pub min_height: f32,
+    /// meters at white
+    #[default(MOUNTAIN_HEIGHT)]
+    pub max_height: f32,
+    pub blend: HeightmapBlend,
+    /// meters inside the edge of the disk over which the heightmap fades out
+    #[default(1000.0)]
+    pub edge_fade: f32,
+}
+
+/// Painted heights, stretched over the `PLANET_MAX_PLAY_RADIUS` disk around the
+/// origin: the square around the disk, +X to the right and the top row at -Z, as
+/// seen from above. Nothing of it shows outside the disk.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Heightmap {
+    pub settings: HeightmapSettings,
+    width: u32,
+    height: u32,
+    /// 0..1, row by row
+    values: Vec<f32>,
+}
+
+fn invalid_data(err: impl std::error::Error + Send + Sync + 'static) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+/// meters between the pixel centers, `size` pixels over the disk
+fn pixel_size(size: u32) -> f32 {
+    2.0 * PLANET_MAX_PLAY_RADIUS / (size - 1) as f32
+}
+
+impl Heightmap {
+    /// `values` are 0..1, `width * height` of them row by row
+    pub fn new(width: u32, height: u32, values: Vec<f32>, settings: HeightmapSettings) -> Self {
+        assert!(width >= 2 && height >= 2, "heightmap too small");
+        assert_eq!(values.len(), (width * height) as usize);
+        Self {
+            settings,
+            width,
+            height,
+            values,
+        }
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Any image the image crate reads: 8 or 16 bit png (the red channel of color
+    /// ones), or exr with 0..1 values. The settings come from the `.ron` next to
+    /// it, or are the defaults without one.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let image = image::open(path).map_err(invalid_data)?.to_luma32f();
+        if image.width() < 2 || image.height() < 2 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "heightmap needs 2x2 pixels, got {}x{}",
+                    image.width(),
+                    image.height()
+                ),
+            ));
+        }
+        let settings_path = path.with_extension("ron");
+        let settings = if settings_path.exists() {
+            ron::from_str(&std::fs::read_to_string(settings_path)?).map_err(invalid_data)?
+        } else {
+            HeightmapSettings::default()
+        };
+        Ok(Self::new(
+            image.width(),
+            image.height(),
+            image.into_raw(),
+            settings,
+        ))
+    }
+
+    /// 16 bit grayscale png, and the settings next to it
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let pixels: Vec<u16> = self
+            .values
+            .iter()
+            .map(|v| (v.clamp(0.0, 1.0) * u16::MAX as f32).round() as u16)
+            .collect();
+        image::ImageBuffer::<image::Luma<u16>, _>::from_raw(self.width, self.height, pixels)
+            .expect("heightmap size")
+            .save_with_format(path, image::ImageFormat::Png)
+            .map_err(invalid_data)?;
+        let settings =
+            ron::ser::to_string_pretty(&self.settings, ron::ser::PrettyConfig::default())
+                .map_err(invalid_data)?;
+        std::fs::write(path.with_extension("ron"), settings)
+    }
+
+    /// `height()` over the disk, `size` x `size` pixels, using the whole 16 bits
+    pub fn from_terrain(size: u32) -> Self {
+        let pixel = pixel_size(size);
+        let positions: Vec<Vec3> = (0..size * size)
+            .map(|i| {
+                let (x, z) = ((i % size) as f32, (i / size) as f32);
+                Vec3::new(
+                    x * pixel - PLANET_MAX_PLAY_RADIUS,
+                    0.0,
+                    z * pixel - PLANET_MAX_PLAY_RADIUS,
+                )
+            })
+            .collect();
+        let heights = heights(&positions);
+        let min = heights.iter().copied().fold(f32::INFINITY, f32::min);
+        let max = heights.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        // a flat map still needs a range
+        let range = (max - min).max(1.0);
+        Self::new(
+            size,
+            size,
+            heights.iter().map(|h| (h - min) / range).collect(),
+            HeightmapSettings {
+                min_height: min,
+                max_height: min + range,
+                ..default()
+            },
+        )
+    }
+
+    fn pixel(&self, x: u32, z: u32) -> f32 {
+        self.values[(z * self.width + x) as usize]
+    }
+
+    /// meters, bilinear between the pixels. `surface` is on the zero-height planet surface
+    pub fn height(&self, surface: &Vec3) -> f32 {
+        let to_pixel = |v: f32, size: u32| {
+            ((v + PLANET_MAX_PLAY_RADIUS) / pixel_size(size)).clamp(0.0, (size - 1) as f32)
+        };
+        let (x, z) = (
+            to_pixel(surface.x, self.width),
+            to_pixel(surface.z, self.height),
+        );
+        let (x0, z0) = (
+            (x as u32).min(self.width - 2),
+            (z as u32).min(self.height - 2),
+        );
+        let (tx, tz) = (x - x0 as f32, z - z0 as f32);
+        let top = self.pixel(x0, z0) * (1.0 - tx) + self.pixel(x0 + 1, z0) * tx;
+        let bottom = self.pixel(x0, z0 + 1) * (1.0 - tx) + self.pixel(x0 + 1, z0 + 1) * tx;
+        let value = top * (1.0 - tz) + bottom * tz;
+        self.settings.min_height + value * (self.settings.max_height - self.settings.min_height)
+    }
+
+    /// 1 inside the disk, smoothly down to 0 at its edge
+    fn weight(&self, surface: &Vec3) -> f32 {
+        let dist = Vec2::new(surface.x, surface.z).length();
+        let t =
+            ((PLANET_MAX_PLAY_RADIUS - dist) / self.settings.edge_fade.max(0.001)).clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    }
+
+    /// the terrain height at `pos`, given the height of the noise there
+    pub fn blend(&self, pos: &Vec3, noise_height: f32) -> f32 {
+        let surface = project_to_surface(pos);
+        let weight = self.weight(&surface);
+        if weight == 0.0 {
+            return noise_height;
+        }
+        let height = self.height(&surface);
+        match self.settings.blend {
+            HeightmapBlend::Replace => noise_height + (height - noise_height) * weight,
+            HeightmapBlend::Add => noise_height + height * weight,
+        }
+    }
+}
+
+#[test]
+fn test_heightmap_blends_inside_the_disk() {
+    use crate::terrain::{PlanetShape, TestTerrain};
+
+    let _terrain = TestTerrain::lock(PlanetShape::Flat);
+
+    // a ramp going up along +X
+    let size = 33;
+    let values = (0..size * size)
+        .map(|i| (i % size) as f32 / (size - 1) as f32)
+        .collect();
+    let mut map = Heightmap::new(size, size, values, HeightmapSettings::default());
+
+    let center = Vec3::ZERO;
+    let half = MOUNTAIN_HEIGHT / 2.0;
+    assert!((map.height(&center) - half).abs() < 1e-3);
+    assert!((map.blend(&center, 123.0) - half).abs() < 1e-3);
+    // between two pixels
+    let quarter = Vec3::new(
+        PLANET_MAX_PLAY_RADIUS / 2.0 + pixel_size(size) / 2.0,
+        0.0,
+        0.0,
+    );
+    let expected = MOUNTAIN_HEIGHT * (24.5 / 32.0);
+    assert!((map.height(&quarter) - expected).abs() < 1e-2);
+
+    // outside the disk, even if inside the square
+    let corner = Vec3::new(
+        PLANET_MAX_PLAY_RADIUS * 0.9,
+        0.0,
+        PLANET_MAX_PLAY_RADIUS * 0.9,
+    );
+    assert_eq!(map.blend(&corner, 123.0), 123.0);
+
+    map.settings.blend = HeightmapBlend::Add;
+    assert!((map.blend(&center, 100.0) - (100.0 + half)).abs() < 1e-3);
+}
+
+#[test]
+fn test_exported_heightmap_round_trips_through_png() {
+    use crate::terrain::{PlanetShape, TestTerrain};
+
+    let _terrain = TestTerrain::lock(PlanetShape::Flat);
+
+    let map = Heightmap::from_terrain(65);
+    let path = std::env::temp_dir().join(format!(
+        "game_test_heightmap_round_trip_{}.png",
+        std::process::id()
+    ));
+    map.save(&path).unwrap();
+    let loaded = Heightmap::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_file(path.with_extension("ron")).unwrap();
+    assert_eq!(loaded.size(), (65, 65));
+    assert_eq!(loaded.settings, map.settings);
+
+    // the pixels are the terrain heights, up to the 16 bit steps
+    let step = (map.settings.max_height - map.settings.min_height) / u16::MAX as f32;
+    for (x, z) in [(0, 0), (32, 32), (10, 50), (64, 7)] {
+        let pos = Vec3::new(
+            x as f32 * pixel_size(65) - PLANET_MAX_PLAY_RADIUS,
+            0.0,
+            z as f32 * pixel_size(65) - PLANET_MAX_PLAY_RADIUS,
+        );
+        let height = heights(&[pos])[0];
+        assert!((map.height(&pos) - height).abs() < 1e-2);
+        assert!((loaded.height(&pos) - height).abs() <= step + 1e-2);
+    }
+}
+
+#[test]
+fn test_loading_a_single_pixel_heightmap_fails() {
+    let path = std::env::temp_dir().join(format!(
+        "game_test_heightmap_single_pixel_{}.png",
+        std::process::id()
+    ));
+    image::GrayImage::new(1, 1).save(&path).unwrap();
+    let loaded = Heightmap::load(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded.unwrap_err().kind(), io::ErrorKind::InvalidData);
+}
@@ -2,14 +2,24 @@ mod assets;
 mod audio;
 mod camera_extra;
 mod camera_flying;
+pub mod crater;
 mod gameplay;
+pub mod heightmap;
+mod lod;
 mod menu;
 #[allow(dead_code)]
 mod oct_tree;
 mod piramida;
 mod planet;
+mod props;
 mod raycast;
-mod terrain;
+mod simplex;
+pub mod terrain;
+mod terrain_cache;
+pub mod terrain_export;
+pub mod terrain_generator;
+mod terrain_material;
+mod tri_coord;
 mod triangle;
 mod utils;
 
@@ -92,6 +102,8 @@ pub fn create_game_app(disable_graphics: bool) -> App {
         // PHYSICS AND SHIT
         // ==============
         .add_plugins(RapierPhysicsPlugin::<NoUserData>::default())
+        // FixedUpdate steps the shells, one step of their flight each
+        .insert_resource(FixedTime::new_from_secs(gameplay::PROJECTILE_TIMESTEP))
         // .add_plugins(RapierDebugRenderPlugin::default())
         // ==============
         // GAME PLUGINS
@@ -101,6 +113,7 @@ pub fn create_game_app(disable_graphics: bool) -> App {
         .add_plugins(camera_extra::ExtraCameraPlugin)
         .add_plugins(GameAudioPlugin)
         .add_plugins(PlanetPlugin)
+        .add_plugins(props::PropsPlugin)
         .add_plugins(GameAssetsPlugin)
         .add_plugins(GameplayPlugin)
         .add_plugins(raycast::RaycastPlugin)
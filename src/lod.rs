@@ -0,0 +1,191 @@
+use bevy::prelude::*;
+use bevy::render::primitives::{Frustum, Sphere};
+use smart_default::SmartDefault;
+
+/// far away from everything, when there are no probes
+const NO_PROBE_DIST: f32 = 666666.0;
+
+/// A camera the terrain detail is computed for.
+#[derive(Debug, Clone, Copy)]
+pub struct LodCamera {
+    pub position: Vec3,
+    pub frustum: Frustum,
+    /// pixels covered by 1m on screen, seen from 1m away for perspective cameras
+    pub pixels_per_meter: f32,
+    pub perspective: bool,
+}
+
+impl LodCamera {
+    pub fn new(
+        transform: &GlobalTransform,
+        frustum: &Frustum,
+        projection: &Projection,
+        viewport_size: Vec2,
+    ) -> Self {
+        let (pixels_per_meter, perspective) = match projection {
+            Projection::Perspective(p) => (viewport_size.y / (2.0 * (p.fov / 2.0).tan()), true),
+            Projection::Orthographic(o) => (viewport_size.y / o.area.height(), false),
+        };
+        Self {
+            position: transform.translation(),
+            frustum: *frustum,
+            pixels_per_meter,
+            perspective,
+        }
+    }
+
+    /// on-screen size in pixels of something `size` meters big, somewhere in the
+    /// sphere. 0 when the sphere is out of view.
+    pub fn screen_size(&self, size: f32, sphere: &Sphere) -> f32 {
+        if !self.frustum.intersects_sphere(sphere, true) {
+            return 0.0;
+        }
+        if !self.perspective {
+            return size * self.pixels_per_meter;
+        }
+        let center = Vec3::from(sphere.center);
+        // inside the sphere, as close as it gets
+        let dist = (center.distance(self.position) - sphere.radius).max(0.01);
+        size * self.pixels_per_meter / dist
+    }
+}
+
+/// A split probe (tank, bullet) wants detailed colliders around it, seen or not.
+/// See `planet::TerrainSplitProbe`.
+#[derive(Debug, Clone, Copy)]
+pub struct LodProbe {
+    pub position: Vec3,
+    /// meters, terrain further away than this is none of its business
+    pub radius: f32,
+    /// it doesn't split triangles at this level or deeper
+    pub max_level: u8,
+    /// 2 asks for the same detail twice as far
+    pub priority: f32,
+}
+
+/// Everything the terrain detail gets computed against.
+#[derive(Debug, Clone, SmartDefault)]
+pub struct LodViews {
+    pub cameras: Vec<LodCamera>,
+    pub probes: Vec<LodProbe>,
+    /// scales the detail down when the planet is over its triangle budget, in (0, 1].
+    /// the cameras and the top priority probes by this much, the other probes by more
+    #[default(1.0)]
+    pub detail: f32,
+}
+
+impl LodViews {
+    /// distance from the closest probe that cares about a triangle at `level`, scaled by
+    /// its priority and the detail. `reach` scales the probe radii, for some hysteresis
+    pub fn probe_dist(&self, sphere: &Sphere, level: u8, reach: f32) -> f32 {
+        let center = Vec3::from(sphere.center);
+        let top_priority = self
+            .probes
+            .iter()
+            .map(|probe| probe.priority)
+            .fold(0.0, f32::max);
+        self.probes
+            .iter()
+            .filter(|probe| level < probe.max_level)
+            .filter_map(|probe| {
+                let dist = probe.position.distance(center);
+                (dist - sphere.radius <= probe.radius * reach).then(|| {
+                    // over budget, the top probes lose `detail` and the others more:
+                    // half the priority, detail squared
+                    let detail = self.detail.powf(top_priority / probe.priority);
+                    dist / (probe.priority * detail)
+                })
+            })
+            .reduce(f32::min)
+            .unwrap_or(NO_PROBE_DIST)
+    }
+
+    /// biggest on-screen size over all the cameras, see `LodCamera::screen_size()`,
+    /// scaled by the detail
+    pub fn screen_size(&self, size: f32, sphere: &Sphere) -> f32 {
+        self.cameras
+            .iter()
+            .map(|camera| camera.screen_size(size, sphere))
+            .fold(0.0, f32::max)
+            * self.detail
+    }
+}
+
+#[test]
+fn test_screen_size_of_visible_and_hidden_spheres() {
+    use bevy::render::camera::CameraProjection;
+
+    let transform = GlobalTransform::from(Transform::IDENTITY);
+    let perspective = PerspectiveProjection::default();
+    let frustum = Frustum::from_view_projection_custom_far(
+        &(perspective.get_projection_matrix() * transform.compute_matrix().inverse()),
+        &transform.translation(),
+        &transform.back(),
+        perspective.far(),
+    );
+    let camera = LodCamera::new(
+        &transform,
+        &frustum,
+        &Projection::Perspective(perspective),
+        Vec2::new(1920.0, 1080.0),
+    );
+    let sphere = |center: Vec3| Sphere {
+        center: center.into(),
+        radius: 1.0,
+    };
+
+    // the camera looks down -Z
+    let near = camera.screen_size(2.0, &sphere(Vec3::new(0.0, 0.0, -11.0)));
+    let far = camera.screen_size(2.0, &sphere(Vec3::new(0.0, 0.0, -101.0)));
+    assert!(near > 0.0 && far > 0.0);
+    assert!((near / far - 10.0).abs() < 1e-3);
+    assert_eq!(
+        camera.screen_size(2.0, &sphere(Vec3::new(0.0, 0.0, 50.0))),
+        0.0
+    );
+
+    let views = LodViews {
+        cameras: vec![camera],
+        ..default()
+    };
+    assert_eq!(views.probe_dist(&sphere(Vec3::ZERO), 0, 1.0), NO_PROBE_DIST);
+}
+
+#[test]
+fn test_probe_radius_level_and_priority() {
+    let probe = LodProbe {
+        position: Vec3::ZERO,
+        radius: 100.0,
+        max_level: 10,
+        priority: 1.0,
+    };
+    let sphere = |x: f32, radius: f32| Sphere {
+        center: Vec3::new(x, 0.0, 0.0).into(),
+        radius,
+    };
+    let mut views = LodViews {
+        probes: vec![probe],
+        ..default()
+    };
+    assert_eq!(views.probe_dist(&sphere(50.0, 1.0), 5, 1.0), 50.0);
+    // out of its radius, unless the triangle reaches into it
+    assert_eq!(views.probe_dist(&sphere(150.0, 1.0), 5, 1.0), NO_PROBE_DIST);
+    assert_eq!(views.probe_dist(&sphere(150.0, 60.0), 5, 1.0), 150.0);
+    assert_eq!(views.probe_dist(&sphere(150.0, 1.0), 5, 1.6), 150.0);
+    // too deep for it
+    assert_eq!(views.probe_dist(&sphere(50.0, 1.0), 10, 1.0), NO_PROBE_DIST);
+
+    // the important probe gets the same detail further away, until over budget
+    views.probes.push(LodProbe {
+        position: Vec3::new(200.0, 0.0, 0.0),
+        priority: 4.0,
+        ..probe
+    });
+    assert_eq!(views.probe_dist(&sphere(120.0, 1.0), 5, 1.0), 20.0);
+    views.detail = 0.5;
+    assert_eq!(views.probe_dist(&sphere(120.0, 1.0), 5, 1.0), 40.0);
+    // and the low priority one gives up its detail first: 0.5^4 instead of 0.5
+    assert_eq!(views.probe_dist(&sphere(50.0, 1.0), 5, 1.0), 800.0);
+    views.probes.pop();
+    assert_eq!(views.probe_dist(&sphere(50.0, 1.0), 5, 1.0), 100.0);
+}
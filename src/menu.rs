@@ -1,8 +1,17 @@
+use std::io;
+
 use bevy::prelude::*;
+use bevy::tasks::{AsyncComputeTaskPool, Task};
+use futures_lite::future;
 
 use bevy_egui::{egui, EguiContexts, EguiPlugin};
 
-use super::terrain::TerrainSettings;
+use super::terrain::{craters, set_heightmap, TerrainSettings};
+use crate::crater::Craters;
+use crate::heightmap::Heightmap;
+use crate::planet::TerrainCraterEvent;
+use crate::terrain_cache::CacheStats;
+use crate::terrain_generator::TerrainGenerator;
 use bevy::window::{CursorGrabMode, PrimaryWindow};
 use bevy_inspector_egui::prelude::InspectorOptions;
 
@@ -34,15 +43,39 @@ pub struct UiMenuState {
     pub triangle_count: f32,
     pub mesh_count: f32,
     pub tri_compute_ms: f32,
+    pub lod_detail: f32,
+    pub subtree_cache: CacheStats,
+    pub mesh_cache: CacheStats,
     pub mouse_over_menu: bool,
     pub is_mouse_captured: bool,
 }
 
+/// where the menu saves the craters of the match, in the working directory
+const CRATERS_SAVE_PATH: &str = "craters.ron";
+/// where the menu loads the heightmap from and exports the terrain to, with its `.ron` next to it
+const HEIGHTMAP_PATH: &str = "heightmap.png";
+/// pixels per side of the exported heightmap
+const HEIGHTMAP_EXPORT_SIZE: u32 = 2049;
+
 pub fn egui_ui_system(
     mut egui_context: EguiContexts,
     mut ui_state: ResMut<UiMenuState>,
+    mut generator: ResMut<TerrainGenerator>,
+    // sampling the whole terrain takes a while, it runs off the frame
+    mut heightmap_export: Local<Option<Task<io::Result<()>>>>,
+    type_registry: Res<AppTypeRegistry>,
+    mut crater_events: EventWriter<TerrainCraterEvent>,
     interaction_query: Query<&Interaction, With<UiMarkMouseOverMenu>>,
 ) {
+    if let Some(task) = heightmap_export.as_mut() {
+        if let Some(result) = future::block_on(future::poll_once(task)) {
+            *heightmap_export = None;
+            match result {
+                Ok(()) => info!("terrain exported to {}", HEIGHTMAP_PATH),
+                Err(err) => warn!("can't export the terrain to {}: {}", HEIGHTMAP_PATH, err),
+            }
+        }
+    }
     egui::Window::new("Piramidă").show(egui_context.ctx_mut(), |ui| {
         ui.label(
             "  Triangles: ".to_string()
@@ -50,8 +83,22 @@ pub fn egui_ui_system(
                 + "  Meshes: "
                 + ui_state.mesh_count.to_string().as_str()
                 + " COMPUTE MS: "
-                + ui_state.tri_compute_ms.to_string().as_str(),
+                + ui_state.tri_compute_ms.to_string().as_str()
+                + " DETAIL: "
+                + ui_state.lod_detail.to_string().as_str(),
         );
+        for (name, stats) in [
+            ("SUBTREE CACHE", ui_state.subtree_cache),
+            ("MESH CACHE", ui_state.mesh_cache),
+        ] {
+            ui.label(format!(
+                "  {}: {} entries, {:.1} MB, {:.0}% hits",
+                name,
+                stats.entries,
+                stats.bytes as f32 / (1024.0 * 1024.0),
+                stats.hit_rate() * 100.0
+            ));
+        }
         ui.label(" MOUSE OVER MENU: ".to_string() + ui_state.mouse_over_menu.to_string().as_str());
         ui.label("Planet Settings");
 
@@ -70,6 +117,11 @@ pub fn egui_ui_system(
                 .text("TESSELATION_VALUE"),
         );
 
+        ui.add(
+            egui::Slider::new(&mut ui_state.settings.SCREEN_SPACE_ERROR, 4.0..=512.0)
+                .text("SCREEN_SPACE_ERROR"),
+        );
+
         ui.add(
             egui::Slider::new(&mut ui_state.settings.MIN_CAMERA_HEIGHT, 0.3..=3.0)
                 .text("MIN_CAMERA_HEIGHT"),
@@ -86,7 +138,83 @@ pub fn egui_ui_system(
             egui::Slider::new(&mut ui_state.settings.MIN_TRIANGLE_EDGE_SIZE, 0.01..=10.0)
                 .text("MIN_TRIANGLE_EDGE_SIZE"),
         );
+        ui.add(
+            egui::Slider::new(&mut ui_state.settings.MESH_UPLOADS_PER_FRAME, 1..=256)
+                .text("MESH_UPLOADS_PER_FRAME"),
+        );
+        ui.add(
+            egui::Slider::new(&mut ui_state.settings.TRIANGLE_BUDGET, 1_000..=5_000_000)
+                .logarithmic(true)
+                .text("TRIANGLE_BUDGET"),
+        );
+        ui.add(
+            egui::Slider::new(&mut ui_state.settings.SUBTREE_CACHE_MB, 0..=1024)
+                .text("SUBTREE_CACHE_MB"),
+        );
+        ui.add(
+            egui::Slider::new(&mut ui_state.settings.MESH_CACHE_MB, 0..=4096).text("MESH_CACHE_MB"),
+        );
+        ui.add(egui::Slider::new(&mut ui_state.settings.PROPS_SEED, 0..=1000).text("PROPS_SEED"));
+        ui.add(
+            egui::Slider::new(&mut ui_state.settings.PROPS_RADIUS, 100.0..=3000.0)
+                .text("PROPS_RADIUS"),
+        );
         ui.checkbox(&mut ui_state.enable_animation, "ENABLE ADNIMATION");
+
+        // editing it rebuilds the planet, so only flag it changed when something really changed
+        egui::CollapsingHeader::new("Terrain Generator").show(ui, |ui| {
+            let changed = bevy_inspector_egui::reflect_inspector::ui_for_value(
+                generator.bypass_change_detection(),
+                ui,
+                &type_registry.read(),
+            );
+            if changed {
+                generator.set_changed();
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Craters");
+            if ui.button("Save").clicked() {
+                match craters().save(CRATERS_SAVE_PATH) {
+                    Ok(()) => info!("craters saved to {}", CRATERS_SAVE_PATH),
+                    Err(err) => warn!("can't save craters to {}: {}", CRATERS_SAVE_PATH, err),
+                }
+            }
+            if ui.button("Load").clicked() {
+                match Craters::load(CRATERS_SAVE_PATH) {
+                    Ok(loaded) => crater_events.send(TerrainCraterEvent::Replace(loaded)),
+                    Err(err) => warn!("can't load craters from {}: {}", CRATERS_SAVE_PATH, err),
+                }
+            }
+        });
+
+        // the heights change everywhere, so it's the same rebuild as for a generator change
+        ui.horizontal(|ui| {
+            ui.label("Heightmap");
+            let exporting = heightmap_export.is_some();
+            let export_text = if exporting { "Exporting..." } else { "Export" };
+            if ui
+                .add_enabled(!exporting, egui::Button::new(export_text))
+                .clicked()
+            {
+                *heightmap_export = Some(AsyncComputeTaskPool::get().spawn(async {
+                    Heightmap::from_terrain(HEIGHTMAP_EXPORT_SIZE).save(HEIGHTMAP_PATH)
+                }));
+            }
+            if ui.button("Load").clicked() {
+                match Heightmap::load(HEIGHTMAP_PATH) {
+                    Ok(heightmap) => {
+                        set_heightmap(Some(heightmap));
+                        generator.set_changed();
+                    }
+                    Err(err) => warn!("can't load the heightmap from {}: {}", HEIGHTMAP_PATH, err),
+                }
+            }
+            if ui.button("Clear").clicked() && set_heightmap(None).is_some() {
+                generator.set_changed();
+            }
+        });
     });
     ui_state.mouse_over_menu = egui_context.ctx_mut().is_pointer_over_area()
         || interaction_query
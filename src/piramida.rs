@@ -1,8 +1,11 @@
 // use std::collections::{vec_deque, VecDeque};
 use bevy::prelude::*;
 
-use super::terrain::PLANET_RADIUS;
-use crate::triangle::Triangle;
+use super::terrain::{
+    planet_shape, project_to_surface, set_planet_shape, PlanetShape, PLANET_CENTER, PLANET_RADIUS,
+};
+use crate::tri_coord::TriCoord;
+use crate::triangle::{child_corners, vert_key, Triangle};
 
 pub trait Piramidesc {
     fn base_tris(&mut self) -> Vec<Triangle>;
@@ -24,6 +27,107 @@ impl<const N: usize> Piramidesc for Piramidă<N> {
     }
 }
 
+impl<const N: usize> Piramidă<N> {
+    fn face(&self, id: u8) -> &Triangle {
+        self.children
+            .iter()
+            .find(|face| face.coord().face_id() == id)
+            .expect("no such face")
+    }
+
+    /// corners of the node on the zero-height surface, same as the splits make them
+    pub fn corners(&self, coord: TriCoord) -> [Vec3; 3] {
+        coord
+            .ids()
+            .skip(1)
+            .fold(self.face(coord.face_id()).base_corners(), |corners, id| {
+                child_corners(&corners, id)
+            })
+    }
+
+    /// the node at `level` with `pos` in it (or under/over it). outside the flat
+    /// map, the closest one.
+    pub fn locate(&self, pos: &Vec3, level: u8) -> TriCoord {
+        let surface = project_to_surface(pos);
+        // how far inside the triangle the point is, negative outside
+        let inside = |corners: &[Vec3; 3]| {
+            (0..3)
+                .map(|i| edge_side(&corners[i], &corners[(i + 1) % 3], &surface))
+                .fold(f32::INFINITY, f32::min)
+        };
+        let closest = |candidates: &mut dyn Iterator<Item = TriCoord>| {
+            candidates
+                .map(|coord| (coord, inside(&self.corners(coord))))
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+                .expect("no candidates")
+                .0
+        };
+        let mut coord = closest(&mut self.children.iter().map(|face| face.coord()));
+        for _ in 0..level {
+            coord = closest(&mut (1..=4).map(|id| coord.child(id)));
+        }
+        coord
+    }
+
+    /// `TriCoord::neighbour()`, going over to the next face when needed
+    pub fn neighbour(&self, coord: TriCoord, edge: u8) -> Option<(TriCoord, u8)> {
+        coord.neighbour_with(edge, &|face, edge| {
+            let corners = self.face(face).base_corners();
+            let (a, b) = (
+                vert_key(&corners[edge as usize]),
+                vert_key(&corners[(edge as usize + 1) % 3]),
+            );
+            // the other face has the same edge, the other way around
+            self.children.iter().find_map(|other| {
+                let corners = other.base_corners();
+                (0..3).find_map(|k| {
+                    (vert_key(&corners[k]) == b && vert_key(&corners[(k + 1) % 3]) == a)
+                        .then(|| (other.coord().face_id(), k as u8))
+                })
+            })
+        })
+    }
+}
+
+/// distance of `p` from the a->b edge, positive on the inside of a triangle
+/// wound like the planet faces
+fn edge_side(a: &Vec3, b: &Vec3, p: &Vec3) -> f32 {
+    if planet_shape().is_spherical() {
+        // plane of the great circle through a and b. along the edge rather than
+        // to b: a and b are nearly parallel from the center on the small nodes,
+        // and the cross product would cancel out
+        let normal = (*a - PLANET_CENTER).cross(*b - *a).normalize();
+        normal.dot(*p - *a)
+    } else {
+        (*b - *a).normalize().cross(*p - *a).dot(Vec3::Y)
+    }
+}
+
+/// sets the global planet shape and builds the matching pyramid
+pub fn build_planet(shape: PlanetShape) -> Box<dyn Piramidesc> {
+    set_planet_shape(shape);
+    match shape {
+        PlanetShape::Flat => Box::new(Piramidă::<1>::new()),
+        PlanetShape::Tetrahedron => Box::new(Piramidă::<4>::new()),
+        PlanetShape::Icosahedron => Box::new(Piramidă::<20>::new()),
+    }
+}
+
+/// base triangle from unit-sphere corners: scaled up onto the planet sphere,
+/// with the corners ordered so the face normal points away from the center.
+fn sphere_tri(points: [Vec3; 3], id: u8) -> Triangle {
+    let [v1, v2, v3] = points;
+    let points = if (v2 - v1).cross(v3 - v2).dot(v1 + v2 + v3) > 0.0 {
+        [v1, v2, v3]
+    } else {
+        [v2, v1, v3]
+    };
+    Triangle::new(
+        points.map(|v| PLANET_CENTER + v * PLANET_RADIUS),
+        TriCoord::face(id),
+    )
+}
+
 impl Piramidă<1> {
     // Flat Earth Confirmed
     pub fn new() -> Self {
@@ -35,9 +139,7 @@ impl Piramidă<1> {
         Self {
             children: [Triangle::new(
                 [v1 * PLANET_RADIUS, v2 * PLANET_RADIUS, v3 * PLANET_RADIUS],
-                0,
-                1,
-                "",
+                TriCoord::face(1),
             )
             .reverse_points()],
         }
@@ -45,7 +147,7 @@ impl Piramidă<1> {
 }
 
 impl Piramidă<4> {
-    #[allow(dead_code)]
+    /// needs `PlanetShape::Tetrahedron` set, see `build_planet`
     pub fn new() -> Self {
         let v1 = Vec3::new((8.0_f32 / 9.).sqrt(), 0., -1. / 3.);
         let v2 = Vec3::new(-(2.0_f32 / 9.).sqrt(), (2.0_f32 / 3.).sqrt(), -1. / 3.);
@@ -53,17 +155,17 @@ impl Piramidă<4> {
         let v4 = Vec3::new(0., 0., 1.);
         Self {
             children: [
-                Triangle::new([v1, v2, v3], 0, 1, "").reverse_points(),
-                Triangle::new([v1, v3, v4], 0, 2, "").reverse_points(),
-                Triangle::new([v2, v1, v4], 0, 3, "").reverse_points(),
-                Triangle::new([v3, v2, v4], 0, 4, "").reverse_points(),
+                sphere_tri([v1, v2, v3], 1),
+                sphere_tri([v1, v3, v4], 2),
+                sphere_tri([v2, v1, v4], 3),
+                sphere_tri([v3, v2, v4], 4),
             ],
         }
     }
 }
 
 impl Piramidă<20> {
-    #[allow(dead_code)]
+    /// needs `PlanetShape::Icosahedron` set, see `build_planet`
     pub fn new() -> Self {
         let v1 = Vec3::new(0., -0.525731, 0.850651);
         let v2 = Vec3::new(0.850651, 0., 0.525731);
@@ -80,27 +182,105 @@ impl Piramidă<20> {
 
         Self {
             children: [
-                Triangle::new([v2, v3, v7], 0, 1, ""),
-                Triangle::new([v2, v8, v3], 0, 2, ""),
-                Triangle::new([v4, v5, v6], 0, 3, ""),
-                Triangle::new([v5, v4, v9], 0, 4, ""),
-                Triangle::new([v7, v6, v12], 0, 5, ""),
-                Triangle::new([v6, v7, v11], 0, 6, ""),
-                Triangle::new([v10, v11, v3], 0, 7, ""),
-                Triangle::new([v11, v10, v4], 0, 8, ""),
-                Triangle::new([v8, v9, v10], 0, 9, ""),
-                Triangle::new([v9, v8, v1], 0, 10, ""),
-                Triangle::new([v12, v1, v2], 0, 11, ""),
-                Triangle::new([v1, v12, v5], 0, 12, ""),
-                Triangle::new([v7, v3, v11], 0, 13, ""),
-                Triangle::new([v2, v7, v12], 0, 14, ""),
-                Triangle::new([v4, v6, v11], 0, 15, ""),
-                Triangle::new([v6, v5, v12], 0, 16, ""),
-                Triangle::new([v3, v8, v10], 0, 17, ""),
-                Triangle::new([v8, v2, v1], 0, 18, ""),
-                Triangle::new([v4, v10, v9], 0, 19, ""),
-                Triangle::new([v5, v9, v1], 0, 20, ""),
+                sphere_tri([v2, v3, v7], 1),
+                sphere_tri([v2, v8, v3], 2),
+                sphere_tri([v4, v5, v6], 3),
+                sphere_tri([v5, v4, v9], 4),
+                sphere_tri([v7, v6, v12], 5),
+                sphere_tri([v6, v7, v11], 6),
+                sphere_tri([v10, v11, v3], 7),
+                sphere_tri([v11, v10, v4], 8),
+                sphere_tri([v8, v9, v10], 9),
+                sphere_tri([v9, v8, v1], 10),
+                sphere_tri([v12, v1, v2], 11),
+                sphere_tri([v1, v12, v5], 12),
+                sphere_tri([v7, v3, v11], 13),
+                sphere_tri([v2, v7, v12], 14),
+                sphere_tri([v4, v6, v11], 15),
+                sphere_tri([v6, v5, v12], 16),
+                sphere_tri([v3, v8, v10], 17),
+                sphere_tri([v8, v2, v1], 18),
+                sphere_tri([v4, v10, v9], 19),
+                sphere_tri([v5, v9, v1], 20),
             ],
         }
     }
 }
+
+/// nodes are found back from their centers, and neighbours share an edge,
+/// over the face edges too
+#[cfg(test)]
+fn check_locate_and_neighbours<const N: usize>(piramidă: &Piramidă<N>) {
+    use crate::terrain::up;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    // neighbours share the edge, the other way around
+    let check_neighbour = |coord: TriCoord, edge: u8| {
+        let (other, other_edge) = piramidă.neighbour(coord, edge)?;
+        let (corners, theirs) = (piramidă.corners(coord), piramidă.corners(other));
+        let (e, k) = (edge as usize, other_edge as usize);
+        assert_eq!(theirs[k], corners[(e + 1) % 3], "{coord} {other}");
+        assert_eq!(theirs[(k + 1) % 3], corners[e], "{coord} {other}");
+        Some(other)
+    };
+
+    let mut rng = StdRng::seed_from_u64(3);
+    for _ in 0..300 {
+        let mut coord = TriCoord::face(rng.gen_range(1..=N as u8));
+        for _ in 0..rng.gen_range(0..14) {
+            coord = coord.child(rng.gen_range(1..=4));
+        }
+        let corners = piramidă.corners(coord);
+        let center = (corners[0] + corners[1] + corners[2]) / 3.0;
+        assert_eq!(piramidă.locate(&center, coord.level()), coord);
+        assert_eq!(
+            piramidă.locate(&(center + up(&center) * 300.0), coord.level()),
+            coord
+        );
+        for edge in 0..3 {
+            let found = check_neighbour(coord, edge);
+            assert!(found.is_some() || !planet_shape().is_spherical());
+        }
+    }
+
+    // the corner children at corner `edge` run along that edge of the face,
+    // the node across is on the next face
+    for face in 1..=N as u8 {
+        for edge in 0..3 {
+            let mut coord = TriCoord::face(face);
+            for _ in 0..6 {
+                coord = coord.child(edge + 2);
+                let Some(other) = check_neighbour(coord, edge) else {
+                    assert!(!planet_shape().is_spherical(), "{coord} has no neighbour");
+                    continue;
+                };
+                assert_ne!(other.face_id(), face);
+                assert_eq!(other.level(), coord.level());
+            }
+        }
+    }
+}
+
+#[test]
+fn test_locate_and_neighbours_match_the_geometry() {
+    use crate::terrain::TestTerrain;
+
+    let _terrain = TestTerrain::lock(PlanetShape::Flat);
+    check_locate_and_neighbours(&Piramidă::<1>::new());
+}
+
+#[test]
+fn test_locate_and_neighbours_on_the_tetrahedron() {
+    use crate::terrain::TestTerrain;
+
+    let _terrain = TestTerrain::lock(PlanetShape::Tetrahedron);
+    check_locate_and_neighbours(&Piramidă::<4>::new());
+}
+
+#[test]
+fn test_locate_and_neighbours_on_the_icosahedron() {
+    use crate::terrain::TestTerrain;
+
+    let _terrain = TestTerrain::lock(PlanetShape::Icosahedron);
+    check_locate_and_neighbours(&Piramidă::<20>::new());
+}
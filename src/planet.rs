@@ -1,147 +1,447 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
-// use std::collections::{vec_deque, VecDeque};
 use super::menu::UiMenuState;
-use crate::piramida::Piramidesc;
-use crate::piramida::Piramidă;
+use crate::crater::{Crater, Craters};
+use crate::lod::{LodCamera, LodProbe, LodViews};
+use crate::piramida::build_planet;
 use crate::raycast::TerrainRaycastSet;
-use crate::triangle::Triangle;
+use crate::terrain::{
+    add_crater, set_craters, set_terrain_generator, water_level, TerrainSettings,
+};
+use crate::terrain_cache::{mesh_bytes, BaseMeshes, TerrainCache};
+use crate::terrain_generator::{BiomeSettings, NoiseKind, NoiseLayer, Octaves, TerrainGenerator};
+use crate::terrain_material::{detail_texture, TerrainMaterial};
+use crate::tri_coord::TriCoord;
+use crate::triangle::{vert_key, LeafVerts, Triangle, VertKey};
 
 use bevy::prelude::*;
-use bevy::render::primitives::Aabb;
+use bevy::render::primitives::{Aabb, Frustum};
+use bevy::tasks::{AsyncComputeTaskPool, Task};
+use bevy::utils::{HashMap, Instant};
 use bevy_mod_raycast::RaycastMesh;
 use bevy_rapier3d::prelude::*;
-use bevy_spatial::kdtree::KDTree3;
-use bevy_spatial::AutomaticUpdate;
-use bevy_spatial::SpatialAccess;
-use bevy_spatial::TransformMode;
-use rayon::prelude::IntoParallelRefMutIterator;
+use futures_lite::future;
+use rayon::prelude::{
+    IndexedParallelIterator, IntoParallelRefIterator, IntoParallelRefMutIterator,
+};
+use smart_default::SmartDefault;
 
 pub struct PlanetPlugin;
 impl Plugin for PlanetPlugin {
     fn build(&self, app: &mut App) {
         app.register_type::<Triangle>()
+            .init_resource::<TerrainGenerator>()
+            .register_type::<TerrainGenerator>()
+            .register_type::<Vec<NoiseLayer>>()
+            .register_type::<NoiseLayer>()
+            .register_type::<NoiseKind>()
+            .register_type::<Octaves>()
+            .register_type::<BiomeSettings>()
+            .add_plugins(MaterialPlugin::<TerrainMaterial>::default())
+            .init_resource::<TerrainLodTask>()
             .add_systems(Startup, setup_planet)
-            .add_systems(PostUpdate, update_triangle_split)
-            .add_plugins(
-                AutomaticUpdate::<TerrainSplitProbe>::new()
-                    .with_frequency(Duration::from_secs_f32(1.0 / 60.0))
-                    .with_transform(TransformMode::GlobalTransform),
-            );
+            .add_event::<TerrainCraterEvent>()
+            .add_systems(Update, rebuild_planet_on_generator_change)
+            .add_systems(Update, apply_crater_events)
+            .add_systems(PostUpdate, update_triangle_split);
     }
 }
 
-#[derive(Reflect, Component, Default)]
-pub struct TerrainSplitProbe;
+/// Asks for detailed terrain (and colliders) around the entity. Probes far from
+/// anything interesting should keep a small radius, they all add up to the budget.
+#[derive(Reflect, Component, Debug, Clone, Copy, SmartDefault)]
+pub struct TerrainSplitProbe {
+    /// meters around the probe it asks for detail in
+    #[default(1000.0)]
+    pub radius: f32,
+    /// deepest level it splits the terrain to
+    #[default(u8::MAX)]
+    pub max_level: u8,
+    /// how far the detail reaches, 2 is twice as far. when over the triangle budget,
+    /// everything loses detail, the probes below the top priority faster
+    #[default(1.0)]
+    pub priority: f32,
+}
+
+/// Digs into the terrain. The heights change right away, the meshes and
+/// colliders on the next LOD update.
+#[derive(Event, Debug, Clone)]
+pub enum TerrainCraterEvent {
+    Add(Crater),
+    /// loading a saved match, the old craters get filled back in
+    Replace(Craters),
+}
 
 /// A marker component for our shapes so we can query them separately from the ground plane
 #[derive(Component)]
 pub struct PlanetComponent;
 
+/// The sea over a base triangle, a child of its entity. Hidden where there's no water.
+#[derive(Component)]
+pub struct WaterSurface;
+
+/// on the base triangles, the entity with their `WaterSurface`
+#[derive(Component)]
+pub struct TerrainWater(pub Entity);
+
+/// Split/merge and meshing of the planet, running on the async compute pool.
+/// While it runs, the trees live in the task and the old meshes stay on screen.
+#[derive(Resource, SmartDefault)]
+pub struct TerrainLodTask {
+    task: Option<Task<LodUpdate>>,
+    /// finished meshes, uploaded a few per frame
+    uploads: VecDeque<(Entity, BaseMeshes)>,
+    /// `LodViews::detail`, goes down when over the triangle budget and back up when under
+    #[default(1.0)]
+    detail: f32,
+    /// craters added or removed since the last update, their triangles need new heights
+    dirty_craters: Vec<Crater>,
+    /// shared with the running task. a new one when the planet gets rebuilt
+    cache: Arc<Mutex<TerrainCache>>,
+}
+
+struct LodUpdate {
+    trees: Vec<(Entity, Triangle)>,
+    meshes: Vec<(Entity, BaseMeshes)>,
+    compute_ms: f32,
+}
+
 #[allow(clippy::type_complexity)]
 fn update_triangle_split(
-    // probe_query: Query<&Transform, With<TerrainSplitProbe>>,
-    probe_tree: Res<KDTree3<TerrainSplitProbe>>,
+    probe_query: Query<(&GlobalTransform, &TerrainSplitProbe)>,
+    camera_query: Query<(&Camera, &GlobalTransform, &Frustum, &Projection), With<Camera3d>>,
     mut tri_query: Query<
-        (&mut Triangle, &mut Handle<Mesh>, &mut Collider, &mut Aabb),
         (
-            With<Triangle>,
-            With<Handle<Mesh>>,
-            With<Collider>,
-            Without<TerrainSplitProbe>,
+            Entity,
+            &mut Triangle,
+            &mut Handle<Mesh>,
+            &mut Collider,
+            &mut Aabb,
+            &TerrainWater,
         ),
+        Without<TerrainSplitProbe>,
+    >,
+    mut water_query: Query<
+        (&mut Handle<Mesh>, &mut Aabb, &mut Visibility),
+        (With<WaterSurface>, Without<Triangle>),
     >,
+    mut lod: ResMut<TerrainLodTask>,
     mut ui_state: ResMut<UiMenuState>,
     mut meshes: ResMut<Assets<Mesh>>,
 ) {
-    // let probe_pos: Vec<Vec3> = probe_query.iter().map(|x| x.translation).collect();
-    // let probe_dist = |pos: &Vec3| -> f32 {
-    //     if probe_pos.is_empty() {
-    //         return 666666.0_f32;
-    //     }
-    //     probe_pos
-    //         .iter()
-    //         .map(|pp| (*pp - *pos).length())
-    //         .min_by(|a, b| a.partial_cmp(b).unwrap())
-    //         .unwrap()
-    // };
-
-    let probe_dist_from_tree = |pos: &Vec3| -> f32 {
-        if let Some(nn) = probe_tree.nearest_neighbour(*pos) {
-            nn.0.distance(*pos)
-        } else {
-            666666.0_f32
+    // put the trees back, queue up the new meshes
+    if let Some(task) = lod.task.as_mut() {
+        if let Some(update) = future::block_on(future::poll_once(task)) {
+            lod.task = None;
+            let mut triangle_count = 0;
+            let mut mesh_count = 0;
+            for (entity, tree) in update.trees {
+                triangle_count += tree.tri_count();
+                mesh_count += 1;
+                // gone if the planet got rebuilt in the meantime
+                if let Ok((_, mut tri, ..)) = tri_query.get_mut(entity) {
+                    *tri = tree;
+                }
+            }
+            lod.uploads.extend(update.meshes);
+            lod.detail = next_lod_detail(lod.detail, triangle_count, &ui_state.settings);
+            ui_state.lod_detail = lod.detail;
+            let cache = lod.cache.lock().expect("terrain cache lock");
+            ui_state.subtree_cache = cache.subtrees.stats();
+            ui_state.mesh_cache = cache.meshes.stats();
+            ui_state.triangle_count = triangle_count as f32;
+            ui_state.mesh_count = mesh_count as f32;
+            ui_state.tri_compute_ms = update.compute_ms;
         }
+    }
+
+    for _ in 0..ui_state.settings.MESH_UPLOADS_PER_FRAME {
+        let Some((entity, (mesh, new_collider, water_mesh))) = lod.uploads.pop_front() else {
+            break;
+        };
+        let Ok((_, _, mut mesh_handle, mut collider, mut aabb, water)) = tri_query.get_mut(entity)
+        else {
+            continue;
+        };
+        *aabb = mesh.compute_aabb().expect("tri mesh returned empty aabb");
+        *collider = new_collider;
+        let old_mesh_handle = mesh_handle.clone();
+        *mesh_handle = meshes.add(mesh);
+        meshes.remove(old_mesh_handle);
+
+        if let Ok((mut water_handle, mut water_aabb, mut visibility)) = water_query.get_mut(water.0)
+        {
+            let old_water_handle = water_handle.clone();
+            (*water_handle, *water_aabb, *visibility) = water_components(water_mesh, &mut meshes);
+            meshes.remove(old_water_handle);
+        }
+    }
+
+    // start the next update once the last one is on screen, so the trees match the meshes
+    if lod.task.is_some() || !lod.uploads.is_empty() {
+        return;
+    }
+    let views = LodViews {
+        cameras: camera_query
+            .iter()
+            .filter(|(camera, ..)| camera.is_active)
+            .filter_map(|(camera, transform, frustum, projection)| {
+                let viewport_size = camera.logical_viewport_size()?;
+                Some(LodCamera::new(
+                    transform,
+                    frustum,
+                    projection,
+                    viewport_size,
+                ))
+            })
+            .collect(),
+        probes: probe_query
+            .iter()
+            .map(|(tr, probe)| LodProbe {
+                position: tr.translation(),
+                radius: probe.radius,
+                max_level: probe.max_level,
+                priority: probe.priority,
+            })
+            .collect(),
+        detail: lod.detail,
+    };
+    let craters = std::mem::take(&mut lod.dirty_craters);
+    let cache = lod.cache.clone();
+    let settings = ui_state.settings;
+    let placeholder = Triangle::default();
+    let trees: Vec<(Entity, Triangle)> = tri_query
+        .iter_mut()
+        .map(|(entity, mut tri, ..)| (entity, std::mem::replace(&mut *tri, placeholder.clone())))
+        .collect();
+    if trees.is_empty() {
+        return;
+    }
+    lod.task = Some(
+        AsyncComputeTaskPool::get()
+            .spawn(async move { update_lod(trees, &views, &craters, &cache, &settings) }),
+    );
+}
+
+fn apply_crater_events(
+    mut events: EventReader<TerrainCraterEvent>,
+    mut lod: ResMut<TerrainLodTask>,
+) {
+    for event in events.iter() {
+        match event {
+            TerrainCraterEvent::Add(crater) => {
+                add_crater(*crater);
+                lod.dirty_craters.push(*crater);
+            }
+            TerrainCraterEvent::Replace(craters) => {
+                let old = set_craters(craters.clone());
+                lod.dirty_craters.extend(old.iter().chain(craters.iter()));
+            }
+        }
+    }
+}
+
+/// the detail never goes below this, however far over the budget
+const MIN_LOD_DETAIL: f32 = 0.05;
+/// detail multiplier per update while comfortably under the budget
+const LOD_DETAIL_RECOVERY: f32 = 1.05;
+
+/// the detail for the next update. the triangle count goes roughly with the square of it
+fn next_lod_detail(detail: f32, triangle_count: usize, settings: &TerrainSettings) -> f32 {
+    let budget = settings.TRIANGLE_BUDGET as f32;
+    let count = triangle_count as f32;
+    let detail = if count > budget {
+        detail * (budget / count).sqrt()
+    } else if count < budget * (1.0 - settings.SPLIT_LAZY_COEF) {
+        detail * LOD_DETAIL_RECOVERY
+    } else {
+        detail
     };
-    // serial version
-    // for (mut tri, mut mesh_handle) in tri_query.iter_mut() {
-    //     let changed = tri.as_mut().update_split(&player_pos);
-    //     if changed {
-    //         // mesh.
-    //         // info!("changing mesh! count={}", tri.tri_count());
-    //         let new_mesh = meshes.add(tri.generate_mesh());
-    //         let old_mesh = mesh_handle.clone();
-    //         *mesh_handle = new_mesh;
-    //         meshes.remove(old_mesh);
-    //     }
-    // }
-
-    // Parallel version
-    // Extract query components into a vector, since the Query<> dont work with Rayone, and
-    // the Query<> .par_for_each() and .par_for_each_mut() don't work with
-    let mut query_args: Vec<_> = tri_query.iter_mut().collect();
+    detail.clamp(MIN_LOD_DETAIL, 1.0)
+}
+
+/// splits/merges all the trees, then rebuilds the meshes that changed
+fn update_lod(
+    mut trees: Vec<(Entity, Triangle)>,
+    views: &LodViews,
+    craters: &[Crater],
+    cache: &Mutex<TerrainCache>,
+    settings: &TerrainSettings,
+) -> LodUpdate {
+    let start = Instant::now();
+    let water_level = water_level();
 
     use rayon::iter::ParallelIterator;
-    let query_results: Vec<Option<_>> = query_args
+    let dug: Vec<bool> = trees
         .par_iter_mut()
-        .map(|(tri, mesh_handle, collider, aabb)| {
-            let changed = tri.update_split(&probe_dist_from_tree, &ui_state.settings);
-            if changed {
-                let (mesh, new_collider) = tri.generate_mesh(&ui_state.settings);
-                *collider.as_mut() = new_collider;
-                **aabb = mesh.compute_aabb().expect("tri mesh returned empty aabb");
-                Some((mesh_handle, mesh))
-            } else {
-                None
-            }
-        })
+        .map(|(_, tri)| tri.apply_craters(craters))
         .collect();
-    query_results.into_iter().for_each(|result| {
-        if let Some((mesh_handle, new_mesh)) = result {
-            let old_mesh_handle = (*mesh_handle).clone();
-            let new_mesh_handle = meshes.add(new_mesh);
-            **mesh_handle = new_mesh_handle;
-            meshes.remove(old_mesh_handle);
+    {
+        let dug_trees: Vec<TriCoord> = trees
+            .iter()
+            .zip(dug.iter())
+            .filter(|(_, dug)| **dug)
+            .map(|((_, tri), _)| tri.coord())
+            .collect();
+        let mut cache = cache.lock().expect("terrain cache lock");
+        cache.set_limits(settings);
+        cache.invalidate_craters(craters, &dug_trees);
+    }
+    let dirty: Vec<bool> = trees
+        .par_iter_mut()
+        .zip(dug.par_iter())
+        .map(|((_, tri), dug)| tri.update_split(views, cache, settings) || *dug)
+        .collect();
+
+    let mut meshes = vec![];
+    if dirty.iter().any(|x| *x) {
+        // a changed base triangle can add or remove vertices on the edges of its neighbours,
+        // so the neighbours (anything sharing a corner) get re-stitched too.
+        let mut tris_at_corner = HashMap::<VertKey, Vec<usize>>::new();
+        let mut leaf_verts = LeafVerts::default();
+        for (i, (_, tri)) in trees.iter().enumerate() {
+            for corner in tri.base_corners().iter() {
+                tris_at_corner.entry(vert_key(corner)).or_default().push(i);
+            }
+            tri.collect_leaf_verts(&mut leaf_verts);
+        }
+        let mut needs_mesh = dirty.clone();
+        for (i, (_, tri)) in trees.iter().enumerate() {
+            if !dirty[i] {
+                continue;
+            }
+            for corner in tri.base_corners().iter() {
+                for neighbour in tris_at_corner[&vert_key(corner)].iter() {
+                    needs_mesh[*neighbour] = true;
+                }
+            }
         }
-    });
 
-    let mut triangle_count = 0;
-    let mut mesh_count = 0;
-    for (triangle, _, _, _) in query_args {
-        triangle_count += triangle.tri_count();
-        mesh_count += 1;
+        meshes = trees
+            .par_iter()
+            .zip(needs_mesh.par_iter())
+            .filter(|(_, needs_mesh)| **needs_mesh)
+            .map(|((entity, tri), _)| {
+                let key = (tri.coord(), tri.mesh_signature(&leaf_verts));
+                let cached = cache
+                    .lock()
+                    .expect("terrain cache lock")
+                    .meshes
+                    .get(&key)
+                    .cloned();
+                let meshes = cached.unwrap_or_else(|| {
+                    let (mesh, collider) = tri.generate_mesh(settings, &leaf_verts);
+                    let water = tri.generate_water_mesh(&leaf_verts, water_level);
+                    let bytes = mesh_bytes(&mesh) + water.as_ref().map_or(0, mesh_bytes);
+                    let meshes = (mesh, collider, water);
+                    cache.lock().expect("terrain cache lock").meshes.insert(
+                        key,
+                        meshes.clone(),
+                        bytes,
+                    );
+                    meshes
+                });
+                (*entity, meshes)
+            })
+            .collect();
     }
 
-    ui_state.triangle_count = triangle_count as f32;
-    ui_state.mesh_count = mesh_count as f32;
+    LodUpdate {
+        trees,
+        meshes,
+        compute_ms: start.elapsed().as_secs_f32() * 1000.0,
+    }
 }
 
 fn setup_planet(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut images: ResMut<Assets<Image>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut materials: ResMut<Assets<TerrainMaterial>>,
+    mut water_materials: ResMut<Assets<StandardMaterial>>,
     ui_state: ResMut<UiMenuState>,
+    generator: Res<TerrainGenerator>,
 ) {
     warn!("TRIANGLE/PYRAMID SETUP SYSTEM...");
+    set_terrain_generator(generator.clone());
+    spawn_planet(
+        &mut commands,
+        &mut meshes,
+        &mut images,
+        &mut materials,
+        &mut water_materials,
+        &ui_state.settings,
+    );
+}
+
+/// wait this long after the last change, so dragging a slider doesn't rebuild every frame
+const GENERATOR_REBUILD_DELAY: Duration = Duration::from_millis(500);
+
+#[allow(clippy::too_many_arguments)]
+fn rebuild_planet_on_generator_change(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<TerrainMaterial>>,
+    mut water_materials: ResMut<Assets<StandardMaterial>>,
+    ui_state: Res<UiMenuState>,
+    generator: Res<TerrainGenerator>,
+    planet_query: Query<Entity, With<PlanetComponent>>,
+    mut lod: ResMut<TerrainLodTask>,
+    time: Res<Time>,
+    mut changed_at: Local<Option<Duration>>,
+) {
+    if generator.is_changed() && !generator.is_added() {
+        *changed_at = Some(time.elapsed());
+    }
+    let Some(at) = *changed_at else {
+        return;
+    };
+    if time.elapsed() < at + GENERATOR_REBUILD_DELAY {
+        return;
+    }
+    *changed_at = None;
 
-    let debug_material = materials.add(StandardMaterial {
-        base_color_texture: Some(images.add(uv_debug_texture())),
+    warn!("TERRAIN GENERATOR CHANGED, REBUILDING THE PLANET...");
+    set_terrain_generator(generator.clone());
+    // everything in flight is for the old planet: the running task (dropping it
+    // cancels it), the meshes waiting for upload, the crater updates and the
+    // cached heights. the detail goes back up for the new one
+    *lod = default();
+    for planet in planet_query.iter() {
+        commands.entity(planet).despawn_recursive();
+    }
+    spawn_planet(
+        &mut commands,
+        &mut meshes,
+        &mut images,
+        &mut materials,
+        &mut water_materials,
+        &ui_state.settings,
+    );
+}
+
+fn spawn_planet(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    images: &mut Assets<Image>,
+    materials: &mut Assets<TerrainMaterial>,
+    water_materials: &mut Assets<StandardMaterial>,
+    settings: &TerrainSettings,
+) {
+    let material = materials.add(TerrainMaterial::new(images.add(detail_texture())));
+    let water_material = water_materials.add(StandardMaterial {
+        base_color: Color::rgba(0.05, 0.22, 0.35, 0.75),
+        perceptual_roughness: 0.08,
+        reflectance: 0.6,
+        alpha_mode: AlphaMode::Blend,
         ..default()
     });
+    let water_level = water_level();
 
-    let mut piramidă = Box::new(Piramidă::<1>::new());
-
+    let mut piramidă = build_planet(settings.PLANET_SHAPE);
     let tris = piramidă.as_mut().base_tris();
     let planet_ent = commands
         .spawn((
@@ -151,17 +451,37 @@ fn setup_planet(
         ))
         .id();
 
+    let mut leaf_verts = LeafVerts::default();
+    for tri in tris.iter() {
+        tri.collect_leaf_verts(&mut leaf_verts);
+    }
+
     for (_tri_idx, tri) in tris.into_iter().enumerate() {
-        let (mesh, collider) = tri.generate_mesh(&ui_state.settings);
+        let (mesh, collider) = tri.generate_mesh(settings, &leaf_verts);
         let mesh_asset = meshes.add(mesh);
+        let (water_mesh, water_aabb, water_visibility) =
+            water_components(tri.generate_water_mesh(&leaf_verts, water_level), meshes);
+        let water_ent = commands
+            .spawn((
+                WaterSurface,
+                PbrBundle {
+                    mesh: water_mesh,
+                    material: water_material.clone(),
+                    visibility: water_visibility,
+                    ..default()
+                },
+                water_aabb,
+                Name::new("Water Surface"),
+            ))
+            .id();
         let mut name = "Base Planet Triangle ".to_owned();
-        name.push_str(tri.coord());
+        name.push_str(&tri.coord().to_string());
 
         let tri_ent = commands
             .spawn((
-                PbrBundle {
+                MaterialMeshBundle {
                     mesh: mesh_asset,
-                    material: debug_material.clone(),
+                    material: material.clone(),
                     ..default()
                 },
                 tri,
@@ -170,42 +490,25 @@ fn setup_planet(
                 Ccd::enabled(),
                 // PickableBundle::default(),
                 RaycastMesh::<TerrainRaycastSet>::default(),
+                TerrainWater(water_ent),
                 Name::new(name),
             ))
             .id();
         commands.entity(tri_ent).set_parent(planet_ent);
+        commands.entity(water_ent).set_parent(tri_ent);
     }
 }
 
-// Creates a colorful test pattern
-fn uv_debug_texture() -> Image {
-    use bevy::{
-        // core_pipeline::bloom::BloomSettings,
-        prelude::*,
-        render::render_resource::{Extent3d, TextureDimension, TextureFormat},
-    };
-    const TEXTURE_SIZE: usize = 8;
-
-    let mut palette: [u8; 32] = [
-        255, 102, 159, 255, 255, 159, 102, 255, 236, 255, 102, 255, 121, 255, 102, 255, 102, 255,
-        198, 255, 102, 198, 255, 255, 121, 102, 255, 255, 236, 102, 255, 255,
-    ];
-
-    let mut texture_data = [0; TEXTURE_SIZE * TEXTURE_SIZE * 4];
-    for y in 0..TEXTURE_SIZE {
-        let offset = TEXTURE_SIZE * y * 4;
-        texture_data[offset..(offset + TEXTURE_SIZE * 4)].copy_from_slice(&palette);
-        palette.rotate_right(4);
+/// mesh, bounds and visibility of a `WaterSurface`, hidden when there's no water
+fn water_components(
+    mesh: Option<Mesh>,
+    meshes: &mut Assets<Mesh>,
+) -> (Handle<Mesh>, Aabb, Visibility) {
+    match mesh {
+        Some(mesh) => {
+            let aabb = mesh.compute_aabb().expect("water mesh returned empty aabb");
+            (meshes.add(mesh), aabb, Visibility::Inherited)
+        }
+        None => (Handle::default(), Aabb::default(), Visibility::Hidden),
     }
-
-    Image::new_fill(
-        Extent3d {
-            width: TEXTURE_SIZE as u32,
-            height: TEXTURE_SIZE as u32,
-            depth_or_array_layers: 1,
-        },
-        TextureDimension::D2,
-        &texture_data,
-        TextureFormat::Rgba8UnormSrgb,
-    )
 }
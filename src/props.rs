@@ -0,0 +1,868 @@
+use bevy::prelude::*;
+use bevy::render::primitives::Sphere;
+use bevy::utils::{HashMap, HashSet};
+use bevy_rapier3d::prelude::*;
+
+use crate::assets::GameSceneAssets;
+use crate::lod::{LodProbe, LodViews};
+use crate::menu::UiMenuState;
+use crate::planet::{PlanetComponent, TerrainSplitProbe};
+use crate::terrain::{
+    height_and_gradient, project_to_surface, terrain_generator, up, PLANET_MAX_PLAY_RADIUS,
+};
+use crate::terrain_generator::Biome;
+
+pub struct PropsPlugin;
+impl Plugin for PropsPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Prop>()
+            .register_type::<Wreck>()
+            .init_resource::<PropCells>()
+            .add_systems(PostUpdate, stream_props);
+    }
+}
+
+/// One of the models props get picked from, with where it can stand.
+#[derive(Debug, Clone, Copy)]
+pub struct PropDef {
+    /// in `assets/3d/ORIGINAL`
+    pub file: &'static str,
+    pub biomes: &'static [Biome],
+    /// radians, the steepest ground it stands on
+    pub max_slope: f32,
+    /// how often it gets picked, against the other props
+    pub weight: f32,
+    pub scale: f32,
+    /// min and max corners of the model, in its own units
+    pub bounds: [[f32; 3]; 2],
+    /// damage it takes to bring it down
+    pub hit_points: f32,
+    /// min and max corners of the `assets/3d/3D_FRACTURE` model, which sits
+    /// elsewhere than the original, where the `ANGLE_DISSOLVE` one sits too.
+    /// None if there is no fractured model, then nothing brings it down.
+    pub fracture_bounds: Option<[[f32; 3]; 2]>,
+}
+
+impl PropDef {
+    /// key in `GameSceneAssets::scenes`
+    pub fn scene_key(&self) -> String {
+        format!("3d/ORIGINAL/{}", self.file)
+    }
+
+    /// half size of the box around the model, in meters
+    pub fn half_extents(&self) -> Vec3 {
+        let [min, max] = self.bounds;
+        (Vec3::from(max) - Vec3::from(min)) * self.scale / 2.0
+    }
+
+    /// key of the fractured model in `GameSceneAssets::scenes`
+    pub fn fracture_key(&self) -> String {
+        format!("3d/3D_FRACTURE/{}", self.file)
+    }
+
+    /// key of the damaged model in `GameSceneAssets::scenes`, it lines up with
+    /// the fractured one
+    pub fn damaged_key(&self) -> String {
+        format!("3d/ANGLE_DISSOLVE/{}", self.file)
+    }
+
+    /// moves the model so it stands on the origin, centered on it
+    pub fn model_transform(&self) -> Transform {
+        self.fit_transform(self.bounds)
+    }
+
+    /// the fractured model where the original one stands
+    pub fn fracture_transform(&self) -> Option<Transform> {
+        self.fracture_bounds
+            .map(|bounds| self.fit_transform(bounds))
+    }
+
+    fn fit_transform(&self, [min, max]: [[f32; 3]; 2]) -> Transform {
+        let center = (Vec3::from(min) + Vec3::from(max)) / 2.0;
+        Transform::from_translation(-Vec3::new(center.x, min[1], center.z) * self.scale)
+            .with_scale(Vec3::splat(self.scale))
+    }
+
+    /// Meters from `pos` to the box around the prop standing at `prop_tr`,
+    /// 0 inside it. For the splash damage.
+    pub fn distance_to(&self, prop_tr: &Transform, pos: Vec3) -> f32 {
+        let half = self.half_extents();
+        let local = prop_tr.rotation.inverse() * (pos - prop_tr.translation) - Vec3::Y * half.y;
+        (local.abs() - half).max(Vec3::ZERO).length()
+    }
+
+    /// meters around its position no other prop can stand
+    fn footprint(&self) -> f32 {
+        let half = self.half_extents();
+        Vec2::new(half.x, half.z).length()
+    }
+}
+
+const PLAINS_AND_HILLS: &[Biome] = &[Biome::Plains, Biome::Hills];
+const PLAINS: &[Biome] = &[Biome::Plains];
+const HILLS: &[Biome] = &[Biome::Hills];
+
+/// The models the scatter uses. Bounds read from the glb files, scales eyeballed
+/// against the tanks.
+pub const PROP_LIBRARY: &[PropDef] = &[
+    PropDef {
+        file: "Trees.glb",
+        biomes: PLAINS_AND_HILLS,
+        max_slope: 0.5,
+        weight: 12.0,
+        scale: 2.0,
+        bounds: [[1.27, 0.0, -0.73], [2.73, 3.71, 0.73]],
+        hit_points: 40.0,
+        fracture_bounds: Some([[-0.66, -0.56, -0.69], [0.66, 3.1, 0.7]]),
+    },
+    PropDef {
+        file: "Trees(1).glb",
+        biomes: PLAINS_AND_HILLS,
+        max_slope: 0.5,
+        weight: 12.0,
+        scale: 2.0,
+        bounds: [[1.06, 0.31, -0.66], [2.94, 3.25, 1.21]],
+        hit_points: 40.0,
+        fracture_bounds: Some([[-0.92, -0.25, -0.64], [0.92, 2.68, 1.19]]),
+    },
+    PropDef {
+        file: "Trees(2).glb",
+        biomes: PLAINS_AND_HILLS,
+        max_slope: 0.5,
+        weight: 12.0,
+        scale: 2.0,
+        bounds: [[1.96, 0.24, -1.09], [3.85, 3.6, 0.84]],
+        hit_points: 40.0,
+        fracture_bounds: Some([[-0.91, -0.3, -0.94], [0.93, 3.02, 0.95]]),
+    },
+    PropDef {
+        file: "Medieval Houses.glb",
+        biomes: PLAINS,
+        max_slope: 0.2,
+        weight: 1.0,
+        scale: 0.5,
+        bounds: [[-5.2, 0.0, 6.13], [7.21, 17.95, 21.53]],
+        hit_points: 250.0,
+        fracture_bounds: Some([[-5.13, -0.09, -7.65], [6.13, 17.54, 7.65]]),
+    },
+    PropDef {
+        file: "Medieval Houses(1).glb",
+        biomes: PLAINS,
+        max_slope: 0.2,
+        weight: 1.0,
+        scale: 0.5,
+        bounds: [[-4.16, -0.04, 9.54], [6.33, 10.29, 18.11]],
+        hit_points: 250.0,
+        fracture_bounds: Some([[-4.1, -0.14, -4.22], [6.21, 10.02, 4.23]]),
+    },
+    PropDef {
+        file: "Medieval Houses(2).glb",
+        biomes: PLAINS,
+        max_slope: 0.2,
+        weight: 1.0,
+        scale: 0.5,
+        bounds: [[-5.72, -0.03, 8.05], [7.17, 12.19, 19.6]],
+        hit_points: 250.0,
+        fracture_bounds: Some([[-5.64, -0.17, -5.67], [6.97, 11.97, 5.67]]),
+    },
+    PropDef {
+        file: "Medieval Houses(3).glb",
+        biomes: PLAINS,
+        max_slope: 0.2,
+        weight: 1.0,
+        scale: 0.5,
+        bounds: [[-4.64, 0.04, 9.19], [4.64, 15.8, 19.99]],
+        hit_points: 250.0,
+        fracture_bounds: Some([[-4.4, -0.05, -4.57], [4.47, 15.53, 6.13]]),
+    },
+    PropDef {
+        file: "Modern Houses.glb",
+        biomes: PLAINS,
+        max_slope: 0.2,
+        weight: 1.0,
+        scale: 0.5,
+        bounds: [[-7.44, 0.0, -9.0], [7.44, 9.44, 7.0]],
+        hit_points: 250.0,
+        fracture_bounds: Some([[-7.42, 0.0, -8.0], [7.41, 9.41, 8.0]]),
+    },
+    PropDef {
+        file: "Modern Houses(1).glb",
+        biomes: PLAINS,
+        max_slope: 0.2,
+        weight: 1.0,
+        scale: 0.5,
+        bounds: [[-4.75, 0.0, -9.0], [2.78, 9.93, 7.0]],
+        hit_points: 250.0,
+        fracture_bounds: Some([[-4.74, 0.0, -8.0], [2.76, 9.84, 8.0]]),
+    },
+    PropDef {
+        file: "Desert Houses.glb",
+        biomes: HILLS,
+        max_slope: 0.25,
+        weight: 1.0,
+        scale: 0.5,
+        bounds: [[-3.25, -0.01, -5.77], [6.93, 6.83, 3.87]],
+        hit_points: 200.0,
+        fracture_bounds: Some([[-3.2, -0.2, -6.75], [6.88, 6.63, 2.82]]),
+    },
+    PropDef {
+        file: "Desert Houses(1).glb",
+        biomes: HILLS,
+        max_slope: 0.25,
+        weight: 1.0,
+        scale: 0.5,
+        bounds: [[-2.09, -0.02, -2.64], [3.75, 8.14, 7.56]],
+        hit_points: 200.0,
+        fracture_bounds: None,
+    },
+    PropDef {
+        file: "Desert Houses(2).glb",
+        biomes: HILLS,
+        max_slope: 0.25,
+        weight: 1.0,
+        scale: 0.5,
+        bounds: [[-2.26, -0.04, -2.57], [2.26, 9.64, 8.27]],
+        hit_points: 200.0,
+        fracture_bounds: Some([[-2.23, -0.23, -3.54], [2.23, 9.41, 7.24]]),
+    },
+    PropDef {
+        file: "Medieval Walls.glb",
+        biomes: PLAINS_AND_HILLS,
+        max_slope: 0.15,
+        weight: 0.5,
+        scale: 0.25,
+        bounds: [[-19.4, 0.0, -11.71], [-8.92, 15.4, 26.36]],
+        hit_points: 400.0,
+        fracture_bounds: Some([[-4.97, -0.01, -33.38], [5.15, 15.27, 4.41]]),
+    },
+    PropDef {
+        file: "Medieval Walls(3).glb",
+        biomes: PLAINS_AND_HILLS,
+        max_slope: 0.15,
+        weight: 0.5,
+        scale: 0.25,
+        bounds: [[-19.33, 0.0, -22.41], [-8.92, 14.28, 25.55]],
+        hit_points: 400.0,
+        fracture_bounds: Some([[-4.85, -0.02, -44.13], [5.01, 14.13, 3.67]]),
+    },
+    PropDef {
+        file: "Horizontal Fence.glb",
+        biomes: PLAINS,
+        max_slope: 0.3,
+        weight: 2.0,
+        scale: 1.5,
+        bounds: [[1.62, 0.0, -1.18], [6.07, 0.65, -1.08]],
+        hit_points: 30.0,
+        fracture_bounds: Some([[-1.12, 0.03, -0.01], [3.28, 0.62, 0.06]]),
+    },
+    PropDef {
+        file: "Horizontal Fence(1).glb",
+        biomes: PLAINS,
+        max_slope: 0.3,
+        weight: 2.0,
+        scale: 1.5,
+        bounds: [[2.17, 0.0, -1.17], [6.04, 0.61, -1.1]],
+        hit_points: 30.0,
+        fracture_bounds: Some([[-0.58, 0.0, 0.0], [3.24, 0.58, 0.01]]),
+    },
+    PropDef {
+        file: "Silos.glb",
+        biomes: PLAINS,
+        max_slope: 0.15,
+        weight: 1.0,
+        scale: 1.0,
+        bounds: [[-2.67, 0.0, -2.15], [-0.64, 5.62, -0.68]],
+        hit_points: 150.0,
+        fracture_bounds: Some([[-1.25, 0.0, -0.71], [0.74, 5.55, 0.71]]),
+    },
+    PropDef {
+        file: "Silos(1).glb",
+        biomes: PLAINS,
+        max_slope: 0.15,
+        weight: 1.0,
+        scale: 1.0,
+        bounds: [[-2.43, -0.01, -2.43], [-0.4, 6.51, -0.4]],
+        hit_points: 150.0,
+        fracture_bounds: Some([[-0.98, 0.0, -0.98], [0.98, 6.46, 0.98]]),
+    },
+    PropDef {
+        file: "Windmills.glb",
+        biomes: PLAINS_AND_HILLS,
+        max_slope: 0.2,
+        weight: 0.5,
+        scale: 0.6,
+        bounds: [[-7.14, 0.0, -7.36], [0.69, 16.96, 7.36]],
+        hit_points: 200.0,
+        fracture_bounds: Some([[-5.1, 0.0, -7.23], [2.55, 16.76, 7.22]]),
+    },
+    PropDef {
+        file: "Water Towers.glb",
+        biomes: PLAINS,
+        max_slope: 0.2,
+        weight: 0.5,
+        scale: 0.6,
+        bounds: [[-2.94, 0.0, -5.81], [3.65, 13.52, 0.45]],
+        hit_points: 150.0,
+        fracture_bounds: Some([[-3.39, -0.49, -3.12], [3.05, 13.01, 2.92]]),
+    },
+    PropDef {
+        file: "Storage Buildings.glb",
+        biomes: PLAINS,
+        max_slope: 0.15,
+        weight: 0.5,
+        scale: 0.5,
+        bounds: [[-14.62, 0.0, -4.85], [7.99, 6.03, 9.53]],
+        hit_points: 300.0,
+        fracture_bounds: Some([[-13.35, -0.01, -2.03], [9.05, 5.95, 12.22]]),
+    },
+];
+
+/// side of the square cells props get scattered and streamed by, in meters
+const PROP_CELL_SIZE: f32 = 128.0;
+/// props tried per cell, the ones on the wrong ground or on top of others are dropped
+const PROP_CANDIDATES_PER_CELL: usize = 16;
+/// new cells filled per frame, the rest wait for the next frames
+const PROP_CELLS_PER_FRAME: usize = 4;
+
+/// splitmix64, so a seed scatters the same props on every machine
+struct CellRng(u64);
+
+impl CellRng {
+    fn new(seed: u32, cell: IVec2) -> Self {
+        Self(
+            (seed as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+                ^ (cell.x as u32 as u64).wrapping_mul(0xbf58_476d_1ce4_e5b9)
+                ^ (cell.y as u32 as u64).wrapping_mul(0x94d0_49bb_1331_11eb),
+        )
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// 0..1
+    fn next_f32(&mut self) -> f32 {
+        (self.next() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// index into `PROP_LIBRARY`, by weight
+    fn next_def(&mut self) -> usize {
+        let total: f32 = PROP_LIBRARY.iter().map(|def| def.weight).sum();
+        let mut pick = self.next_f32() * total;
+        for (i, def) in PROP_LIBRARY.iter().enumerate() {
+            if pick < def.weight {
+                return i;
+            }
+            pick -= def.weight;
+        }
+        PROP_LIBRARY.len() - 1
+    }
+}
+
+/// cell the position falls in, from above
+fn cell_of(pos: &Vec3) -> IVec2 {
+    let surface = project_to_surface(pos);
+    (Vec2::new(surface.x, surface.z) / PROP_CELL_SIZE)
+        .floor()
+        .as_ivec2()
+}
+
+/// the point on the zero-height surface seen at `x`, `z` from above
+fn surface_at(x: f32, z: f32) -> Vec3 {
+    project_to_surface(&Vec3::new(x, 0.0, z))
+}
+
+/// A prop put on the terrain by the scatter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PropPlacement {
+    /// index into `PROP_LIBRARY`
+    pub def: usize,
+    /// on the ground
+    pub position: Vec3,
+    /// standing along `up()`, turned around it
+    pub rotation: Quat,
+}
+
+/// The props of a cell. Always the same for the same seed, cell and terrain.
+/// Empty outside the play area.
+pub fn scatter_cell(seed: u32, cell: IVec2) -> Vec<PropPlacement> {
+    let origin = cell.as_vec2() * PROP_CELL_SIZE;
+    let center = origin + Vec2::splat(PROP_CELL_SIZE / 2.0);
+    if center.length() > PLANET_MAX_PLAY_RADIUS {
+        return vec![];
+    }
+    let generator = terrain_generator();
+    let mut rng = CellRng::new(seed, cell);
+    let mut placed: Vec<PropPlacement> = vec![];
+    for _ in 0..PROP_CANDIDATES_PER_CELL {
+        // all drawn up front, so a rejected candidate doesn't shift the next ones
+        let xz = origin + Vec2::new(rng.next_f32(), rng.next_f32()) * PROP_CELL_SIZE;
+        let angle = rng.next_f32() * std::f32::consts::TAU;
+        let def_index = rng.next_def();
+        let def = &PROP_LIBRARY[def_index];
+
+        let surface = surface_at(xz.x, xz.y);
+        let (height, gradient) = height_and_gradient(&surface);
+        let slope = gradient.length().atan();
+        if slope > def.max_slope || !def.biomes.contains(&generator.biome(height, slope)) {
+            continue;
+        }
+        let position = surface + up(&surface) * height;
+        let crowded = placed.iter().any(|other| {
+            other.position.distance(position)
+                < PROP_LIBRARY[other.def].footprint() + def.footprint()
+        });
+        if crowded {
+            continue;
+        }
+        placed.push(PropPlacement {
+            def: def_index,
+            position,
+            rotation: Quat::from_rotation_arc(Vec3::Y, up(&surface)) * Quat::from_rotation_y(angle),
+        });
+    }
+    placed
+}
+
+/// The box and rubble colliders of the props, for the aim to steer clear of.
+#[derive(Reflect, Component, Debug)]
+pub struct PropCollider;
+
+/// A scattered prop, the root of its model and collider.
+#[derive(Reflect, Component, Debug)]
+pub struct Prop {
+    /// index into `PROP_LIBRARY`
+    pub def: usize,
+    /// where `scatter_cell()` put it
+    pub cell: IVec2,
+    pub index: usize,
+    /// down to 0 it is a wreck
+    pub hit_points: f32,
+}
+
+/// part of its hit points a prop has left when it starts to look damaged
+const DAMAGED_HIT_POINTS: f32 = 0.5;
+
+impl Prop {
+    /// still standing, but showing its `ANGLE_DISSOLVE` model
+    pub fn is_damaged(&self) -> bool {
+        self.hit_points < PROP_LIBRARY[self.def].hit_points * DAMAGED_HIT_POINTS
+    }
+}
+
+/// The props on screen, by cell. Cells come and go with the terrain split probes
+/// and the cameras, like the terrain detail.
+#[derive(Resource, Default)]
+pub struct PropCells {
+    cells: HashMap<IVec2, Entity>,
+    /// props brought down, by cell and index. They come back as wrecks.
+    destroyed: HashSet<(IVec2, usize)>,
+    /// the props are for this seed and planet, a new one throws them all away
+    seed: u32,
+    planet: Option<Entity>,
+}
+
+fn stream_props(
+    mut commands: Commands,
+    probe_query: Query<(&GlobalTransform, &TerrainSplitProbe)>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<Camera3d>>,
+    planet_query: Query<Entity, With<PlanetComponent>>,
+    ui_state: Res<UiMenuState>,
+    scene_assets: Res<GameSceneAssets>,
+    mut props: ResMut<PropCells>,
+) {
+    let settings = &ui_state.settings;
+    let planet = planet_query.iter().next();
+    if planet != props.planet || settings.PROPS_SEED != props.seed {
+        for (_, cell_ent) in props.cells.drain() {
+            commands.entity(cell_ent).despawn_recursive();
+        }
+        props.destroyed.clear();
+        props.planet = planet;
+        props.seed = settings.PROPS_SEED;
+    }
+    if planet.is_none() {
+        return;
+    }
+
+    // the cameras ask for props like a probe of the props radius would
+    let views = LodViews {
+        probes: probe_query
+            .iter()
+            .map(|(tr, probe)| LodProbe {
+                position: tr.translation(),
+                radius: probe.radius.min(settings.PROPS_RADIUS),
+                max_level: u8::MAX,
+                priority: probe.priority,
+            })
+            .chain(
+                camera_query
+                    .iter()
+                    .filter(|(camera, _)| camera.is_active)
+                    .map(|(_, tr)| LodProbe {
+                        position: tr.translation(),
+                        radius: settings.PROPS_RADIUS,
+                        max_level: u8::MAX,
+                        priority: 1.0,
+                    }),
+            )
+            .collect(),
+        ..default()
+    };
+    let cell_sphere = |cell: IVec2| {
+        let center = (cell.as_vec2() + Vec2::splat(0.5)) * PROP_CELL_SIZE;
+        Sphere {
+            center: surface_at(center.x, center.y).into(),
+            radius: PROP_CELL_SIZE * std::f32::consts::FRAC_1_SQRT_2,
+        }
+    };
+    // same hysteresis as the terrain splits
+    let lazy = 1.0 + settings.SPLIT_LAZY_COEF;
+    let cell_dist = |cell: IVec2, reach: f32| views.probe_dist(&cell_sphere(cell), 0, reach);
+
+    props.cells.retain(|cell, cell_ent| {
+        let keep = cell_dist(*cell, lazy) < settings.PROPS_RADIUS * lazy;
+        if !keep {
+            commands.entity(*cell_ent).despawn_recursive();
+        }
+        keep
+    });
+
+    let mut wanted: Vec<(f32, IVec2)> = vec![];
+    for probe in views.probes.iter() {
+        let reach = probe.radius.min(settings.PROPS_RADIUS * probe.priority);
+        let steps = (reach / PROP_CELL_SIZE).ceil() as i32 + 1;
+        let around = cell_of(&probe.position);
+        for x in -steps..=steps {
+            for z in -steps..=steps {
+                let cell = around + IVec2::new(x, z);
+                if props.cells.contains_key(&cell) {
+                    continue;
+                }
+                let dist = cell_dist(cell, 1.0);
+                if dist < settings.PROPS_RADIUS {
+                    wanted.push((dist, cell));
+                }
+            }
+        }
+    }
+    // closest first, probes close to each other want the same cells
+    wanted.sort_by(|a, b| a.0.total_cmp(&b.0));
+    wanted.dedup_by_key(|(_, cell)| *cell);
+
+    for (_, cell) in wanted.into_iter().take(PROP_CELLS_PER_FRAME) {
+        if props.cells.contains_key(&cell) {
+            continue;
+        }
+        let cell_ent = commands
+            .spawn((
+                SpatialBundle::default(),
+                Name::new(format!("Props {} {}", cell.x, cell.y)),
+            ))
+            .id();
+        for (index, placement) in scatter_cell(props.seed, cell).iter().enumerate() {
+            let destroyed = props.destroyed.contains(&(cell, index));
+            let prop = Prop {
+                def: placement.def,
+                cell,
+                index,
+                hit_points: if destroyed {
+                    0.0
+                } else {
+                    PROP_LIBRARY[placement.def].hit_points
+                },
+            };
+            spawn_prop(&mut commands, &scene_assets, placement, prop, cell_ent);
+        }
+        props.cells.insert(cell, cell_ent);
+    }
+}
+
+/// the model, and a box collider that blocks the tanks and the shells.
+/// Props out of hit points come as wrecks.
+fn spawn_prop(
+    commands: &mut Commands,
+    scene_assets: &GameSceneAssets,
+    placement: &PropPlacement,
+    prop: Prop,
+    parent: Entity,
+) {
+    let def = &PROP_LIBRARY[placement.def];
+    let destroyed = prop.hit_points <= 0.0;
+    let damaged = prop.is_damaged();
+    let prop_ent = commands
+        .spawn((
+            prop,
+            SpatialBundle::from_transform(
+                Transform::from_translation(placement.position).with_rotation(placement.rotation),
+            ),
+            RigidBody::Fixed,
+            Name::new(def.file),
+        ))
+        .set_parent(parent)
+        .id();
+    if destroyed {
+        spawn_wreck(commands, scene_assets, prop_ent, def, None);
+    } else {
+        spawn_standing(commands, scene_assets, prop_ent, def, damaged);
+    }
+}
+
+/// Swaps the model of a prop that just got damaged for its damaged model.
+pub fn damage_prop(
+    commands: &mut Commands,
+    scene_assets: &GameSceneAssets,
+    prop_ent: Entity,
+    prop: &Prop,
+) {
+    commands.entity(prop_ent).despawn_descendants();
+    spawn_standing(
+        commands,
+        scene_assets,
+        prop_ent,
+        &PROP_LIBRARY[prop.def],
+        true,
+    );
+}
+
+fn spawn_standing(
+    commands: &mut Commands,
+    scene_assets: &GameSceneAssets,
+    prop_ent: Entity,
+    def: &PropDef,
+    damaged: bool,
+) {
+    let (key, transform) = match def.fracture_transform() {
+        Some(transform) if damaged => (def.damaged_key(), transform),
+        _ => (def.scene_key(), def.model_transform()),
+    };
+    let Some(scene) = scene_assets.scenes.get(&key) else {
+        warn!("prop scene not loaded: {}", key);
+        return;
+    };
+    let half = def.half_extents();
+    commands.entity(prop_ent).with_children(|prop| {
+        prop.spawn((
+            SceneBundle {
+                scene: scene.clone(),
+                transform,
+                ..default()
+            },
+            Name::new("Prop Model"),
+        ));
+        prop.spawn((
+            Collider::cuboid(half.x, half.y, half.z),
+            TransformBundle::from(Transform::from_translation(Vec3::Y * half.y)),
+            PropCollider,
+        ));
+    });
+}
+
+/// part of the prop height the rubble collider keeps
+const RUBBLE_HEIGHT: f32 = 0.2;
+
+/// The fractured model of a prop brought down. Its pieces fly away from the
+/// blast as debris once the scene is in.
+#[derive(Reflect, Component, Debug)]
+pub struct Wreck {
+    /// None for wrecks streamed back in, their pieces just fall in place
+    pub blast: Option<Vec3>,
+}
+
+/// Brings the prop down: swaps its model and collider for the fractured model
+/// and a low rubble collider, and remembers it so it streams back in as a wreck.
+pub fn wreck_prop(
+    commands: &mut Commands,
+    scene_assets: &GameSceneAssets,
+    props: &mut PropCells,
+    prop_ent: Entity,
+    prop: &Prop,
+    blast: Vec3,
+) {
+    props.destroyed.insert((prop.cell, prop.index));
+    commands.entity(prop_ent).despawn_descendants();
+    spawn_wreck(
+        commands,
+        scene_assets,
+        prop_ent,
+        &PROP_LIBRARY[prop.def],
+        Some(blast),
+    );
+}
+
+fn spawn_wreck(
+    commands: &mut Commands,
+    scene_assets: &GameSceneAssets,
+    prop_ent: Entity,
+    def: &PropDef,
+    blast: Option<Vec3>,
+) {
+    let (Some(scene), Some(transform)) = (
+        scene_assets.scenes.get(&def.fracture_key()),
+        def.fracture_transform(),
+    ) else {
+        warn!("prop fracture scene not loaded: {}", def.file);
+        return;
+    };
+    let half = def.half_extents();
+    let rubble = half.y * RUBBLE_HEIGHT;
+    commands.entity(prop_ent).with_children(|prop| {
+        prop.spawn((
+            SceneBundle {
+                scene: scene.clone(),
+                transform,
+                ..default()
+            },
+            Wreck { blast },
+            Name::new("Prop Wreck"),
+        ));
+        prop.spawn((
+            Collider::cuboid(half.x, rubble, half.z),
+            TransformBundle::from(Transform::from_translation(Vec3::Y * rubble)),
+            PropCollider,
+            Name::new("Prop Rubble"),
+        ));
+    });
+}
+
+#[test]
+fn test_scatter_is_deterministic_and_respects_the_ground() {
+    use crate::terrain::{apply_height, PlanetShape, TestTerrain};
+
+    let _terrain = TestTerrain::lock(PlanetShape::Flat);
+
+    let cells: Vec<IVec2> = (-6..6)
+        .flat_map(|x| (-6..6).map(move |z| IVec2::new(x * 7, z * 5)))
+        .collect();
+    let generator = terrain_generator();
+    let mut total = 0;
+    let mut differs = false;
+    for cell in cells.iter() {
+        let props = scatter_cell(3, *cell);
+        assert_eq!(props, scatter_cell(3, *cell));
+        differs |= props != scatter_cell(4, *cell);
+        total += props.len();
+
+        let origin = cell.as_vec2() * PROP_CELL_SIZE;
+        for (i, prop) in props.iter().enumerate() {
+            let def = &PROP_LIBRARY[prop.def];
+            let xz = Vec2::new(prop.position.x, prop.position.z) - origin;
+            assert!(xz.min_element() >= 0.0 && xz.max_element() <= PROP_CELL_SIZE);
+            assert!((apply_height(&prop.position) - prop.position).length() < 1e-2);
+            let (height, gradient) = height_and_gradient(&prop.position);
+            let slope = gradient.length().atan();
+            assert!(slope <= def.max_slope + 1e-4);
+            assert!(def.biomes.contains(&generator.biome(height, slope)));
+            for other in props[..i].iter() {
+                assert!(other.position.distance(prop.position) >= def.footprint());
+            }
+        }
+    }
+    assert!(
+        total > cells.len(),
+        "only {} props in {} cells",
+        total,
+        cells.len()
+    );
+    assert!(differs, "another seed should scatter other props");
+}
+
+#[test]
+fn test_models_line_up_and_blast_distance() {
+    use serde_json::Value;
+
+    // the box around the POSITION bounds of every mesh in the scene, in scene space
+    fn glb_bounds(path: &str) -> [Vec3; 2] {
+        let glb = std::fs::read(path).unwrap();
+        let json_len = u32::from_le_bytes(glb[12..16].try_into().unwrap()) as usize;
+        let gltf: Value = serde_json::from_slice(&glb[20..20 + json_len]).unwrap();
+        let vec = |value: &Value| -> Vec<f32> {
+            let values = value.as_array().unwrap().iter();
+            values.map(|v| v.as_f64().unwrap() as f32).collect()
+        };
+        let mut bounds = [Vec3::INFINITY, Vec3::NEG_INFINITY];
+        let mut nodes: Vec<(Mat4, &Value)> = gltf["scenes"][0]["nodes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|i| (Mat4::IDENTITY, i))
+            .collect();
+        while let Some((parent, index)) = nodes.pop() {
+            let node = &gltf["nodes"][index.as_u64().unwrap() as usize];
+            let local = if node["matrix"].is_array() {
+                Mat4::from_cols_slice(&vec(&node["matrix"]))
+            } else {
+                let or = |key: &str, default: &[f32]| match node[key].is_array() {
+                    true => vec(&node[key]),
+                    false => default.to_vec(),
+                };
+                Mat4::from_scale_rotation_translation(
+                    Vec3::from_slice(&or("scale", &[1.0; 3])),
+                    Quat::from_slice(&or("rotation", &[0.0, 0.0, 0.0, 1.0])),
+                    Vec3::from_slice(&or("translation", &[0.0; 3])),
+                )
+            };
+            let global = parent * local;
+            if let Some(mesh) = node["mesh"].as_u64() {
+                for primitive in gltf["meshes"][mesh as usize]["primitives"]
+                    .as_array()
+                    .unwrap()
+                {
+                    let position = primitive["attributes"]["POSITION"].as_u64().unwrap();
+                    let accessor = &gltf["accessors"][position as usize];
+                    let (min, max) = (vec(&accessor["min"]), vec(&accessor["max"]));
+                    for corner in 0..8 {
+                        let pick = |i: usize| match (corner >> i) & 1 {
+                            0 => min[i],
+                            _ => max[i],
+                        };
+                        let point = global.transform_point3(Vec3::new(pick(0), pick(1), pick(2)));
+                        bounds = [bounds[0].min(point), bounds[1].max(point)];
+                    }
+                }
+            }
+            if let Some(children) = node["children"].as_array() {
+                nodes.extend(children.iter().map(|child| (global, child)));
+            }
+        }
+        bounds
+    }
+
+    // every model of a prop, moved where it goes, stands on the origin centered on it
+    for def in PROP_LIBRARY {
+        let mut models = vec![(def.scene_key(), def.model_transform())];
+        if let Some(transform) = def.fracture_transform() {
+            models.push((def.fracture_key(), transform));
+            models.push((def.damaged_key(), transform));
+        }
+        for (key, transform) in models {
+            let [min, max] = glb_bounds(&format!("assets/{}", key));
+            let (min, max) = (
+                transform.transform_point(min),
+                transform.transform_point(max),
+            );
+            let center = (min + max) / 2.0;
+            assert!(
+                Vec2::new(center.x, center.z).length() < 0.05,
+                "{} centered at {}",
+                key,
+                center
+            );
+            assert!(min.y.abs() < 0.05, "{} stands at {}", key, min.y);
+        }
+    }
+
+    let def = &PROP_LIBRARY[0];
+    let half = def.half_extents();
+    let prop_tr = Transform::from_xyz(10.0, 5.0, -3.0).with_rotation(Quat::from_rotation_y(1.0));
+    let inside = prop_tr.transform_point(Vec3::Y * half.y);
+    assert_eq!(def.distance_to(&prop_tr, inside), 0.0);
+    let above = prop_tr.transform_point(Vec3::Y * (half.y * 2.0 + 7.0));
+    assert!((def.distance_to(&prop_tr, above) - 7.0).abs() < 1e-4);
+    let beside = prop_tr.transform_point(Vec3::new(half.x + 3.0, half.y, 0.0));
+    assert!((def.distance_to(&prop_tr, beside) - 3.0).abs() < 1e-4);
+}
@@ -0,0 +1,87 @@
+//! Pure-Rust 2d simplex noise and fbm, the same algorithm and permutation table
+//! as simdnoise, for the targets it doesn't build on (wasm, arm).
+
+const F2: f32 = 0.366_025_42;
+const G2: f32 = 0.211_324_87;
+const G22: f32 = G2 * 2.0;
+
+/// Ken Perlin's permutation table. simdnoise stores it twice in a row, we wrap the index instead
+#[rustfmt::skip]
+const PERM: [i32; 256] = [
+    151, 160, 137, 91, 90, 15, 131, 13, 201, 95, 96, 53, 194, 233, 7, 225,
+    140, 36, 103, 30, 69, 142, 8, 99, 37, 240, 21, 10, 23, 190, 6, 148,
+    247, 120, 234, 75, 0, 26, 197, 62, 94, 252, 219, 203, 117, 35, 11, 32,
+    57, 177, 33, 88, 237, 149, 56, 87, 174, 20, 125, 136, 171, 168, 68, 175,
+    74, 165, 71, 134, 139, 48, 27, 166, 77, 146, 158, 231, 83, 111, 229, 122,
+    60, 211, 133, 230, 220, 105, 92, 41, 55, 46, 245, 40, 244, 102, 143, 54,
+    65, 25, 63, 161, 1, 216, 80, 73, 209, 76, 132, 187, 208, 89, 18, 169,
+    200, 196, 135, 130, 116, 188, 159, 86, 164, 100, 109, 198, 173, 186, 3, 64,
+    52, 217, 226, 250, 124, 123, 5, 202, 38, 147, 118, 126, 255, 82, 85, 212,
+    207, 206, 59, 227, 47, 16, 58, 17, 182, 189, 28, 42, 223, 183, 170, 213,
+    119, 248, 152, 2, 44, 154, 163, 70, 221, 153, 101, 155, 167, 43, 172, 9,
+    129, 22, 39, 253, 19, 98, 108, 110, 79, 113, 224, 232, 178, 185, 112, 104,
+    218, 246, 97, 228, 251, 34, 242, 193, 238, 210, 144, 12, 191, 179, 162, 241,
+    81, 51, 145, 235, 249, 14, 239, 107, 49, 192, 214, 31, 181, 199, 106, 157,
+    184, 84, 204, 176, 115, 121, 50, 45, 127, 4, 150, 254, 138, 236, 205, 93,
+    222, 114, 67, 29, 24, 72, 243, 141, 128, 195, 78, 66, 215, 61, 156, 180,
+];
+
+fn perm(i: i32) -> i32 {
+    PERM[(i & 0xff) as usize]
+}
+
+fn grad2(seed: i32, hash: i32, x: f32, y: f32) -> f32 {
+    let h = (hash ^ seed) & 7;
+    let (u, v) = if h < 4 { (x, 2.0 * y) } else { (y, 2.0 * x) };
+    let u = if h & 1 == 0 { u } else { -u };
+    let v = if h & 2 == 0 { v } else { -v };
+    u + v
+}
+
+pub fn simplex_2d(x: f32, y: f32, seed: i32) -> f32 {
+    let s = F2 * (x + y);
+    let ips = (x + s).floor();
+    let jps = (y + s).floor();
+    let (i, j) = (ips as i32, jps as i32);
+
+    let t = (i + j) as f32 * G2;
+    let x0 = x - (ips - t);
+    let y0 = y - (jps - t);
+
+    // second corner: one step along x or along y, depending on the simplex we're in
+    let (i1, j1) = if x0 >= y0 { (1, 0) } else { (0, 1) };
+
+    let x1 = x0 - i1 as f32 + G2;
+    let y1 = y0 - j1 as f32 + G2;
+    let x2 = x0 - 1.0 + G22;
+    let y2 = y0 - 1.0 + G22;
+
+    let (ii, jj) = (i & 0xff, j & 0xff);
+    let gi0 = perm(ii + perm(jj));
+    let gi1 = perm(ii + i1 + perm(jj + j1));
+    let gi2 = perm(ii + 1 + perm(jj + 1));
+
+    let corner = |t: f32, hash: i32, x: f32, y: f32| {
+        if t < 0.0 {
+            return 0.0;
+        }
+        let t2 = t * t;
+        t2 * t2 * grad2(seed, hash, x, y)
+    };
+    let n0 = corner(0.5 - x0 * x0 - y0 * y0, gi0, x0, y0);
+    let n1 = corner(0.5 - x1 * x1 - y1 * y1, gi1, x1, y1);
+    let n2 = corner(0.5 - x2 * x2 - y2 * y2, gi2, x2, y2);
+    n0 + (n1 + n2)
+}
+
+pub fn fbm_2d(mut x: f32, mut y: f32, lacunarity: f32, gain: f32, octaves: u8, seed: i32) -> f32 {
+    let mut result = simplex_2d(x, y, seed);
+    let mut amp = 1.0;
+    for _ in 1..octaves {
+        x *= lacunarity;
+        y *= lacunarity;
+        amp *= gain;
+        result += simplex_2d(x, y, seed) * amp;
+    }
+    result
+}
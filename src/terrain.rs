@@ -1,16 +1,27 @@
 use bevy::prelude::Vec3;
 use bevy::prelude::*;
 use bevy_inspector_egui::prelude::*;
+use serde::{Deserialize, Serialize};
 use smart_default::SmartDefault;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, RwLock};
+
+use crate::crater::{Crater, Craters};
+use crate::heightmap::Heightmap;
+use crate::terrain_generator::{Biome, Octaves, TerrainGenerator};
 
 pub const PLANET_RADIUS: f32 = 100000.0;
 pub const PLANET_MAX_PLAY_RADIUS: f32 = PLANET_RADIUS / 5.0;
+/// center of the spherical planets. sits under the origin so the play area
+/// around (0, 0, 0) is the north pole, same as the flat map.
+pub const PLANET_CENTER: Vec3 = Vec3::new(0.0, -PLANET_RADIUS, 0.0);
 
 pub const BASE_SPLIT_LEVEL: u8 = 4;
 
 #[allow(non_snake_case)]
-#[derive(Debug, Copy, Clone, Reflect, InspectorOptions, SmartDefault)]
+#[derive(Debug, Copy, Clone, Reflect, InspectorOptions, SmartDefault, Serialize, Deserialize)]
 #[reflect(InspectorOptions)]
+#[serde(default)]
 pub struct TerrainSettings {
     #[default(20)]
     #[inspector(min=BASE_SPLIT_LEVEL, max=30)]
@@ -24,6 +35,11 @@ pub struct TerrainSettings {
     #[inspector(min = 1.0, max = 10.0)]
     pub TESSELATION_VALUE: f32,
 
+    /// pixels the longest edge of a visible triangle can cover on screen before it's split
+    #[default(64.0)]
+    #[inspector(min = 4.0, max = 512.0)]
+    pub SCREEN_SPACE_ERROR: f32,
+
     #[default(0.3)]
     #[inspector(min = 0.3, max = 3.0)]
     pub MIN_CAMERA_HEIGHT: f32,
@@ -39,42 +55,604 @@ pub struct TerrainSettings {
     #[default(9.1)]
     #[inspector(min = 0.1, max = 20.0)]
     pub MIN_TRIANGLE_EDGE_SIZE: f32,
+
+    /// triangles on the whole planet. over it, the detail drops everywhere until it fits
+    #[default(300_000)]
+    #[inspector(min = 1_000, max = 5_000_000)]
+    pub TRIANGLE_BUDGET: usize,
+
+    /// memory for the children of merged triangles, kept around in case they split again
+    #[default(64)]
+    #[inspector(min = 0, max = 1024)]
+    pub SUBTREE_CACHE_MB: usize,
+
+    /// memory for meshes and colliders of base triangles, in case their leafs come back
+    #[default(256)]
+    #[inspector(min = 0, max = 4096)]
+    pub MESH_CACHE_MB: usize,
+
+    /// finished terrain meshes sent to the gpu per frame, the rest wait for the next frames
+    #[default(16)]
+    #[inspector(min = 1, max = 256)]
+    pub MESH_UPLOADS_PER_FRAME: usize,
+
+    /// read once, when the planet is built
+    #[default(PlanetShape::Icosahedron)]
+    pub PLANET_SHAPE: PlanetShape,
+
+    /// same seed, same trees and houses in the same places
+    #[default(7)]
+    pub PROPS_SEED: u32,
+
+    /// meters around the cameras and split probes the props show up
+    #[default(600.0)]
+    #[inspector(min = 100.0, max = 3000.0)]
+    pub PROPS_RADIUS: f32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Reflect, Default, Serialize, Deserialize)]
+pub enum PlanetShape {
+    /// single big triangle, heights go up on Y
+    #[default]
+    Flat,
+    /// 4 base triangles projected on the sphere
+    Tetrahedron,
+    /// 20 base triangles projected on the sphere
+    Icosahedron,
+}
+
+impl PlanetShape {
+    pub fn is_spherical(&self) -> bool {
+        *self != PlanetShape::Flat
+    }
+}
+
+/// shape of the planet currently in use. global because `height()` & co. are
+/// called from everywhere (rayon workers included) without access to the ECS.
+static PLANET_SHAPE: AtomicU8 = AtomicU8::new(PlanetShape::Flat as u8);
+
+pub fn set_planet_shape(shape: PlanetShape) {
+    PLANET_SHAPE.store(shape as u8, Ordering::Relaxed);
+}
+
+pub fn planet_shape() -> PlanetShape {
+    match PLANET_SHAPE.load(Ordering::Relaxed) {
+        1 => PlanetShape::Tetrahedron,
+        2 => PlanetShape::Icosahedron,
+        _ => PlanetShape::Flat,
+    }
+}
+
+/// generator behind `height()`, mirrored from the `TerrainGenerator` resource
+/// when the planet gets rebuilt. global for the same reasons as the shape.
+static TERRAIN_GENERATOR: RwLock<Option<Arc<TerrainGenerator>>> = RwLock::new(None);
+
+pub fn set_terrain_generator(generator: TerrainGenerator) {
+    *TERRAIN_GENERATOR.write().expect("terrain generator lock") = Some(Arc::new(generator));
+}
+
+pub fn terrain_generator() -> Arc<TerrainGenerator> {
+    if let Some(generator) = TERRAIN_GENERATOR
+        .read()
+        .expect("terrain generator lock")
+        .as_ref()
+    {
+        return generator.clone();
+    }
+    TERRAIN_GENERATOR
+        .write()
+        .expect("terrain generator lock")
+        .get_or_insert_with(Default::default)
+        .clone()
+}
+
+/// craters dug into the terrain, on top of the generator heights
+static TERRAIN_CRATERS: RwLock<Craters> = RwLock::new(Craters::new());
+
+pub fn add_crater(crater: Crater) {
+    TERRAIN_CRATERS
+        .write()
+        .expect("terrain craters lock")
+        .add(crater);
+}
+
+/// replaces all the craters, returns the old ones
+pub fn set_craters(craters: Craters) -> Craters {
+    std::mem::replace(
+        &mut *TERRAIN_CRATERS.write().expect("terrain craters lock"),
+        craters,
+    )
+}
+
+/// copy of all the craters, for saving
+pub fn craters() -> Craters {
+    TERRAIN_CRATERS
+        .read()
+        .expect("terrain craters lock")
+        .clone()
+}
+
+/// painted heights over the play area, blended into the generator heights
+static TERRAIN_HEIGHTMAP: RwLock<Option<Arc<Heightmap>>> = RwLock::new(None);
+
+/// replaces (or removes) the heightmap, returns the old one. the planet needs
+/// rebuilding after, the heights change everywhere
+pub fn set_heightmap(heightmap: Option<Heightmap>) -> Option<Arc<Heightmap>> {
+    std::mem::replace(
+        &mut *TERRAIN_HEIGHTMAP.write().expect("terrain heightmap lock"),
+        heightmap.map(Arc::new),
+    )
+}
+
+pub fn heightmap() -> Option<Arc<Heightmap>> {
+    TERRAIN_HEIGHTMAP
+        .read()
+        .expect("terrain heightmap lock")
+        .clone()
+}
+
+/// unit vector pointing away from the ground (against gravity) at this position
+pub fn up(pos: &Vec3) -> Vec3 {
+    if planet_shape().is_spherical() {
+        (*pos - PLANET_CENTER).normalize()
+    } else {
+        Vec3::Y
+    }
+}
+
+/// moves the position straight down (or up) onto the zero-height planet surface
+pub fn project_to_surface(pos: &Vec3) -> Vec3 {
+    if planet_shape().is_spherical() {
+        PLANET_CENTER + (*pos - PLANET_CENTER).normalize() * PLANET_RADIUS
+    } else {
+        Vec3::new(pos.x, 0.0, pos.z)
+    }
 }
 
 pub fn height(_pos: &Vec3) -> f32 {
-    let (x, y) = (_pos.x, _pos.z);
-    d_height(x, y)
+    let mut height = noise_heights(&terrain_generator(), std::slice::from_ref(_pos))[0];
+    if let Some(heightmap) = TERRAIN_HEIGHTMAP
+        .read()
+        .expect("terrain heightmap lock")
+        .as_ref()
+    {
+        height = heightmap.blend(_pos, height);
+    }
+    height
+        + TERRAIN_CRATERS
+            .read()
+            .expect("terrain craters lock")
+            .offset(&project_to_surface(_pos))
+}
+
+/// same as calling `height()` on each position, but the noise is computed
+/// `NOISE_LANES` positions at a time
+pub fn heights(positions: &[Vec3]) -> Vec<f32> {
+    let generator = terrain_generator();
+    let heightmap = heightmap();
+    let craters = TERRAIN_CRATERS.read().expect("terrain craters lock");
+    let mut out = Vec::with_capacity(positions.len());
+    for chunk in positions.chunks(NOISE_LANES) {
+        let lanes = noise_heights(&generator, chunk);
+        out.extend(chunk.iter().zip(lanes).map(|(pos, h)| {
+            let h = match heightmap.as_ref() {
+                Some(heightmap) => heightmap.blend(pos, h),
+                None => h,
+            };
+            h + craters.offset(&project_to_surface(pos))
+        }));
+    }
+    out
+}
+
+/// same as calling `apply_height()` on each position
+pub fn apply_heights(positions: &[Vec3]) -> Vec<Vec3> {
+    positions
+        .iter()
+        .zip(heights(positions))
+        .map(|(pos, h)| project_to_surface(pos) + up(pos) * h)
+        .collect()
 }
 
+/// the cube faces the sphere reads its noise on, as (axis, sign), +Y first
+const CUBE_FACES: [(usize, f32); 6] = [
+    (1, 1.0),
+    (1, -1.0),
+    (0, 1.0),
+    (0, -1.0),
+    (2, 1.0),
+    (2, -1.0),
+];
+
+/// where each cube face sits on the noise plane, further apart than the faces
+/// are wide so no two faces read the same noise. +Y stays at the origin, the
+/// play area gets the same terrain as on the flat map
+const CUBE_FACE_OFFSETS: [Vec2; 6] = [
+    Vec2::ZERO,
+    Vec2::new(2.5 * PLANET_RADIUS, 0.0),
+    Vec2::new(-2.5 * PLANET_RADIUS, 0.0),
+    Vec2::new(0.0, 2.5 * PLANET_RADIUS),
+    Vec2::new(0.0, -2.5 * PLANET_RADIUS),
+    Vec2::new(2.5 * PLANET_RADIUS, 2.5 * PLANET_RADIUS),
+];
+
+/// a cube face fades out where the direction along its axis drops under this,
+/// so the faces overlap around their edges and blend there
+const CUBE_FACE_FADE: f32 = 0.3;
+
+/// terrain noise of up to `NOISE_LANES` positions, unused lanes are 0.
+/// the flat map reads the noise plane at (x, z). the sphere reads it on the six
+/// faces of a cube around the planet, so every point of the surface gets its
+/// own noise, and blends the faces where they overlap
+fn noise_heights(generator: &TerrainGenerator, positions: &[Vec3]) -> Lanes {
+    let mut x = [0.0; NOISE_LANES];
+    let mut y = [0.0; NOISE_LANES];
+    if !planet_shape().is_spherical() {
+        for (i, pos) in positions.iter().enumerate() {
+            (x[i], y[i]) = (pos.x, pos.z);
+        }
+        return generator.height_lanes(&x, &y);
+    }
+
+    let mut total = [0.0; NOISE_LANES];
+    let mut total_weight = [0.0; NOISE_LANES];
+    for ((axis, sign), offset) in CUBE_FACES.into_iter().zip(CUBE_FACE_OFFSETS) {
+        let mut weight = [0.0; NOISE_LANES];
+        for (i, pos) in positions.iter().enumerate() {
+            let rel = project_to_surface(pos) - PLANET_CENTER;
+            let along = sign * rel[axis] / PLANET_RADIUS;
+            weight[i] = (along - CUBE_FACE_FADE).max(0.0).powi(2);
+            // the +Y face has x along u and z along v
+            x[i] = rel[(axis + 2) % 3] + offset.x;
+            y[i] = rel[(axis + 1) % 3] + offset.y;
+        }
+        if weight.iter().all(|w| *w == 0.0) {
+            continue;
+        }
+        let lanes = generator.height_lanes(&x, &y);
+        for i in 0..NOISE_LANES {
+            total[i] += lanes[i] * weight[i];
+            total_weight[i] += weight[i];
+        }
+    }
+    // only the unused lanes have no weight: every direction is at least
+    // 1/sqrt(3) along one of the axes, well inside that face
+    for (t, w) in total.iter_mut().zip(total_weight) {
+        if w > 0.0 {
+            *t /= w;
+        }
+    }
+    total
+}
+
+/// terrain point under (or over) the given position
 pub fn apply_height(pos: &Vec3) -> Vec3 {
-    Vec3::new(pos.x, height(pos), pos.z)
+    project_to_surface(pos) + up(pos) * height(pos)
+}
+
+/// distance between the samples of the finite differences, in meters
+const GRADIENT_SAMPLE_DIST: f32 = 1.0;
+
+/// height of the terrain under `pos`, and its gradient: tangent to the planet
+/// surface, pointing uphill, in meters of height per meter.
+pub fn height_and_gradient(pos: &Vec3) -> (f32, Vec3) {
+    surface_gradient(pos, height)
+}
+
+/// central differences of `field` along two tangent directions of the surface under `pos`
+fn surface_gradient(pos: &Vec3, field: impl Fn(&Vec3) -> f32) -> (f32, Vec3) {
+    let surface = project_to_surface(pos);
+    let (t1, t2) = up(&surface).any_orthonormal_pair();
+    let d = GRADIENT_SAMPLE_DIST;
+    let diff = |t: Vec3| (field(&(surface + t * d)) - field(&(surface - t * d))) / (2.0 * d);
+    (field(&surface), t1 * diff(t1) + t2 * diff(t2))
+}
+
+/// smooth terrain normal under `pos`
+pub fn normal(pos: &Vec3) -> Vec3 {
+    let (_, gradient) = height_and_gradient(pos);
+    normal_from_gradient(pos, &gradient)
+}
+
+pub fn normal_from_gradient(pos: &Vec3, gradient: &Vec3) -> Vec3 {
+    (up(pos) - *gradient).normalize()
+}
+
+/// what kind of ground is under `pos`
+pub fn biome(pos: &Vec3) -> Biome {
+    let (height, gradient) = height_and_gradient(pos);
+    terrain_generator().biome(height, gradient.length().atan())
+}
+
+/// steepness of the terrain under `pos`, in radians: 0 is flat, PI/2 would be a wall
+pub fn slope(pos: &Vec3) -> f32 {
+    height_and_gradient(pos).1.length().atan()
+}
+
+/// signed distance above the terrain, measured along `up()`
+pub fn altitude(pos: &Vec3) -> f32 {
+    (*pos - apply_height(pos)).dot(up(pos))
+}
+
+/// height of the sea surface, the `water_level` of the generator biomes
+pub fn water_level() -> f32 {
+    terrain_generator().biomes.water_level
+}
+
+/// signed distance above the sea surface, measured along `up()`. negative under
+/// the water level even inland, where the ground is higher: see `water_depth()`
+pub fn water_altitude(pos: &Vec3) -> f32 {
+    (*pos - project_to_surface(pos)).dot(up(pos)) - water_level()
+}
+
+/// meters of water over the terrain under `pos`, 0 on dry land
+pub fn water_depth(pos: &Vec3) -> f32 {
+    (water_level() - height(pos)).max(0.0)
 }
 
 pub const NOISE_SEED: i32 = 11;
 pub const MOUNTAIN_HEIGHT: f32 = 500.0;
 pub const NOISE_BASE_FREQ: f32 = 100.0;
 
-/// returns single noise value for unscaled position. noise is capped [-1, 1]
-fn noise_single(x: f32, y: f32, seed: i32) -> f32 {
-    use simdnoise::NoiseBuilder;
-    // size = 1 does not work gives NaN
-    NoiseBuilder::fbm_2d_offset(x, 2, y, 2)
-        .with_seed(seed)
-        .generate_scaled(-1.0, 1.0)[0]
-}
-
-fn d_height(x: f32, y: f32) -> f32 {
-    let octaves = 2;
-    let count_per_octave = 2;
-    (1..=octaves)
-        .map(|i| {
-            let exp = 1.3_f32.powi(i);
-            let freq = NOISE_BASE_FREQ * exp;
-            let height = MOUNTAIN_HEIGHT / exp;
-            (0..count_per_octave)
-                .map(|j| noise_single(x / freq, y / freq, (j + 1) * NOISE_SEED + i) * height)
-                .sum::<f32>()
+/// how many points get their noise computed at once. one avx2 call, two sse2 calls.
+pub const NOISE_LANES: usize = 8;
+pub(crate) type Lanes = [f32; NOISE_LANES];
+
+/// highest one octave of the raw simplex noise gets, measured on a fine grid. the
+/// octaves can't add up to more than their amplitudes, so fbm stays in [-1, 1]
+/// with no clamp flattening the peaks (and zeroing the gradient there)
+const SIMPLEX_AMPLITUDE: f32 = 0.0222;
+
+/// fbm noise for a lane of noise coordinates. noise is capped [-1, 1]
+pub(crate) fn noise_lanes(x: &Lanes, y: &Lanes, octaves: &Octaves, seed: i32) -> Lanes {
+    // the builder's generate_scaled() stretches every generated block to its own
+    // min/max, so neighbouring samples jumped around. use a fixed scale instead.
+    let amplitude = SIMPLEX_AMPLITUDE
+        * (0..octaves.count)
+            .map(|k| octaves.gain.powi(k as i32))
+            .sum::<f32>();
+    ActiveNoise::fbm_lanes(x, y, octaves, seed).map(|v| v / amplitude)
+}
+
+/// Where the raw fbm noise comes from. Every lane is computed on its own, so a
+/// point gets the same value whatever batch it is in.
+pub trait NoiseBackend {
+    fn fbm_lanes(x: &Lanes, y: &Lanes, octaves: &Octaves, seed: i32) -> Lanes;
+}
+
+/// `simdnoise`, with the widest simd the cpu has. intel only, scalar elsewhere.
+#[cfg(feature = "simd_noise")]
+pub struct SimdNoise;
+
+/// pure rust port of the simdnoise scalar code, builds everywhere (wasm included)
+pub struct ScalarNoise;
+
+#[cfg(feature = "simd_noise")]
+pub type ActiveNoise = SimdNoise;
+#[cfg(not(feature = "simd_noise"))]
+pub type ActiveNoise = ScalarNoise;
+
+impl NoiseBackend for ScalarNoise {
+    fn fbm_lanes(x: &Lanes, y: &Lanes, octaves: &Octaves, seed: i32) -> Lanes {
+        std::array::from_fn(|i| {
+            crate::simplex::fbm_2d(
+                x[i],
+                y[i],
+                octaves.lacunarity,
+                octaves.gain,
+                octaves.count,
+                seed,
+            )
         })
-        .sum::<f32>()
-        / octaves as f32
+    }
+}
+
+#[cfg(feature = "simd_noise")]
+impl NoiseBackend for SimdNoise {
+    #[cfg(target_arch = "x86_64")]
+    fn fbm_lanes(x: &Lanes, y: &Lanes, octaves: &Octaves, seed: i32) -> Lanes {
+        if is_x86_feature_detected!("avx2") {
+            // safe: just checked for avx2
+            unsafe { fbm_lanes_avx2(x, y, octaves, seed) }
+        } else {
+            // safe: sse2 is always there on x86_64
+            unsafe { fbm_lanes_sse2(x, y, octaves, seed) }
+        }
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    fn fbm_lanes(x: &Lanes, y: &Lanes, octaves: &Octaves, seed: i32) -> Lanes {
+        ScalarNoise::fbm_lanes(x, y, octaves, seed)
+    }
+}
+
+#[cfg(all(feature = "simd_noise", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2")]
+unsafe fn fbm_lanes_avx2(x: &Lanes, y: &Lanes, octaves: &Octaves, seed: i32) -> Lanes {
+    use std::arch::x86_64::*;
+    let noise = simdnoise::avx2::fbm_2d(
+        _mm256_loadu_ps(x.as_ptr()),
+        _mm256_loadu_ps(y.as_ptr()),
+        _mm256_set1_ps(octaves.lacunarity),
+        _mm256_set1_ps(octaves.gain),
+        octaves.count,
+        seed,
+    );
+    let mut out = [0.0; NOISE_LANES];
+    _mm256_storeu_ps(out.as_mut_ptr(), noise);
+    out
+}
+
+#[cfg(all(feature = "simd_noise", target_arch = "x86_64"))]
+#[target_feature(enable = "sse2")]
+unsafe fn fbm_lanes_sse2(x: &Lanes, y: &Lanes, octaves: &Octaves, seed: i32) -> Lanes {
+    use std::arch::x86_64::*;
+    let mut out = [0.0; NOISE_LANES];
+    for half in (0..NOISE_LANES).step_by(4) {
+        let noise = simdnoise::sse2::fbm_2d(
+            _mm_loadu_ps(x[half..].as_ptr()),
+            _mm_loadu_ps(y[half..].as_ptr()),
+            _mm_set1_ps(octaves.lacunarity),
+            _mm_set1_ps(octaves.gain),
+            octaves.count,
+            seed,
+        );
+        _mm_storeu_ps(out[half..].as_mut_ptr(), noise);
+    }
+    out
+}
+
+/// The terrain globals for one test: the planet shape it asks for, and the
+/// craters, put back when it's dropped. Every test reading the terrain holds
+/// one, so the tests changing the globals don't run under the others.
+#[cfg(test)]
+pub(crate) struct TestTerrain {
+    craters: Craters,
+    _lock: std::sync::MutexGuard<'static, ()>,
+}
+
+#[cfg(test)]
+impl TestTerrain {
+    pub(crate) fn lock(shape: PlanetShape) -> Self {
+        static LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+        // a failed test already put the globals back, when its guard got dropped
+        let lock = LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        set_planet_shape(shape);
+        Self {
+            craters: craters(),
+            _lock: lock,
+        }
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestTerrain {
+    fn drop(&mut self) {
+        set_craters(std::mem::take(&mut self.craters));
+        set_planet_shape(PlanetShape::Flat);
+    }
+}
+
+#[test]
+fn test_gradient_of_plane() {
+    let _terrain = TestTerrain::lock(PlanetShape::Flat);
+    let plane = |p: &Vec3| 0.3 * p.x - 0.2 * p.z + 5.0;
+    for pos in [Vec3::ZERO, Vec3::new(1234.5, 40.0, -987.0)] {
+        let (h, gradient) = surface_gradient(&pos, plane);
+        assert!((h - plane(&Vec3::new(pos.x, 0.0, pos.z))).abs() < 1e-3);
+        assert!(
+            gradient.distance(Vec3::new(0.3, 0.0, -0.2)) < 1e-3,
+            "{gradient}"
+        );
+    }
+}
+
+#[test]
+fn test_normal_of_noise_field() {
+    let _terrain = TestTerrain::lock(PlanetShape::Flat);
+    // flat map: the normal is perpendicular to the terrain around the point, at a
+    // smaller scale than the one used for the differences
+    let step = 0.25;
+    let mut max_slope: f32 = 0.0;
+    for i in 0..100 {
+        let pos = Vec3::new(i as f32 * 173.3 - 8000.0, 0.0, i as f32 * -91.7 + 3000.0);
+        let n = normal(&pos);
+        assert!((n.length() - 1.0).abs() < 1e-4);
+        assert!(n.y > 0.0);
+        for dir in [Vec3::X, Vec3::Z, Vec3::new(1.0, 0.0, 1.0).normalize()] {
+            let along = apply_height(&(pos + dir * step)) - apply_height(&(pos - dir * step));
+            assert!(along.normalize().dot(n).abs() < 0.01, "{pos} {n} {along}");
+        }
+        let (h, gradient) = height_and_gradient(&pos);
+        assert_eq!(h, height(&pos));
+        assert!((slope(&pos) - n.angle_between(Vec3::Y)).abs() < 1e-3);
+        assert!(gradient.y.abs() < 1e-6);
+        max_slope = max_slope.max(slope(&pos));
+    }
+    assert!(max_slope > 0.01, "the test points should not all be flat");
+}
+
+#[test]
+fn test_batched_heights_match_single_heights() {
+    let _terrain = TestTerrain::lock(PlanetShape::Flat);
+    // not a multiple of the lanes, so the last batch is partly empty
+    let positions: Vec<Vec3> = (0..19)
+        .map(|i| Vec3::new(i as f32 * 311.7 - 2000.0, 5.0, i as f32 * 97.1 + 400.0))
+        .collect();
+    let batched = heights(&positions);
+    assert_eq!(batched.len(), positions.len());
+    for (pos, h) in positions.iter().zip(batched) {
+        assert_eq!(h, height(pos));
+    }
+    assert_eq!(apply_heights(&positions)[7], apply_height(&positions[7]));
+}
+
+#[test]
+fn test_sphere_noise_differs_between_mirrored_and_antipodal_points() {
+    use std::f32::consts::{PI, TAU};
+
+    let _terrain = TestTerrain::lock(PlanetShape::Icosahedron);
+    let (mut same, mut pairs) = (0, 0);
+    for i in 0..40 {
+        // skips the equator, which the mirror keeps in place
+        for j in (1..20).filter(|j| *j != 10) {
+            let (lon, lat) = (i as f32 / 40.0 * TAU, (j as f32 / 20.0 - 0.5) * PI);
+            let dir = Vec3::new(lat.cos() * lon.cos(), lat.sin(), lat.cos() * lon.sin());
+            let pos = PLANET_CENTER + dir * PLANET_RADIUS;
+            let mirrored = Vec3::new(pos.x, 2.0 * PLANET_CENTER.y - pos.y, pos.z);
+            let antipodal = PLANET_CENTER - dir * PLANET_RADIUS;
+            for other in [mirrored, antipodal] {
+                pairs += 1;
+                if (height(&pos) - height(&other)).abs() < 1.0 {
+                    same += 1;
+                }
+            }
+        }
+    }
+    assert!(same * 20 < pairs, "{same} of {pairs} pairs got one height");
+
+    // the play area reads the noise where the flat map does
+    let pos = project_to_surface(&Vec3::new(3000.0, 0.0, -7000.0));
+    let on_sphere = height(&pos);
+    set_planet_shape(PlanetShape::Flat);
+    let on_flat = height(&pos);
+    assert!((on_sphere - on_flat).abs() < 1e-3, "{on_sphere} {on_flat}");
+}
+
+#[cfg(feature = "simd_noise")]
+#[test]
+fn test_noise_backends_agree() {
+    let octaves = Octaves::default();
+    for row in -20..20 {
+        let x: Lanes = std::array::from_fn(|i| (row * 8 + i as i32) as f32 * 0.37);
+        let y: Lanes = [row as f32 * -0.53; NOISE_LANES];
+        let simd = SimdNoise::fbm_lanes(&x, &y, &octaves, NOISE_SEED);
+        let scalar = ScalarNoise::fbm_lanes(&x, &y, &octaves, NOISE_SEED);
+        for (a, b) in simd.iter().zip(scalar) {
+            assert!((a - b).abs() < 1e-6, "{a} != {b}");
+        }
+    }
+}
+
+#[test]
+fn test_noise_stays_in_range_without_clipping() {
+    let mut highest: f32 = 0.0;
+    for octaves in [
+        Octaves::default(),
+        Octaves {
+            count: 1,
+            ..default()
+        },
+    ] {
+        for row in -300..300 {
+            let x: Lanes = std::array::from_fn(|i| (row * 8 + i as i32) as f32 * 0.031);
+            let y: Lanes = [row as f32 * 0.029; NOISE_LANES];
+            for v in noise_lanes(&x, &y, &octaves, NOISE_SEED) {
+                assert!(v.abs() <= 1.0, "{v}");
+                highest = highest.max(v.abs());
+            }
+        }
+    }
+    // the peaks still get close to the top
+    assert!(highest > 0.9, "{highest}");
 }
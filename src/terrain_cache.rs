@@ -0,0 +1,206 @@
+use std::collections::BTreeMap;
+use std::hash::Hash;
+
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy_rapier3d::prelude::Collider;
+
+use crate::crater::Crater;
+use crate::terrain::TerrainSettings;
+use crate::tri_coord::TriCoord;
+use crate::triangle::Triangle;
+
+/// Hit/miss counters and size of one cache, for the menu.
+#[derive(Reflect, Debug, Clone, Copy, Default)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+    pub bytes: usize,
+}
+
+impl CacheStats {
+    /// 0..1, 0 when it wasn't used yet
+    pub fn hit_rate(&self) -> f32 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            return 0.0;
+        }
+        self.hits as f32 / total as f32
+    }
+}
+
+/// Least recently used cache, limited by the (estimated) bytes of its values.
+pub struct LruCache<K, V> {
+    /// value, size in bytes, last use
+    entries: HashMap<K, (V, usize, u64)>,
+    /// last use -> key, oldest first
+    order: BTreeMap<u64, K>,
+    tick: u64,
+    max_bytes: usize,
+    stats: CacheStats,
+}
+
+impl<K: Hash + Eq + Clone, V> LruCache<K, V> {
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            entries: HashMap::default(),
+            order: BTreeMap::new(),
+            tick: 0,
+            max_bytes,
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    /// drops the oldest entries until it fits
+    pub fn set_max_bytes(&mut self, max_bytes: usize) {
+        self.max_bytes = max_bytes;
+        self.evict();
+    }
+
+    pub fn insert(&mut self, key: K, value: V, bytes: usize) {
+        self.remove(&key);
+        self.tick += 1;
+        self.order.insert(self.tick, key.clone());
+        self.entries.insert(key, (value, bytes, self.tick));
+        self.stats.bytes += bytes;
+        self.stats.entries += 1;
+        self.evict();
+    }
+
+    /// counts a hit or a miss
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let Some((_, _, last_use)) = self.entries.get_mut(key) else {
+            self.stats.misses += 1;
+            return None;
+        };
+        self.stats.hits += 1;
+        self.order.remove(last_use);
+        self.tick += 1;
+        *last_use = self.tick;
+        self.order.insert(self.tick, key.clone());
+        self.entries.get(key).map(|(value, _, _)| value)
+    }
+
+    /// like `get()`, but the value leaves the cache
+    pub fn take(&mut self, key: &K) -> Option<V> {
+        let value = self.remove(key);
+        if value.is_some() {
+            self.stats.hits += 1;
+        } else {
+            self.stats.misses += 1;
+        }
+        value
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let (value, bytes, last_use) = self.entries.remove(key)?;
+        self.order.remove(&last_use);
+        self.stats.bytes -= bytes;
+        self.stats.entries -= 1;
+        Some(value)
+    }
+
+    pub fn retain(&mut self, mut keep: impl FnMut(&K, &V) -> bool) {
+        let gone: Vec<K> = self
+            .entries
+            .iter()
+            .filter(|(key, (value, _, _))| !keep(key, value))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in gone.iter() {
+            self.remove(key);
+        }
+    }
+
+    fn evict(&mut self) {
+        while self.stats.bytes > self.max_bytes {
+            let Some((_, key)) = self.order.pop_first() else {
+                break;
+            };
+            let (_, bytes, _) = self.entries.remove(&key).expect("lru order out of sync");
+            self.stats.bytes -= bytes;
+            self.stats.entries -= 1;
+        }
+    }
+}
+
+const MB: usize = 1024 * 1024;
+
+/// the terrain mesh of a base triangle, its collider and the water over it
+pub type BaseMeshes = (Mesh, Collider, Option<Mesh>);
+
+/// Work the LOD would otherwise redo when the cameras go back and forth:
+/// the children of merged triangles, and the meshes of base triangles.
+pub struct TerrainCache {
+    /// children thrown away by a merge, by the coord of their parent
+    pub subtrees: LruCache<TriCoord, [Box<Triangle>; 4]>,
+    /// by the coord of the base triangle and `Triangle::mesh_signature()`
+    pub meshes: LruCache<(TriCoord, u64), BaseMeshes>,
+}
+
+impl Default for TerrainCache {
+    fn default() -> Self {
+        let settings = TerrainSettings::default();
+        Self {
+            subtrees: LruCache::new(settings.SUBTREE_CACHE_MB * MB),
+            meshes: LruCache::new(settings.MESH_CACHE_MB * MB),
+        }
+    }
+}
+
+impl TerrainCache {
+    pub fn set_limits(&mut self, settings: &TerrainSettings) {
+        self.subtrees.set_max_bytes(settings.SUBTREE_CACHE_MB * MB);
+        self.meshes.set_max_bytes(settings.MESH_CACHE_MB * MB);
+    }
+
+    /// forgets everything with heights from before the craters
+    pub fn invalidate_craters(&mut self, craters: &[Crater], dug_trees: &[TriCoord]) {
+        if craters.is_empty() {
+            return;
+        }
+        self.subtrees
+            .retain(|_, children| !children.iter().any(|child| child.dug_by(craters)));
+        self.meshes.retain(|(key, _), _| !dug_trees.contains(key));
+    }
+}
+
+/// rough size of a mesh and its collider, in bytes
+pub fn mesh_bytes(mesh: &Mesh) -> usize {
+    let attributes: usize = mesh
+        .attributes()
+        .map(|(_, values)| values.get_bytes().len())
+        .sum();
+    let indices = mesh.indices().map_or(0, |indices| indices.len() * 4);
+    // the trimesh collider keeps its own vertices, indices and bvh
+    attributes + indices * 3
+}
+
+#[test]
+fn test_lru_evicts_the_least_recently_used() {
+    let mut cache = LruCache::<u32, &str>::new(30);
+    cache.insert(1, "one", 10);
+    cache.insert(2, "two", 10);
+    cache.insert(3, "three", 10);
+    assert_eq!(cache.get(&1), Some(&"one"));
+    // 2 is the oldest now
+    cache.insert(4, "four", 10);
+    assert_eq!(cache.get(&2), None);
+    assert_eq!(cache.take(&3), Some("three"));
+    assert_eq!(cache.get(&3), None);
+    assert_eq!(cache.get(&4), Some(&"four"));
+
+    let stats = cache.stats();
+    assert_eq!((stats.hits, stats.misses), (3, 2));
+    assert_eq!((stats.entries, stats.bytes), (2, 20));
+    assert_eq!(stats.hit_rate(), 0.6);
+
+    cache.set_max_bytes(10);
+    assert_eq!(cache.get(&1), None);
+    assert_eq!(cache.stats().entries, 1);
+}
@@ -0,0 +1,377 @@
+use std::io::{self, Write};
+use std::sync::Mutex;
+
+use bevy::prelude::*;
+use bevy::render::mesh::VertexAttributeValues;
+use rayon::prelude::*;
+use serde_json::{json, Value};
+
+use crate::lod::{LodProbe, LodViews};
+use crate::piramida::build_planet;
+use crate::terrain::TerrainSettings;
+use crate::terrain_cache::TerrainCache;
+use crate::triangle::LeafVerts;
+
+/// Plain copy of a terrain mesh, for writing it out without a bevy app.
+#[derive(Debug, Clone, Default)]
+pub struct TerrainMesh {
+    pub name: String,
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub uvs: Vec<[f32; 2]>,
+    pub colors: Vec<[f32; 4]>,
+    pub indices: Vec<u32>,
+}
+
+impl TerrainMesh {
+    /// takes the attributes `Triangle::generate_mesh()` makes
+    pub fn from_mesh(name: String, mesh: &Mesh) -> Self {
+        let Some(VertexAttributeValues::Float32x3(positions)) =
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+        else {
+            panic!("terrain mesh without positions");
+        };
+        let Some(VertexAttributeValues::Float32x3(normals)) =
+            mesh.attribute(Mesh::ATTRIBUTE_NORMAL)
+        else {
+            panic!("terrain mesh without normals");
+        };
+        let Some(VertexAttributeValues::Float32x2(uvs)) = mesh.attribute(Mesh::ATTRIBUTE_UV_0)
+        else {
+            panic!("terrain mesh without uvs");
+        };
+        let Some(VertexAttributeValues::Float32x4(colors)) = mesh.attribute(Mesh::ATTRIBUTE_COLOR)
+        else {
+            panic!("terrain mesh without colors");
+        };
+        Self {
+            name,
+            positions: positions.clone(),
+            normals: normals.clone(),
+            uvs: uvs.clone(),
+            colors: colors.clone(),
+            indices: mesh
+                .indices()
+                .expect("terrain mesh without indices")
+                .iter()
+                .map(|i| i as u32)
+                .collect(),
+        }
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    /// all the meshes in one, for when the base triangles don't need to stay apart
+    pub fn merged(name: &str, meshes: &[TerrainMesh]) -> Self {
+        let mut merged = Self {
+            name: name.to_owned(),
+            ..default()
+        };
+        for mesh in meshes {
+            let offset = merged.positions.len() as u32;
+            merged.positions.extend(&mesh.positions);
+            merged.normals.extend(&mesh.normals);
+            merged.uvs.extend(&mesh.uvs);
+            merged.colors.extend(&mesh.colors);
+            merged
+                .indices
+                .extend(mesh.indices.iter().map(|i| i + offset));
+        }
+        merged
+    }
+}
+
+/// Builds the planet and splits it around `probes` (`radius` meters around each)
+/// the same way the LOD does, only without cameras. One mesh per base triangle,
+/// stitched to each other, using the global terrain generator and craters.
+pub fn refined_terrain(
+    settings: &TerrainSettings,
+    probes: &[Vec3],
+    radius: f32,
+) -> Vec<TerrainMesh> {
+    let mut piramidă = build_planet(settings.PLANET_SHAPE);
+    let mut tris = piramidă.as_mut().base_tris();
+    let views = LodViews {
+        probes: probes
+            .iter()
+            .map(|position| LodProbe {
+                position: *position,
+                radius,
+                max_level: u8::MAX,
+                priority: 1.0,
+            })
+            .collect(),
+        ..default()
+    };
+    // nothing to reuse on a single pass, it just has to be there
+    let cache = Mutex::new(TerrainCache::default());
+    tris.par_iter_mut().for_each(|tri| {
+        tri.update_split(&views, &cache, settings);
+    });
+
+    let mut leaf_verts = LeafVerts::default();
+    for tri in tris.iter() {
+        tri.collect_leaf_verts(&mut leaf_verts);
+    }
+    tris.par_iter()
+        .map(|tri| {
+            let (mesh, _) = tri.generate_mesh(settings, &leaf_verts);
+            TerrainMesh::from_mesh(format!("triangle {}", tri.coord()), &mesh)
+        })
+        .collect()
+}
+
+/// Wavefront OBJ, one object per mesh. the vertex colors go after the positions,
+/// blender and meshlab read them from there.
+pub fn write_obj(out: &mut impl Write, meshes: &[TerrainMesh]) -> io::Result<()> {
+    writeln!(out, "# game-v3 terrain")?;
+    // obj indices start at 1 and don't restart per object
+    let mut offset = 1;
+    for mesh in meshes {
+        writeln!(out, "o {}", mesh.name)?;
+        for (p, c) in mesh.positions.iter().zip(mesh.colors.iter()) {
+            writeln!(
+                out,
+                "v {} {} {} {} {} {}",
+                p[0], p[1], p[2], c[0], c[1], c[2]
+            )?;
+        }
+        for n in mesh.normals.iter() {
+            writeln!(out, "vn {} {} {}", n[0], n[1], n[2])?;
+        }
+        for uv in mesh.uvs.iter() {
+            writeln!(out, "vt {} {}", uv[0], uv[1])?;
+        }
+        for tri in mesh.indices.chunks(3) {
+            write!(out, "f")?;
+            for i in tri {
+                let i = *i as usize + offset;
+                write!(out, " {i}/{i}/{i}")?;
+            }
+            writeln!(out)?;
+        }
+        offset += mesh.positions.len();
+    }
+    Ok(())
+}
+
+const GLB_MAGIC: u32 = 0x4654_6C67;
+const GLB_JSON_CHUNK: u32 = 0x4E4F_534A;
+const GLB_BIN_CHUNK: u32 = 0x004E_4942;
+const GL_FLOAT: u32 = 5126;
+const GL_UNSIGNED_INT: u32 = 5125;
+const GL_ARRAY_BUFFER: u32 = 34962;
+const GL_ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+/// the binary buffer of a glb, with the json for its views and accessors
+#[derive(Default)]
+struct GlbBuffer {
+    bin: Vec<u8>,
+    views: Vec<Value>,
+    accessors: Vec<Value>,
+}
+
+impl GlbBuffer {
+    /// appends the values with a view and an accessor for them, returns the accessor index
+    fn accessor<const N: usize>(
+        &mut self,
+        values: &[[f32; N]],
+        kind: &str,
+        with_bounds: bool,
+    ) -> usize {
+        let bytes: Vec<u8> = values
+            .iter()
+            .flatten()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        let index = self.push(&bytes, GL_ARRAY_BUFFER, GL_FLOAT, values.len(), kind);
+        // an empty accessor has no bounds, and infinities aren't json
+        if with_bounds && !values.is_empty() {
+            let mut min = [f32::INFINITY; N];
+            let mut max = [f32::NEG_INFINITY; N];
+            for value in values {
+                for i in 0..N {
+                    min[i] = min[i].min(value[i]);
+                    max[i] = max[i].max(value[i]);
+                }
+            }
+            self.accessors[index]["min"] = json!(min.as_slice());
+            self.accessors[index]["max"] = json!(max.as_slice());
+        }
+        index
+    }
+
+    fn index_accessor(&mut self, indices: &[u32]) -> usize {
+        let bytes: Vec<u8> = indices.iter().flat_map(|i| i.to_le_bytes()).collect();
+        self.push(
+            &bytes,
+            GL_ELEMENT_ARRAY_BUFFER,
+            GL_UNSIGNED_INT,
+            indices.len(),
+            "SCALAR",
+        )
+    }
+
+    fn push(
+        &mut self,
+        bytes: &[u8],
+        target: u32,
+        component_type: u32,
+        count: usize,
+        kind: &str,
+    ) -> usize {
+        self.views.push(json!({
+            "buffer": 0,
+            "byteOffset": self.bin.len(),
+            "byteLength": bytes.len(),
+            "target": target,
+        }));
+        self.bin.extend(bytes);
+        // every view starts 4 byte aligned
+        self.bin.resize(self.bin.len().next_multiple_of(4), 0);
+        self.accessors.push(json!({
+            "bufferView": self.views.len() - 1,
+            "componentType": component_type,
+            "count": count,
+            "type": kind,
+        }));
+        self.accessors.len() - 1
+    }
+}
+
+/// Binary glTF 2.0, one node and mesh per (non empty) mesh, all with the same
+/// plain material so the vertex colors show.
+pub fn write_glb(out: &mut impl Write, meshes: &[TerrainMesh]) -> io::Result<()> {
+    let mut buffer = GlbBuffer::default();
+    let mut gltf_meshes = vec![];
+    for mesh in meshes.iter().filter(|mesh| !mesh.indices.is_empty()) {
+        let position = buffer.accessor(&mesh.positions, "VEC3", true);
+        let normal = buffer.accessor(&mesh.normals, "VEC3", false);
+        let uv = buffer.accessor(&mesh.uvs, "VEC2", false);
+        let color = buffer.accessor(&mesh.colors, "VEC4", false);
+        let indices = buffer.index_accessor(&mesh.indices);
+        gltf_meshes.push(json!({
+            "name": mesh.name,
+            "primitives": [{
+                "attributes": {
+                    "POSITION": position,
+                    "NORMAL": normal,
+                    "TEXCOORD_0": uv,
+                    "COLOR_0": color,
+                },
+                "indices": indices,
+                "material": 0,
+            }],
+        }));
+    }
+    let nodes: Vec<Value> = (0..gltf_meshes.len())
+        .map(|i| json!({ "mesh": i }))
+        .collect();
+    let mut json = serde_json::to_vec(&json!({
+        "asset": { "version": "2.0", "generator": "game-v3 export_terrain" },
+        "scene": 0,
+        "scenes": [{ "nodes": (0..nodes.len()).collect::<Vec<_>>() }],
+        "nodes": nodes,
+        "meshes": gltf_meshes,
+        "materials": [{
+            "name": "terrain",
+            "pbrMetallicRoughness": { "metallicFactor": 0.0, "roughnessFactor": 1.0 },
+        }],
+        "buffers": [{ "byteLength": buffer.bin.len() }],
+        "bufferViews": buffer.views,
+        "accessors": buffer.accessors,
+    }))?;
+    // the json chunk is padded with spaces, the bin chunk with zeros
+    json.resize(json.len().next_multiple_of(4), b' ');
+
+    let length = 12 + 8 + json.len() + 8 + buffer.bin.len();
+    out.write_all(&GLB_MAGIC.to_le_bytes())?;
+    out.write_all(&2u32.to_le_bytes())?;
+    out.write_all(&(length as u32).to_le_bytes())?;
+    out.write_all(&(json.len() as u32).to_le_bytes())?;
+    out.write_all(&GLB_JSON_CHUNK.to_le_bytes())?;
+    out.write_all(&json)?;
+    out.write_all(&(buffer.bin.len() as u32).to_le_bytes())?;
+    out.write_all(&GLB_BIN_CHUNK.to_le_bytes())?;
+    out.write_all(&buffer.bin)
+}
+
+#[test]
+fn test_export_writes_every_vertex_and_triangle() {
+    use crate::terrain::{PlanetShape, TestTerrain};
+
+    let _terrain = TestTerrain::lock(PlanetShape::Flat);
+
+    let settings = TerrainSettings {
+        MAX_SPLIT_LEVEL: 8,
+        PLANET_SHAPE: PlanetShape::Flat,
+        ..default()
+    };
+    let meshes = refined_terrain(&settings, &[Vec3::ZERO], 2000.0);
+    let unrefined = refined_terrain(&settings, &[], 0.0);
+    assert_eq!(meshes.len(), unrefined.len());
+    let triangles: usize = meshes.iter().map(|m| m.triangle_count()).sum();
+    let verts: usize = meshes.iter().map(|m| m.positions.len()).sum();
+    assert!(triangles > unrefined.iter().map(|m| m.triangle_count()).sum());
+
+    let mut obj = vec![];
+    write_obj(&mut obj, &meshes).unwrap();
+    let obj = String::from_utf8(obj).unwrap();
+    let count = |prefix: &str| obj.lines().filter(|l| l.starts_with(prefix)).count();
+    assert_eq!(count("o "), meshes.len());
+    assert_eq!(count("v "), verts);
+    assert_eq!(count("vn "), verts);
+    assert_eq!(count("f "), triangles);
+    let last_index = obj
+        .lines()
+        .filter(|l| l.starts_with("f "))
+        .flat_map(|l| l.split(' ').skip(1))
+        .map(|v| v.split('/').next().unwrap().parse::<usize>().unwrap())
+        .max();
+    assert_eq!(last_index, Some(verts));
+
+    let merged = TerrainMesh::merged("terrain", &meshes);
+    assert_eq!(merged.triangle_count(), triangles);
+    let mut glb = vec![];
+    write_glb(&mut glb, &[merged]).unwrap();
+    let word = |at: usize| u32::from_le_bytes(glb[at..at + 4].try_into().unwrap());
+    assert_eq!(word(0), GLB_MAGIC);
+    assert_eq!(word(8) as usize, glb.len());
+    let json_len = word(12) as usize;
+    assert_eq!(word(16), GLB_JSON_CHUNK);
+    let json: Value = serde_json::from_slice(&glb[20..20 + json_len]).unwrap();
+    let accessors = json["accessors"].as_array().unwrap();
+    assert_eq!(accessors[0]["count"], verts);
+    assert_eq!(accessors[0]["type"], "VEC3");
+    assert_eq!(accessors[0]["min"].as_array().unwrap().len(), 3);
+    assert_eq!(accessors[4]["count"], triangles * 3);
+    assert_eq!(accessors[4]["type"], "SCALAR");
+    let bin_len = word(20 + json_len) as usize;
+    assert_eq!(word(24 + json_len), GLB_BIN_CHUNK);
+    assert_eq!(28 + json_len + bin_len, glb.len());
+}
+
+#[test]
+fn test_glb_json_escapes_names_and_skips_empty_meshes() {
+    let quoted = TerrainMesh {
+        name: "triangle \"0\"\n".to_owned(),
+        positions: vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 2.0]],
+        normals: vec![[0.0, 0.0, 1.0]; 3],
+        uvs: vec![[0.0, 0.0]; 3],
+        colors: vec![[1.0; 4]; 3],
+        indices: vec![0, 1, 2],
+    };
+    let empty = TerrainMesh {
+        name: "empty".to_owned(),
+        ..default()
+    };
+    let mut glb = vec![];
+    write_glb(&mut glb, &[quoted, empty]).unwrap();
+    let json_len = u32::from_le_bytes(glb[12..16].try_into().unwrap()) as usize;
+    let json: Value = serde_json::from_slice(&glb[20..20 + json_len]).unwrap();
+    assert_eq!(json["meshes"].as_array().unwrap().len(), 1);
+    assert_eq!(json["meshes"][0]["name"], "triangle \"0\"\n");
+    assert_eq!(json["accessors"][0]["max"], json!([1.0, 1.0, 2.0]));
+}
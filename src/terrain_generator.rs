@@ -0,0 +1,315 @@
+use bevy::prelude::*;
+use bevy_inspector_egui::prelude::*;
+use smart_default::SmartDefault;
+
+use crate::terrain::{
+    noise_lanes, Lanes, MOUNTAIN_HEIGHT, NOISE_BASE_FREQ, NOISE_LANES, NOISE_SEED,
+};
+
+/// added to a layer's seed for its mask noise
+const MASK_SEED_OFFSET: i32 = 1000;
+/// added to a domain warp layer's seed for the noise that pushes y
+const WARP_SEED_OFFSET: i32 = 2000;
+/// how much mask noise it takes for a masked layer to fade in completely
+const MASK_FADE: f32 = 0.2;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Reflect, Default)]
+pub enum NoiseKind {
+    /// plain fractal noise, rolling hills
+    #[default]
+    Fbm,
+    /// sharp crests where the fbm crosses zero, mountain ranges
+    Ridged,
+    /// rounded bumps with creases in between
+    Billow,
+    /// adds no height, pushes the coordinates of the layers after it around
+    DomainWarp,
+}
+
+/// how the octaves of the fbm noise are stacked
+#[derive(Debug, Copy, Clone, PartialEq, Reflect, InspectorOptions, SmartDefault)]
+#[reflect(InspectorOptions)]
+pub struct Octaves {
+    #[default(3)]
+    #[inspector(min = 1, max = 8)]
+    pub count: u8,
+
+    /// frequency multiplier from one octave to the next
+    #[default(0.5)]
+    #[inspector(min = 0.1, max = 4.0)]
+    pub lacunarity: f32,
+
+    /// amplitude multiplier from one octave to the next
+    #[default(2.0)]
+    #[inspector(min = 0.1, max = 4.0)]
+    pub gain: f32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Reflect, InspectorOptions, SmartDefault)]
+#[reflect(InspectorOptions)]
+pub struct NoiseLayer {
+    #[default(true)]
+    pub enabled: bool,
+
+    pub kind: NoiseKind,
+
+    #[default(NOISE_SEED)]
+    pub seed: i32,
+
+    /// noise coordinates per kilometer, features are roughly 1 / frequency km wide
+    #[default(0.15)]
+    #[inspector(min = 0.001, max = 100.0)]
+    pub frequency: f32,
+
+    /// meters. for `DomainWarp`, how far the coordinates get pushed
+    #[default(200.0)]
+    #[inspector(min = 0.0, max = 5000.0)]
+    pub amplitude: f32,
+
+    pub octaves: Octaves,
+
+    /// frequency of the mask noise, same units. 0 means no mask
+    #[default(0.0)]
+    #[inspector(min = 0.0, max = 100.0)]
+    pub mask_frequency: f32,
+
+    /// the layer fades in where the mask noise [-1, 1] goes over this
+    #[default(0.0)]
+    #[inspector(min = -1.0, max = 1.0)]
+    pub mask_threshold: f32,
+}
+
+impl NoiseLayer {
+    /// layer noise in [-1, 1], before the amplitude. coordinates are in meters
+    fn value_lanes(&self, x: &Lanes, y: &Lanes, seed: i32) -> Lanes {
+        let f = self.frequency / 1000.0;
+        let noise = noise_lanes(&x.map(|v| v * f), &y.map(|v| v * f), &self.octaves, seed);
+        match self.kind {
+            NoiseKind::Ridged => noise.map(|n| (1.0 - n.abs()).powi(2) * 2.0 - 1.0),
+            NoiseKind::Billow => noise.map(|n| n.abs() * 2.0 - 1.0),
+            NoiseKind::Fbm | NoiseKind::DomainWarp => noise,
+        }
+    }
+
+    /// 0 where the layer is hidden, 1 where it's fully there
+    fn mask_lanes(&self, x: &Lanes, y: &Lanes) -> Lanes {
+        if self.mask_frequency <= 0.0 {
+            return [1.0; NOISE_LANES];
+        }
+        let f = self.mask_frequency / 1000.0;
+        let seed = self.seed.wrapping_add(MASK_SEED_OFFSET);
+        noise_lanes(
+            &x.map(|v| v * f),
+            &y.map(|v| v * f),
+            &Octaves::default(),
+            seed,
+        )
+        .map(|n| {
+            let t = ((n - self.mask_threshold) / MASK_FADE).clamp(0.0, 1.0);
+            t * t * (3.0 - 2.0 * t)
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Reflect)]
+pub enum Biome {
+    Water,
+    Plains,
+    Hills,
+    Mountains,
+}
+
+impl Biome {
+    /// tint for the terrain vertices
+    pub fn color(&self) -> Color {
+        match self {
+            Biome::Water => Color::rgb(0.25, 0.4, 0.85),
+            Biome::Plains => Color::rgb(0.5, 0.8, 0.35),
+            Biome::Hills => Color::rgb(0.75, 0.7, 0.45),
+            Biome::Mountains => Color::rgb(0.7, 0.7, 0.7),
+        }
+    }
+}
+
+/// height and slope thresholds between the biomes. heights in meters, slopes in radians
+#[derive(Debug, Copy, Clone, PartialEq, Reflect, InspectorOptions, SmartDefault)]
+#[reflect(InspectorOptions)]
+pub struct BiomeSettings {
+    #[default(-250.0)]
+    #[inspector(min = -2000.0, max = 2000.0)]
+    pub water_level: f32,
+
+    #[default(100.0)]
+    #[inspector(min = -2000.0, max = 2000.0)]
+    pub hills_height: f32,
+
+    #[default(300.0)]
+    #[inspector(min = -2000.0, max = 2000.0)]
+    pub mountains_height: f32,
+
+    #[default(0.25)]
+    #[inspector(min = 0.0, max = 1.5)]
+    pub hills_slope: f32,
+
+    #[default(0.6)]
+    #[inspector(min = 0.0, max = 1.5)]
+    pub mountains_slope: f32,
+}
+
+/// Describes the terrain height field. Edited from the menu; the planet is rebuilt
+/// with it and `terrain::height()` & co. use a copy of it.
+#[derive(Debug, Clone, PartialEq, Reflect, Resource, InspectorOptions, SmartDefault)]
+#[reflect(Resource, InspectorOptions)]
+pub struct TerrainGenerator {
+    /// added up in order. a domain warp layer only moves the layers after it
+    #[default(default_layers())]
+    pub layers: Vec<NoiseLayer>,
+
+    pub biomes: BiomeSettings,
+}
+
+/// the fbm stack the terrain had before the generator was configurable
+fn default_layers() -> Vec<NoiseLayer> {
+    let octaves = 2;
+    let count_per_octave = 2;
+    let mut layers = vec![];
+    for i in 1..=octaves {
+        let exp = 1.3_f32.powi(i);
+        for j in 0..count_per_octave {
+            layers.push(NoiseLayer {
+                seed: (j + 1) * NOISE_SEED + i,
+                frequency: 20.0 / (NOISE_BASE_FREQ * exp),
+                amplitude: MOUNTAIN_HEIGHT / exp / octaves as f32,
+                ..default()
+            });
+        }
+    }
+    layers
+}
+
+impl TerrainGenerator {
+    /// heights for a lane of zero-height surface coordinates, in meters
+    pub(crate) fn height_lanes(&self, x: &Lanes, y: &Lanes) -> Lanes {
+        let (mut x, mut y) = (*x, *y);
+        let mut total = [0.0; NOISE_LANES];
+        for layer in self.layers.iter().filter(|layer| layer.enabled) {
+            let value = layer.value_lanes(&x, &y, layer.seed);
+            if layer.kind == NoiseKind::DomainWarp {
+                let value_y = layer.value_lanes(&x, &y, layer.seed.wrapping_add(WARP_SEED_OFFSET));
+                for ((x, y), (dx, dy)) in x
+                    .iter_mut()
+                    .zip(y.iter_mut())
+                    .zip(value.iter().zip(value_y))
+                {
+                    *x += dx * layer.amplitude;
+                    *y += dy * layer.amplitude;
+                }
+                continue;
+            }
+            let mask = layer.mask_lanes(&x, &y);
+            for (t, (v, m)) in total.iter_mut().zip(value.iter().zip(mask)) {
+                *t += v * m * layer.amplitude;
+            }
+        }
+        total
+    }
+
+    /// `slope` in radians, like `terrain::slope()`
+    pub fn biome(&self, height: f32, slope: f32) -> Biome {
+        let biomes = &self.biomes;
+        if height < biomes.water_level {
+            Biome::Water
+        } else if height > biomes.mountains_height || slope > biomes.mountains_slope {
+            Biome::Mountains
+        } else if height > biomes.hills_height || slope > biomes.hills_slope {
+            Biome::Hills
+        } else {
+            Biome::Plains
+        }
+    }
+}
+
+#[cfg(test)]
+fn test_lanes() -> (Lanes, Lanes) {
+    (
+        std::array::from_fn(|i| i as f32 * 731.0 - 3000.0),
+        std::array::from_fn(|i| i as f32 * -417.0 + 1200.0),
+    )
+}
+
+#[test]
+fn test_layers_add_up() {
+    let (x, y) = test_lanes();
+    let hills = NoiseLayer::default();
+    let ridges = NoiseLayer {
+        kind: NoiseKind::Ridged,
+        seed: 5,
+        frequency: 0.4,
+        ..default()
+    };
+    let generator = |layers: Vec<NoiseLayer>| TerrainGenerator {
+        layers,
+        ..default()
+    };
+    let both = generator(vec![hills, ridges]).height_lanes(&x, &y);
+    let hills = generator(vec![hills]).height_lanes(&x, &y);
+    let ridges = generator(vec![ridges]).height_lanes(&x, &y);
+    for i in 0..NOISE_LANES {
+        assert!((both[i] - hills[i] - ridges[i]).abs() < 1e-3);
+        assert!(hills[i].abs() <= 200.0 && ridges[i].abs() <= 200.0);
+    }
+    assert!(hills != ridges);
+}
+
+#[test]
+fn test_masks_and_disabled_layers_hide_the_layer() {
+    let (x, y) = test_lanes();
+    let flat = TerrainGenerator {
+        layers: vec![
+            NoiseLayer {
+                enabled: false,
+                ..default()
+            },
+            NoiseLayer {
+                mask_frequency: 1.0,
+                mask_threshold: 1.0,
+                ..default()
+            },
+        ],
+        ..default()
+    };
+    assert_eq!(flat.height_lanes(&x, &y), [0.0; NOISE_LANES]);
+}
+
+#[test]
+fn test_domain_warp_moves_the_next_layers() {
+    let (x, y) = test_lanes();
+    let warp = |amplitude: f32| NoiseLayer {
+        kind: NoiseKind::DomainWarp,
+        amplitude,
+        ..default()
+    };
+    let generator = |layers: Vec<NoiseLayer>| {
+        TerrainGenerator {
+            layers,
+            ..default()
+        }
+        .height_lanes(&x, &y)
+    };
+    let plain = generator(vec![NoiseLayer::default()]);
+    assert_eq!(generator(vec![warp(0.0), NoiseLayer::default()]), plain);
+    assert_ne!(generator(vec![warp(800.0), NoiseLayer::default()]), plain);
+    // nothing after the warp, nothing to move
+    assert_eq!(generator(vec![NoiseLayer::default(), warp(800.0)]), plain);
+}
+
+#[test]
+fn test_biome_classification() {
+    let generator = TerrainGenerator::default();
+    assert_eq!(generator.biome(-300.0, 0.0), Biome::Water);
+    assert_eq!(generator.biome(0.0, 0.0), Biome::Plains);
+    assert_eq!(generator.biome(0.0, 0.3), Biome::Hills);
+    assert_eq!(generator.biome(150.0, 0.0), Biome::Hills);
+    assert_eq!(generator.biome(150.0, 0.7), Biome::Mountains);
+    assert_eq!(generator.biome(400.0, 0.0), Biome::Mountains);
+}
@@ -0,0 +1,218 @@
+use bevy::{
+    pbr::{MaterialPipeline, MaterialPipelineKey},
+    prelude::*,
+    reflect::{TypePath, TypeUuid},
+    render::{
+        mesh::{MeshVertexAttribute, MeshVertexBufferLayout},
+        render_resource::{
+            AddressMode, AsBindGroup, Extent3d, FilterMode, RenderPipelineDescriptor,
+            SamplerDescriptor, ShaderRef, SpecializedMeshPipelineError, TextureDimension,
+            TextureFormat, VertexFormat,
+        },
+        texture::ImageSampler,
+    },
+};
+
+use crate::terrain_generator::BiomeSettings;
+
+/// how much grass, rock, snow and sand each terrain vertex has, adding up to 1
+pub const ATTRIBUTE_SPLAT: MeshVertexAttribute =
+    MeshVertexAttribute::new("Terrain_Splat", 861_537_204, VertexFormat::Float32x4);
+
+/// meters above the water level the beaches go up to
+const SAND_HEIGHT: f32 = 30.0;
+/// meters over the mountains height from bare rock to full snow
+const SNOW_FADE_HEIGHT: f32 = 150.0;
+
+fn smoothstep(from: f32, to: f32, x: f32) -> f32 {
+    let t = ((x - from) / (to - from)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Grass, rock, snow and sand weights of a terrain vertex, from the same height
+/// and slope (radians) thresholds as the biomes. Rock on the slopes, snow over the
+/// mountains height, sand along the water, grass everywhere else.
+pub fn splat_weights(height: f32, slope: f32, biomes: &BiomeSettings) -> [f32; 4] {
+    let rock = smoothstep(biomes.hills_slope, biomes.mountains_slope, slope);
+    let snow = smoothstep(
+        biomes.mountains_height,
+        biomes.mountains_height + SNOW_FADE_HEIGHT,
+        height,
+    ) * (1.0 - rock);
+    let sand = (1.0 - smoothstep(biomes.water_level, biomes.water_level + SAND_HEIGHT, height))
+        * (1.0 - rock)
+        * (1.0 - snow);
+    let grass = (1.0 - rock - snow - sand).max(0.0);
+    [grass, rock, snow, sand]
+}
+
+/// Terrain splatting: grass, rock, snow and sand mixed by the `ATTRIBUTE_SPLAT`
+/// weights, each with its own channel of the detail texture mapped tri-planar
+/// (no uv stretching on cliffs), tinted a bit with the biome vertex colors.
+#[derive(AsBindGroup, TypeUuid, TypePath, Debug, Clone)]
+#[uuid = "3b1f6a52-7c4e-4d38-9e2a-5f0c81d7a6b4"]
+pub struct TerrainMaterial {
+    #[uniform(0)]
+    pub grass: Color,
+    #[uniform(0)]
+    pub rock: Color,
+    #[uniform(0)]
+    pub snow: Color,
+    #[uniform(0)]
+    pub sand: Color,
+    /// meters per repeat of the detail texture
+    #[uniform(0)]
+    pub detail_scale: f32,
+    /// 0 ignores the biome colors, 1 is only them
+    #[uniform(0)]
+    pub biome_tint: f32,
+    /// a grayscale detail pattern per layer: grass, rock, snow, sand in r, g, b, a
+    #[texture(1)]
+    #[sampler(2)]
+    pub detail_texture: Handle<Image>,
+}
+
+impl TerrainMaterial {
+    pub fn new(detail_texture: Handle<Image>) -> Self {
+        Self {
+            grass: Color::rgb(0.3, 0.48, 0.18),
+            rock: Color::rgb(0.42, 0.4, 0.38),
+            snow: Color::rgb(0.93, 0.95, 1.0),
+            sand: Color::rgb(0.78, 0.7, 0.5),
+            detail_scale: 8.0,
+            biome_tint: 0.25,
+            detail_texture,
+        }
+    }
+}
+
+impl Material for TerrainMaterial {
+    fn vertex_shader() -> ShaderRef {
+        "shaders/terrain_material.wgsl".into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        "shaders/terrain_material.wgsl".into()
+    }
+
+    fn specialize(
+        _pipeline: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        layout: &MeshVertexBufferLayout,
+        _key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        // shadows and the prepass run bevy's own vertex shader, with its own locations
+        if descriptor.label.as_deref() == Some("prepass_pipeline") {
+            return Ok(());
+        }
+        // the locations of the `Vertex` struct in the shader
+        let vertex_layout = layout.get_layout(&[
+            Mesh::ATTRIBUTE_POSITION.at_shader_location(0),
+            Mesh::ATTRIBUTE_NORMAL.at_shader_location(1),
+            Mesh::ATTRIBUTE_COLOR.at_shader_location(2),
+            ATTRIBUTE_SPLAT.at_shader_location(3),
+        ])?;
+        descriptor.vertex.buffers = vec![vertex_layout];
+        Ok(())
+    }
+}
+
+const DETAIL_TEXTURE_SIZE: usize = 256;
+
+/// value noise that wraps around every `period` cells
+fn tiling_noise(x: f32, y: f32, period: u32, seed: u32) -> f32 {
+    let hash = |x: u32, y: u32| {
+        let mut h = (x % period)
+            .wrapping_mul(0x27d4_eb2d)
+            .wrapping_add((y % period).wrapping_mul(0x1656_67b1))
+            .wrapping_add(seed.wrapping_mul(0x9e37_79b9));
+        h ^= h >> 15;
+        h = h.wrapping_mul(0x85eb_ca6b);
+        h ^= h >> 13;
+        (h & 0xffff) as f32 / 0xffff as f32
+    };
+    let (x0, y0) = (x.floor(), y.floor());
+    let (tx, ty) = (smoothstep(0.0, 1.0, x - x0), smoothstep(0.0, 1.0, y - y0));
+    let (x0, y0) = (x0 as u32, y0 as u32);
+    let top = hash(x0, y0) * (1.0 - tx) + hash(x0 + 1, y0) * tx;
+    let bottom = hash(x0, y0 + 1) * (1.0 - tx) + hash(x0 + 1, y0 + 1) * tx;
+    top * (1.0 - ty) + bottom * ty
+}
+
+/// a few octaves of `tiling_noise()`, starting with `period` cells over the texture
+fn tiling_fbm(u: f32, v: f32, period: u32, seed: u32) -> f32 {
+    (0..4)
+        .map(|octave| {
+            let period = period << octave;
+            tiling_noise(u * period as f32, v * period as f32, period, seed + octave)
+                * 0.5_f32.powi(octave as i32)
+        })
+        .sum::<f32>()
+        / 1.875
+}
+
+/// The detail patterns of `TerrainMaterial`, made up here so the terrain needs
+/// no texture assets. Tiles seamlessly, repeats in every direction.
+pub fn detail_texture() -> Image {
+    let size = DETAIL_TEXTURE_SIZE;
+    let mut data = Vec::with_capacity(size * size * 4);
+    for i in 0..size * size {
+        let (u, v) = (
+            (i % size) as f32 / size as f32,
+            (i / size) as f32 / size as f32,
+        );
+        let grass = tiling_fbm(u, v, 32, 1);
+        let rock = tiling_fbm(u, v, 8, 2);
+        let snow = 0.7 + 0.3 * tiling_fbm(u, v, 4, 3);
+        // ripples, bent by the noise
+        let sand = 0.5 + 0.5 * (std::f32::consts::TAU * (8.0 * v + tiling_fbm(u, v, 4, 4))).sin();
+        data.extend([grass, rock, snow, sand].map(|c| (c.clamp(0.0, 1.0) * 255.0) as u8));
+    }
+
+    let mut image = Image::new(
+        Extent3d {
+            width: size as u32,
+            height: size as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8Unorm,
+    );
+    image.sampler_descriptor = ImageSampler::Descriptor(SamplerDescriptor {
+        address_mode_u: AddressMode::Repeat,
+        address_mode_v: AddressMode::Repeat,
+        mag_filter: FilterMode::Linear,
+        min_filter: FilterMode::Linear,
+        ..default()
+    });
+    image
+}
+
+#[test]
+fn test_splat_weights_follow_height_and_slope() {
+    let biomes = BiomeSettings::default();
+    let layer = |weights: [f32; 4]| {
+        let sum: f32 = weights.iter().sum();
+        assert!((sum - 1.0).abs() < 1e-5, "weights add up to {}", sum);
+        assert!(weights.iter().all(|w| *w >= 0.0));
+        (0..4)
+            .max_by(|a, b| weights[*a].total_cmp(&weights[*b]))
+            .unwrap()
+    };
+    let (grass, rock, snow, sand) = (0, 1, 2, 3);
+    assert_eq!(layer(splat_weights(0.0, 0.0, &biomes)), grass);
+    assert_eq!(layer(splat_weights(0.0, 1.0, &biomes)), rock);
+    assert_eq!(layer(splat_weights(1000.0, 0.0, &biomes)), snow);
+    assert_eq!(layer(splat_weights(1000.0, 1.2, &biomes)), rock);
+    assert_eq!(layer(splat_weights(biomes.water_level, 0.0, &biomes)), sand);
+    assert_eq!(layer(splat_weights(-1000.0, 0.0, &biomes)), sand);
+
+    // the detail texture tiles: the first column of the rock pattern continues the last one
+    let image = detail_texture();
+    let size = DETAIL_TEXTURE_SIZE;
+    let texel = |x: usize, y: usize| image.data[(y * size + x) * 4 + 1] as i32;
+    for y in 0..size {
+        assert!((texel(0, y) - texel(size - 1, y)).abs() < 24);
+    }
+}
@@ -0,0 +1,229 @@
+use std::fmt;
+use std::str::FromStr;
+
+use bevy::prelude::*;
+
+/// deepest level the path bits have room for
+pub const MAX_COORD_LEVEL: u8 = 32;
+
+/// Address of a triangle node: the base face of the planet it's on, and the
+/// child ids (1..=4) down from there, 2 bits per level. Written out in the
+/// dotted format: face first, then the child ids, like `3.1.4.2`.
+///
+/// The children of a triangle, and the ids of their corners and edges:
+/// ```text
+///              v1
+///              /\
+///             / 2\
+///        v12 /----\ v13
+///           /\ 1  /\
+///          / 3\  / 4\
+///         /----\/----\
+///       v2     v23     v3
+/// ```
+/// 1: [v12, v23, v13], 2: [v1, v12, v13], 3: [v12, v2, v23], 4: [v13, v23, v3].
+/// Edge `i` goes from corner `i` to corner `i + 1`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Reflect, Default)]
+pub struct TriCoord {
+    face: u8,
+    level: u8,
+    /// child id - 1 of each level, the deepest in the lowest bits
+    path: u64,
+}
+
+impl TriCoord {
+    /// the base face itself, level 0
+    pub const fn face(face: u8) -> Self {
+        Self {
+            face,
+            level: 0,
+            path: 0,
+        }
+    }
+
+    pub fn face_id(&self) -> u8 {
+        self.face
+    }
+
+    pub fn level(&self) -> u8 {
+        self.level
+    }
+
+    /// 1..=4, see the picture on `TriCoord`. the face id on level 0
+    pub fn id(&self) -> u8 {
+        if self.level == 0 {
+            return self.face;
+        }
+        (self.path & 3) as u8 + 1
+    }
+
+    pub fn parent(&self) -> Option<Self> {
+        if self.level == 0 {
+            return None;
+        }
+        Some(Self {
+            face: self.face,
+            level: self.level - 1,
+            path: self.path >> 2,
+        })
+    }
+
+    pub fn child(&self, id: u8) -> Self {
+        assert!((1..=4).contains(&id), "bad child id {}", id);
+        assert!(self.level < MAX_COORD_LEVEL, "too deep for a TriCoord");
+        Self {
+            face: self.face,
+            level: self.level + 1,
+            path: self.path << 2 | (id - 1) as u64,
+        }
+    }
+
+    /// ids from the face down, the face id first
+    pub fn ids(&self) -> impl Iterator<Item = u8> + '_ {
+        std::iter::once(self.face).chain(
+            (0..self.level)
+                .rev()
+                .map(|depth| (self.path >> (2 * depth as u32) & 3) as u8 + 1),
+        )
+    }
+
+    /// the node on the other side of `edge`, on the same level, and its id for the
+    /// same edge. `None` when the edge is on the outline of the face, see
+    /// `Piramidă::neighbour()` for crossing faces.
+    pub fn neighbour(&self, edge: u8) -> Option<(Self, u8)> {
+        self.neighbour_with(edge, &|_, _| None)
+    }
+
+    /// `neighbour()`, with `faces` telling which face (and edge of it) is across the
+    /// edge of a base face
+    pub fn neighbour_with(
+        &self,
+        edge: u8,
+        faces: &impl Fn(u8, u8) -> Option<(u8, u8)>,
+    ) -> Option<(Self, u8)> {
+        assert!(edge < 3, "bad edge {}", edge);
+        let Some(parent) = self.parent() else {
+            let (face, face_edge) = faces(self.face, edge)?;
+            return Some((Self::face(face), face_edge));
+        };
+        // the middle child touches the other three
+        let sibling = match (self.id(), edge) {
+            (1, 0) => Some((3, 2)),
+            (1, 1) => Some((4, 0)),
+            (1, 2) => Some((2, 1)),
+            (2, 1) => Some((1, 2)),
+            (3, 2) => Some((1, 0)),
+            (4, 0) => Some((1, 1)),
+            _ => None,
+        };
+        if let Some((id, their_edge)) = sibling {
+            return Some((parent.child(id), their_edge));
+        }
+        // on the parent's edge: the corner child (2, 3, 4 on corner 0, 1, 2) next to
+        // ours on the other side. the shared edge runs the other way over there.
+        let (other, other_edge) = parent.neighbour_with(edge, faces)?;
+        let corner = self.id() - 2;
+        let other_corner = if corner == edge {
+            (other_edge + 1) % 3
+        } else {
+            other_edge
+        };
+        Some((other.child(other_corner + 2), other_edge))
+    }
+}
+
+impl fmt::Display for TriCoord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, id) in self.ids().enumerate() {
+            if i > 0 {
+                f.write_str(".")?;
+            }
+            write!(f, "{}", id)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for TriCoord {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut ids = s.split('.').map(|id| {
+            id.parse::<u8>()
+                .map_err(|err| format!("bad triangle coord {:?}: {}", s, err))
+        });
+        let face = ids.next().expect("split gives at least one")?;
+        let mut coord = TriCoord::face(face);
+        for id in ids {
+            let id = id?;
+            if !(1..=4).contains(&id) || coord.level >= MAX_COORD_LEVEL {
+                return Err(format!("bad triangle coord {:?}", s));
+            }
+            coord = coord.child(id);
+        }
+        Ok(coord)
+    }
+}
+
+#[cfg(test)]
+fn random_coords() -> Vec<TriCoord> {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    let mut rng = StdRng::seed_from_u64(7);
+    (0..2000)
+        .map(|_| {
+            let mut coord = TriCoord::face(rng.gen_range(1..=20));
+            for _ in 0..rng.gen_range(0..MAX_COORD_LEVEL) {
+                coord = coord.child(rng.gen_range(1..=4));
+            }
+            coord
+        })
+        .collect()
+}
+
+#[test]
+fn test_coord_round_trips_through_the_dotted_format() {
+    assert_eq!(
+        "3.1.4.2".parse::<TriCoord>().unwrap().to_string(),
+        "3.1.4.2"
+    );
+    assert_eq!(
+        "3.1.4.2".parse::<TriCoord>(),
+        Ok(TriCoord::face(3).child(1).child(4).child(2))
+    );
+    assert!("3.5".parse::<TriCoord>().is_err());
+    assert!("3..1".parse::<TriCoord>().is_err());
+    assert!("".parse::<TriCoord>().is_err());
+
+    for coord in random_coords() {
+        let text = coord.to_string();
+        assert_eq!(text.parse::<TriCoord>(), Ok(coord));
+        assert_eq!(text.split('.').count(), coord.level() as usize + 1);
+    }
+}
+
+#[test]
+fn test_coord_parent_child_and_neighbour_arithmetic() {
+    for coord in random_coords() {
+        for id in 1..=4 {
+            if coord.level() < MAX_COORD_LEVEL {
+                let child = coord.child(id);
+                assert_eq!(child.id(), id);
+                assert_eq!(child.parent(), Some(coord));
+            }
+        }
+        for edge in 0..3 {
+            // going over an edge and back over the same edge lands where it started
+            if let Some((other, other_edge)) = coord.neighbour(edge) {
+                assert_ne!(other, coord);
+                assert_eq!(other.level(), coord.level());
+                assert_eq!(other.face_id(), coord.face_id());
+                assert_eq!(other.neighbour(other_edge), Some((coord, edge)));
+            }
+        }
+    }
+    // the middle child is surrounded
+    let middle = TriCoord::face(1).child(1);
+    assert!((0..3).all(|edge| middle.neighbour(edge).is_some()));
+    assert_eq!(TriCoord::face(1).neighbour(0), None);
+}
@@ -1,57 +1,98 @@
 // use std::collections::{vec_deque, VecDeque};
 
 use bevy::prelude::*;
-use rand::Rng;
+use bevy::utils::{HashMap, HashSet};
 
 use bevy::render::mesh::{Indices, PrimitiveTopology};
+use bevy::render::primitives::Sphere;
 // use rayon::prelude::IntoParallelRefMutIterator;
 
-use super::terrain::apply_height;
+use super::terrain::{
+    apply_height, apply_heights, height_and_gradient, normal_from_gradient, project_to_surface,
+    terrain_generator, up,
+};
+use crate::crater::Crater;
+use crate::lod::LodViews;
 use crate::terrain::{TerrainSettings, BASE_SPLIT_LEVEL};
+use crate::terrain_cache::TerrainCache;
+use crate::terrain_generator::TerrainGenerator;
+use crate::terrain_material::{splat_weights, ATTRIBUTE_SPLAT};
+use crate::tri_coord::TriCoord;
 use bevy_rapier3d::prelude::*;
+use std::sync::{Arc, Mutex};
+
+/// Bit-exact key of a point on the zero-height planet surface. Neighbouring
+/// triangles compute their shared corners with the same float operations, so
+/// the same corner always gets the same key, whichever side computed it.
+pub type VertKey = [u32; 3];
+
+pub fn vert_key(v: &Vec3) -> VertKey {
+    [v.x.to_bits(), v.y.to_bits(), v.z.to_bits()]
+}
+
+/// Corners of all the leaf triangles on the planet, across all base meshes.
+/// A leaf edge with a midpoint in here touches finer neighbours and needs stitching.
+pub type LeafVerts = HashSet<VertKey>;
+
+/// meters of terrain covered by one repeat of the texture
+const UV_WORLD_SIZE: f32 = 64.0;
+/// the terrain inside a triangle can bulge past its corners, pad the height range
+/// by this much of the longest edge until the children tell us better
+const HEIGHT_RANGE_MARGIN: f32 = 0.25;
 
 #[derive(Reflect, Debug, Clone, Copy)]
 pub struct TriangleData {
+    base_verts: [Vec3; 3],
     verts: [Vec3; 3],
-    uvs: [Vec2; 3],
-    norm: [Vec3; 3],
     center: Vec3,
     max_edge_len: f32,
     min_edge_len: f32,
+    /// terrain altitude range over the triangle, bounds the terrain for culling
+    min_height: f32,
+    max_height: f32,
 }
 impl TriangleData {
-    fn new(verts: [Vec3; 3]) -> Self {
-        let mut rng = rand::thread_rng();
-        let uv_x = rng.gen_range(0.0..1.0) as f32;
-        let uv_y = rng.gen_range(0.0..1.0) as f32;
-
-        let v12 = verts[2] - verts[1];
-        let v01 = verts[1] - verts[0];
-        let norm = -v12.cross(v01).normalize();
-        let normals = [norm, norm, norm];
-
+    /// `base_verts` are on the zero-height planet surface, `verts` are the same with the height applied
+    fn new(base_verts: [Vec3; 3], verts: [Vec3; 3]) -> Self {
         let l1 = (verts[0] - verts[1]).length();
         let l2 = (verts[2] - verts[1]).length();
         let l3 = (verts[0] - verts[2]).length();
+        let max_edge_len = max3(l1, l2, l3);
 
-        // let uvs = [
-        //     Vec2 { x: 0.0, y: 0.0 },
-        //     Vec2 { x: 1.0, y: 0.0 },
-        //     Vec2 { x: 1.0, y: 1.0 },
-        // ];
-        let uvs = [
-            Vec2 { x: uv_x, y: uv_y },
-            Vec2 { x: uv_x, y: uv_y },
-            Vec2 { x: uv_x, y: uv_y },
-        ];
+        let heights: [f32; 3] =
+            std::array::from_fn(|i| (verts[i] - base_verts[i]).dot(up(&base_verts[i])));
+        let margin = HEIGHT_RANGE_MARGIN * max_edge_len;
 
         Self {
+            base_verts,
             verts,
-            uvs,
-            norm: normals,
             center: (verts[0] + verts[1] + verts[2]) / 3.0,
-            max_edge_len: max3(l1, l2, l3),
+            max_edge_len,
             min_edge_len: min3(l1, l2, l3),
+            min_height: min3(heights[0], heights[1], heights[2]) - margin,
+            max_height: max3(heights[0], heights[1], heights[2]) + margin,
+        }
+    }
+
+    /// center and radius of the circle around the base triangle, on the zero-height surface
+    fn footprint(&self) -> (Vec3, f32) {
+        let base_center = (self.base_verts[0] + self.base_verts[1] + self.base_verts[2]) / 3.0;
+        let base_radius = self
+            .base_verts
+            .iter()
+            .map(|v| v.distance(base_center))
+            .fold(0.0, f32::max);
+        (base_center, base_radius)
+    }
+
+    /// sphere around all the terrain the triangle covers
+    fn bounding_sphere(&self) -> Sphere {
+        let (base_center, base_radius) = self.footprint();
+        let half_range = (self.max_height - self.min_height) / 2.0;
+        let mid_height = (self.max_height + self.min_height) / 2.0;
+        Sphere {
+            center: (base_center + up(&base_center) * mid_height).into(),
+            radius: (base_radius * base_radius + half_range * half_range).sqrt(),
         }
     }
 }
@@ -63,10 +104,6 @@ pub struct Triangle {
     data: TriangleData,
     /// info for current triangle if no children, else all child triangles. used to build mesh into
     all_data: Vec<TriangleData>,
-    /// info for building skirts around this triangle. Generated and kept all the time.
-    skirt_data: [TriangleData; 3],
-    /// info for the required skirts for all sub-nodes.
-    all_skirt_data: Vec<TriangleData>,
     /// depth of current node
     pub level: u8,
     /// index in the parent's list of children
@@ -78,8 +115,8 @@ pub struct Triangle {
     pub max_leaf_level: u8,
     /// min level of this sub-tree where we find leafs
     pub min_leaf_level: u8,
-    /// node key (chain of IDs)
-    coord: String,
+    /// node address (chain of IDs)
+    coord: TriCoord,
     /// marks if this is first update happened or not
     was_updated: bool,
 }
@@ -92,9 +129,7 @@ impl Default for Triangle {
                 Vec3::new(0.0, -0.5, 0.0),
                 Vec3::new(0.0, 0.0, 1.0),
             ],
-            0,
-            0,
-            "",
+            TriCoord::default(),
         )
     }
 }
@@ -127,59 +162,179 @@ fn min3(l1: f32, l2: f32, l3: f32) -> f32 {
     }
 }
 
-impl Triangle {
-    pub fn new(points: [Vec3; 3], level: u8, id: u8, parent_coord: &str) -> Self {
-        let data = TriangleData::new([
-            apply_height(&points[0]),
-            apply_height(&points[1]),
-            apply_height(&points[2]),
-        ]);
+/// corners of child `id` (1..=4), see the picture on `TriCoord`
+pub(crate) fn child_corners(corners: &[Vec3; 3], id: u8) -> [Vec3; 3] {
+    let [v1, v2, v3] = *corners;
+    let [v12, v23, v13] = surface_midpoints(corners);
+    match id {
+        1 => [v12, v23, v13],
+        2 => [v1, v12, v13],
+        3 => [v12, v2, v23],
+        4 => [v13, v23, v3],
+        _ => panic!("bad child id {}", id),
+    }
+}
 
-        let coord = {
-            let mut coord = parent_coord.to_owned();
-            if !coord.is_empty() {
-                coord.push('.');
-            }
-            coord.push_str(id.to_string().as_str());
-            coord
-        };
+/// edge midpoints (v12, v23, v13), put back on the planet surface
+fn surface_midpoints(points: &[Vec3; 3]) -> [Vec3; 3] {
+    let [v1, v2, v3] = *points;
+    [
+        surface_midpoint(&v1, &v2),
+        surface_midpoint(&v2, &v3),
+        surface_midpoint(&v1, &v3),
+    ]
+}
 
-        let skirt_data = {
-            let [v1, v2, v3] = data.verts;
-            let v12 = (v1 + v2) * 0.5;
-            let v23 = (v2 + v3) * 0.5;
-            let v13 = (v1 + v3) * 0.5;
-            [
-                TriangleData::new([v1, apply_height(&v12), v2]),
-                TriangleData::new([v2, apply_height(&v23), v3]),
-                TriangleData::new([v3, apply_height(&v13), v1]),
-            ]
-        };
+/// (a + b) is commutative, so both triangles sharing an edge get the same bits here
+fn surface_midpoint(a: &Vec3, b: &Vec3) -> Vec3 {
+    project_to_surface(&((*a + *b) * 0.5))
+}
+
+/// pushes the leaf vertices found strictly inside the a->b edge, in order from a to b,
+/// as zero-height points
+fn push_edge_verts(a: &Vec3, b: &Vec3, leaf_verts: &LeafVerts, depth: u8, out: &mut Vec<Vec3>) {
+    // nothing gets split deeper than the u8 levels can count
+    if depth >= 32 {
+        return;
+    }
+    let mid = surface_midpoint(a, b);
+    if leaf_verts.contains(&vert_key(&mid)) {
+        push_edge_verts(a, &mid, leaf_verts, depth + 1, out);
+        out.push(mid);
+        push_edge_verts(&mid, b, leaf_verts, depth + 1, out);
+    }
+}
+
+/// corners of the leaf plus all the vertices its finer neighbours put on its edges, as
+/// (zero-height point, point with height) pairs around a closed polygon wound like the leaf
+fn stitched_outline(data: &TriangleData, leaf_verts: &LeafVerts) -> Vec<(Vec3, Vec3)> {
+    let mut outline = Vec::with_capacity(3);
+    let mut edge_verts = vec![];
+    for i in 0..3 {
+        let j = (i + 1) % 3;
+        outline.push((data.base_verts[i], data.verts[i]));
+        edge_verts.clear();
+        push_edge_verts(
+            &data.base_verts[i],
+            &data.base_verts[j],
+            leaf_verts,
+            0,
+            &mut edge_verts,
+        );
+        outline.extend(edge_verts.iter().map(|v| (*v, apply_height(v))));
+    }
+    outline
+}
+
+/// indexed mesh under construction. vertices are keyed on their zero-height
+/// position, so each corner shared by a few leafs is stored once.
+struct MeshBuilder {
+    index_of: HashMap<VertKey, u32>,
+    verts: Vec<Vec3>,
+    norms: Vec<Vec3>,
+    uvs: Vec<Vec2>,
+    colors: Vec<[f32; 4]>,
+    splats: Vec<[f32; 4]>,
+    indices: Vec<[u32; 3]>,
+    /// for the biome colors
+    generator: Arc<TerrainGenerator>,
+    /// planar uvs are taken in the tangent plane at the base triangle center
+    uv_origin: Vec3,
+    uv_frame: Quat,
+}
+
+impl MeshBuilder {
+    fn new(base_center: Vec3) -> Self {
+        Self {
+            index_of: HashMap::default(),
+            verts: vec![],
+            norms: vec![],
+            uvs: vec![],
+            colors: vec![],
+            splats: vec![],
+            indices: vec![],
+            generator: terrain_generator(),
+            uv_origin: base_center,
+            uv_frame: Quat::from_rotation_arc(up(&base_center), Vec3::Y),
+        }
+    }
+
+    /// `base` is on the zero-height surface, `vert` is the same point with the height applied
+    fn push_vert(&mut self, base: &Vec3, vert: &Vec3) -> u32 {
+        let idx = self.verts.len() as u32;
+        let local = self.uv_frame * (*vert - self.uv_origin);
+        self.verts.push(*vert);
+        let (height, gradient) = height_and_gradient(base);
+        let slope = gradient.length().atan();
+        let biome = self.generator.biome(height, slope);
+        self.norms.push(normal_from_gradient(base, &gradient));
+        self.colors.push(biome.color().as_rgba_f32());
+        self.splats
+            .push(splat_weights(height, slope, &self.generator.biomes));
+        self.uvs.push(Vec2::new(local.x, local.z) / UV_WORLD_SIZE);
+        idx
+    }
+
+    fn shared_vert(&mut self, base: &Vec3, vert: &Vec3) -> u32 {
+        let key = vert_key(base);
+        if let Some(idx) = self.index_of.get(&key) {
+            return *idx;
+        }
+        let idx = self.push_vert(base, vert);
+        self.index_of.insert(key, idx);
+        idx
+    }
+
+    fn build(self) -> (Mesh, Collider) {
+        let collider = Collider::trimesh(self.verts.clone(), self.indices.clone());
 
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.set_indices(Some(Indices::U32(self.indices.concat())));
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, self.verts);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, self.norms);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, self.uvs);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, self.colors);
+        mesh.insert_attribute(ATTRIBUTE_SPLAT, self.splats);
+        (mesh, collider)
+    }
+}
+
+impl Triangle {
+    /// `points` are on the zero-height planet surface; the terrain height is applied here.
+    pub fn new(points: [Vec3; 3], coord: TriCoord) -> Self {
+        let verts = apply_heights(&points);
+        Self::with_verts(points, [verts[0], verts[1], verts[2]], coord)
+    }
+
+    /// same as `new()`, for when the heights are already known
+    fn with_verts(points: [Vec3; 3], verts: [Vec3; 3], coord: TriCoord) -> Self {
+        let data = TriangleData::new(points, verts);
+        let level = coord.level();
         Self {
             data,
             all_data: vec![data],
-            all_skirt_data: vec![],
             level,
-            id,
+            id: coord.id(),
             children: None,
             max_leaf_level: level,
             min_leaf_level: level,
             coord,
-            skirt_data,
             was_updated: false,
         }
     }
-    pub fn coord(&self) -> &str {
-        return self.coord.as_str();
+
+    pub fn coord(&self) -> TriCoord {
+        self.coord
     }
 
     pub fn reverse_points(&self) -> Self {
         Self::new(
-            [self.data.verts[1], self.data.verts[0], self.data.verts[2]],
-            self.level,
-            self.id,
-            "",
+            [
+                self.data.base_verts[1],
+                self.data.base_verts[0],
+                self.data.base_verts[2],
+            ],
+            self.coord,
         )
     }
 
@@ -188,60 +343,190 @@ impl Triangle {
             return vec![self.clone()];
         }
         assert!(self.level < BASE_SPLIT_LEVEL);
-        self.split();
+        // a level at a time, so the midpoints of a level get their heights in one batch
+        let mut tris: Vec<&mut Triangle> = vec![self];
+        while tris[0].level < BASE_SPLIT_LEVEL {
+            Triangle::split_all(&mut tris);
+            tris = tris
+                .into_iter()
+                .flat_map(|tri| tri.children_mut())
+                .collect();
+        }
+        tris.into_iter().map(|tri| tri.clone()).collect()
+    }
 
-        let mut vec = Vec::<Triangle>::new();
-        for child in self
-            .children
-            .as_mut()
-            .expect("no children after split?!")
-            .iter_mut()
-        {
-            vec.extend(child.base_tris());
+    /// corners of the base triangle, on the zero-height surface
+    pub fn base_corners(&self) -> [Vec3; 3] {
+        self.data.base_verts
+    }
+
+    /// adds the corners of all current leafs to the set. call on base triangles.
+    pub fn collect_leaf_verts(&self, leaf_verts: &mut LeafVerts) {
+        for data in self.all_data.iter() {
+            leaf_verts.extend(data.base_verts.iter().map(vert_key));
+        }
+    }
+
+    /// Builds the mesh out of the current leafs. Leaf edges that have finer
+    /// neighbours (their midpoints are in `leaf_verts`) get the extra vertices
+    /// too, and the leaf is drawn as a fan around its center, so there are no
+    /// T-junctions between LOD levels, including across base meshes.
+    /// The mesh is indexed, with `terrain::normal()` normals, planar uvs, biome
+    /// colors and `TerrainMaterial` splat weights; the collider uses the same vertices.
+    pub fn generate_mesh(
+        &self,
+        _settings: &TerrainSettings,
+        leaf_verts: &LeafVerts,
+    ) -> (Mesh, Collider) {
+        assert!(self.level == BASE_SPLIT_LEVEL);
+
+        let [v1, v2, v3] = self.data.base_verts;
+        let mut builder = MeshBuilder::new(project_to_surface(&((v1 + v2 + v3) / 3.0)));
+        for data in self.all_data.iter() {
+            let outline: Vec<u32> = stitched_outline(data, leaf_verts)
+                .iter()
+                .map(|(base, vert)| builder.shared_vert(base, vert))
+                .collect();
+            if outline.len() == 3 {
+                builder.indices.push([outline[0], outline[1], outline[2]]);
+                continue;
+            }
+            // the fan center is only used by this leaf, no need to share it
+            let center = builder.push_vert(&project_to_surface(&data.center), &data.center);
+            for i in 0..outline.len() {
+                let next = outline[(i + 1) % outline.len()];
+                builder.indices.push([center, outline[i], next]);
+            }
         }
-        vec
+        builder.build()
     }
 
-    pub fn generate_mesh(&self, _settings: &TerrainSettings) -> (Mesh, Collider) {
+    /// The sea surface over the leafs that can go under `water_level`, stitched
+    /// the same way as `generate_mesh()`. The terrain hides the parts over dry land.
+    /// None when the whole base triangle is above the water.
+    pub fn generate_water_mesh(&self, leaf_verts: &LeafVerts, water_level: f32) -> Option<Mesh> {
         assert!(self.level == BASE_SPLIT_LEVEL);
 
-        let mut all_verts = Vec::<Vec3>::new();
-        let mut all_norms = Vec::<Vec3>::new();
-        let mut all_uvs = Vec::<Vec2>::new();
-        let mut all_indices = Vec::<u32>::new();
-        let mut all_indices_grp = Vec::<[u32; 3]>::new();
-
-        let mut idx: u32 = 0;
-        for data in self.all_data.iter().chain(self.all_skirt_data.iter()) {
-            all_verts.extend_from_slice(&data.verts);
-            all_norms.extend_from_slice(&data.norm);
-            all_uvs.extend_from_slice(&data.uvs);
-            all_indices.extend_from_slice(&[idx, idx + 1, idx + 2]);
-            all_indices_grp.push([idx, idx + 1, idx + 2]);
-            idx += 3;
+        let mut index_of = HashMap::<VertKey, u32>::default();
+        let mut verts: Vec<Vec3> = vec![];
+        let mut norms: Vec<Vec3> = vec![];
+        let mut indices: Vec<u32> = vec![];
+        let mut push_vert = |base: &Vec3, shared: bool| {
+            if let Some(idx) = shared.then(|| index_of.get(&vert_key(base))).flatten() {
+                return *idx;
+            }
+            let idx = verts.len() as u32;
+            verts.push(*base + up(base) * water_level);
+            norms.push(up(base));
+            if shared {
+                index_of.insert(vert_key(base), idx);
+            }
+            idx
+        };
+        let mut edge_verts = vec![];
+        for data in self.all_data.iter() {
+            if data.min_height > water_level {
+                continue;
+            }
+            let mut outline = vec![];
+            for i in 0..3 {
+                outline.push(push_vert(&data.base_verts[i], true));
+                edge_verts.clear();
+                let j = (i + 1) % 3;
+                push_edge_verts(
+                    &data.base_verts[i],
+                    &data.base_verts[j],
+                    leaf_verts,
+                    0,
+                    &mut edge_verts,
+                );
+                outline.extend(edge_verts.iter().map(|v| push_vert(v, true)));
+            }
+            if outline.len() == 3 {
+                indices.extend(outline);
+                continue;
+            }
+            let center = push_vert(&project_to_surface(&data.center), false);
+            for i in 0..outline.len() {
+                indices.extend([center, outline[i], outline[(i + 1) % outline.len()]]);
+            }
+        }
+        if indices.is_empty() {
+            return None;
         }
-        let collider = Collider::trimesh(all_verts.clone(), all_indices_grp);
 
         let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
-        mesh.set_indices(Some(Indices::U32(all_indices)));
-        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, all_verts);
-        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, all_norms);
-        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, all_uvs);
+        mesh.set_indices(Some(Indices::U32(indices)));
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, verts);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, norms);
+        Some(mesh)
+    }
 
-        // let collider =  Collider::from_bevy_mesh(&mesh, &ComputedColliderShape::TriMesh).expect("mesh incompatible with physics lib");
-        (mesh, collider)
+    /// changes whenever `generate_mesh()` would build a different mesh, for caching
+    pub fn mesh_signature(&self, leaf_verts: &LeafVerts) -> u64 {
+        use std::hash::{Hash, Hasher};
+
+        // the heights of the stitching vertices follow from their positions, no need to sample them
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        let mut edge_verts = vec![];
+        for data in self.all_data.iter() {
+            for i in 0..3 {
+                vert_key(&data.verts[i]).hash(&mut hasher);
+                edge_verts.clear();
+                let j = (i + 1) % 3;
+                push_edge_verts(
+                    &data.base_verts[i],
+                    &data.base_verts[j],
+                    leaf_verts,
+                    0,
+                    &mut edge_verts,
+                );
+                edge_verts.len().hash(&mut hasher);
+                for v in edge_verts.iter() {
+                    vert_key(v).hash(&mut hasher);
+                }
+            }
+        }
+        hasher.finish()
     }
 
     pub fn is_split(&self) -> bool {
         self.children.is_some()
     }
 
-    fn split(&mut self) {
+    fn children_mut(&mut self) -> impl Iterator<Item = &mut Triangle> {
+        self.children
+            .as_mut()
+            .expect("no children")
+            .iter_mut()
+            .map(|child| child.as_mut())
+    }
+
+    /// splits all of `tris`, with one `apply_heights()` call for all their midpoints
+    fn split_all(tris: &mut [&mut Triangle]) {
+        let midpoints: Vec<Vec3> = tris
+            .iter()
+            .flat_map(|tri| surface_midpoints(&tri.data.base_verts))
+            .collect();
+        let heights = apply_heights(&midpoints);
+        for ((tri, points), verts) in tris
+            .iter_mut()
+            .zip(midpoints.chunks_exact(3))
+            .zip(heights.chunks_exact(3))
+        {
+            tri.split_at(
+                [points[0], points[1], points[2]],
+                [verts[0], verts[1], verts[2]],
+            );
+        }
+    }
+
+    /// `mids` are the (v12, v23, v13) edge midpoints on the zero-height surface,
+    /// `mid_verts` the same with their height
+    fn split_at(&mut self, mids: [Vec3; 3], mid_verts: [Vec3; 3]) {
         assert!(!self.is_split(), "can't split with children");
-        let [v1, v2, v3] = self.data.verts;
-        let v12 = (v1 + v2) * 0.5;
-        let v23 = (v2 + v3) * 0.5;
-        let v13 = (v1 + v3) * 0.5;
+        let [v1, v2, v3] = self.data.base_verts;
+        let [v12, v23, v13] = mids;
 
         /*
         Triangle ID vs. vertex ID.
@@ -257,46 +542,89 @@ impl Triangle {
         ->    v2      v23       v3
         id=1 is neighbour of 2,3,4.
         */
+        // the corners already have their height, only the midpoints need it
+        let [h1, h2, h3] = self.data.verts;
+        let [h12, h23, h13] = mid_verts;
+        let child = |points: [Vec3; 3], verts: [Vec3; 3], id: u8| {
+            Box::new(Triangle::with_verts(points, verts, self.coord.child(id)))
+        };
         self.children = Some([
-            Box::new(Triangle::new(
-                [v12, v23, v13],
-                self.level + 1,
-                1,
-                self.coord(),
-            )),
-            Box::new(Triangle::new(
-                [v1, v12, v13],
-                self.level + 1,
-                2,
-                self.coord(),
-            )),
-            Box::new(Triangle::new(
-                [v12, v2, v23],
-                self.level + 1,
-                3,
-                self.coord(),
-            )),
-            Box::new(Triangle::new(
-                [v13, v23, v3],
-                self.level + 1,
-                4,
-                self.coord(),
-            )),
+            child([v12, v23, v13], [h12, h23, h13], 1),
+            child([v1, v12, v13], [h1, h12, h13], 2),
+            child([v12, v2, v23], [h12, h2, h23], 3),
+            child([v13, v23, v3], [h13, h23, h3], 4),
         ]);
         self.max_leaf_level = self.level + 1;
     }
-    fn merge(&mut self) {
+
+    /// splits the ones of `tris` that want it: with the same children as the last
+    /// time when they're still cached, in one `split_all()` batch otherwise.
+    /// returns true if any got split
+    fn split_wanting<'a>(
+        tris: impl Iterator<Item = &'a mut Triangle>,
+        views: &LodViews,
+        cache: &Mutex<TerrainCache>,
+        settings: &TerrainSettings,
+    ) -> bool {
+        let (mut split, mut uncached) = (false, vec![]);
+        for tri in tris.filter(|tri| tri.wants_split(views, settings)) {
+            split = true;
+            let cached = cache
+                .lock()
+                .expect("terrain cache lock")
+                .subtrees
+                .take(&tri.coord);
+            match cached {
+                Some(children) => {
+                    tri.children = Some(children);
+                    tri.max_leaf_level = tri.level + 1;
+                }
+                None => uncached.push(tri),
+            }
+        }
+        Triangle::split_all(&mut uncached);
+        split
+    }
+
+    fn wants_split(&self, views: &LodViews, settings: &TerrainSettings) -> bool {
+        if self.is_split() {
+            return false;
+        }
+        let sphere = self.data.bounding_sphere();
+        let screen_size = views.screen_size(self.data.max_edge_len, &sphere);
+        // probes reach a bit further before letting go, so they don't flip at their radius
+        let split_dist = views.probe_dist(&sphere, self.level, 1.0);
+        self.should_split(split_dist, screen_size, settings)
+    }
+
+    /// returns the children that were thrown away
+    fn merge(&mut self) -> [Box<Triangle>; 4] {
         assert!(self.is_split(), "can't mrege without children");
-        self.children = None;
         self.max_leaf_level = self.level;
+        self.children.take().expect("no children")
+    }
+
+    /// rough heap + stack size of the subtree, in bytes
+    pub fn memory_size(&self) -> usize {
+        std::mem::size_of::<Triangle>()
+            + self.all_data.capacity() * std::mem::size_of::<TriangleData>()
+            + self
+                .children
+                .iter()
+                .flatten()
+                .map(|child| child.memory_size())
+                .sum::<usize>()
     }
 
     pub fn update_split(
         &mut self,
-        probe_dist: &(impl Fn(&Vec3) -> f32 + std::marker::Sync),
+        views: &LodViews,
+        cache: &Mutex<TerrainCache>,
         settings: &TerrainSettings,
     ) -> bool {
-        let dirty = self._do_update_split(probe_dist, settings);
+        // the children get split by their parent, together with their siblings
+        let split = Triangle::split_wanting(std::iter::once(&mut *self), views, cache, settings);
+        let dirty = self._do_update_split(views, cache, settings) || split;
 
         if !self.was_updated || dirty {
             self.was_updated = true;
@@ -307,29 +635,35 @@ impl Triangle {
     /// returns true if we changed something notable and you wanna update the thing
     fn _do_update_split(
         &mut self,
-        probe_dist: &(impl Fn(&Vec3) -> f32 + std::marker::Sync),
+        views: &LodViews,
+        cache: &Mutex<TerrainCache>,
         settings: &TerrainSettings,
     ) -> bool {
         use rayon::prelude::*;
 
         let mut dirty: bool = false;
-        let closest_dist: f32 = probe_dist(&self.data.center);
-        if !self.is_split() && self.should_split(closest_dist, settings) {
-            self.split();
-            dirty = true;
-        }
-        if self.is_split() && self.should_merge(closest_dist, settings) {
-            self.merge();
+        let sphere = self.data.bounding_sphere();
+        let screen_size = views.screen_size(self.data.max_edge_len, &sphere);
+        let merge_dist = views.probe_dist(&sphere, self.level, 1.0 + settings.SPLIT_LAZY_COEF);
+        if self.is_split() && self.should_merge(merge_dist, screen_size, settings) {
+            let children = self.merge();
+            let bytes = children.iter().map(|child| child.memory_size()).sum();
+            cache
+                .lock()
+                .expect("terrain cache lock")
+                .subtrees
+                .insert(self.coord, children, bytes);
             dirty = true;
         }
         // triger children
         if self.is_split() {
+            dirty = Triangle::split_wanting(self.children_mut(), views, cache, settings) || dirty;
             let child_results: Vec<_> = self
                 .children
                 .as_mut()
                 .expect("wtf")
                 .par_iter_mut()
-                .map(|child| child.as_mut()._do_update_split(probe_dist, settings))
+                .map(|child| child.as_mut()._do_update_split(views, cache, settings))
                 .collect();
 
             for child_dirty in child_results {
@@ -339,7 +673,6 @@ impl Triangle {
         // pull data from children
         if ((!self.was_updated) || dirty) && self.level >= BASE_SPLIT_LEVEL {
             self.all_data.clear();
-            self.all_skirt_data.clear();
             if self.is_split() {
                 self.max_leaf_level = self
                     .children
@@ -358,22 +691,19 @@ impl Triangle {
                     .min()
                     .expect("no children");
 
-                for child in self.children.as_mut().expect("wtf").iter_mut() {
-                    self.all_data.extend(child.all_data.iter());
+                // children sampled the terrain inside, their height range is tighter
+                let children = self.children.as_ref().expect("wtf");
+                self.data.min_height = children
+                    .iter()
+                    .map(|x| x.data.min_height)
+                    .fold(f32::INFINITY, f32::min);
+                self.data.max_height = children
+                    .iter()
+                    .map(|x| x.data.max_height)
+                    .fold(f32::NEG_INFINITY, f32::max);
 
-                    // if child is leaf but non-max-level, add its skirts to itself
-                    if (!child.is_split())
-                        && (child.level < self.max_leaf_level || child.level == self.min_leaf_level)
-                    {
-                        for t in child.skirt_data.iter() {
-                            // in skirts, the midpoint is always the second point. we only want the skirt if the point is lower
-                            if t.verts[1].y <= (t.verts[0].y + t.verts[2].y) * 0.5 {
-                                child.all_skirt_data.push(*t);
-                            }
-                        }
-                    }
-
-                    self.all_skirt_data.extend(child.all_skirt_data.iter());
+                for child in children.iter() {
+                    self.all_data.extend(child.all_data.iter());
                 }
             } else {
                 self.all_data.push(self.data);
@@ -384,6 +714,51 @@ impl Triangle {
         dirty
     }
 
+    /// re-samples the heights of the parts of the tree the craters dug into.
+    /// returns true if anything changed and the mesh needs rebuilding
+    pub fn apply_craters(&mut self, craters: &[Crater]) -> bool {
+        // the whole dug part of the subtree gets its heights in one batch
+        let mut dug = vec![];
+        self.collect_dug(craters, &mut dug);
+        let points: Vec<Vec3> = dug.iter().flat_map(|(data, _)| data.base_verts).collect();
+        let verts = apply_heights(&points);
+        for ((data, was_updated), verts) in dug.iter_mut().zip(verts.chunks_exact(3)) {
+            **data = TriangleData::new(data.base_verts, [verts[0], verts[1], verts[2]]);
+            // pull the new data from the children on the next update
+            **was_updated = false;
+        }
+        !dug.is_empty()
+    }
+
+    /// the data of the nodes the craters dug into, with their `was_updated`
+    fn collect_dug<'a>(
+        &'a mut self,
+        craters: &[Crater],
+        out: &mut Vec<(&'a mut TriangleData, &'a mut bool)>,
+    ) {
+        if !self.dug_by(craters) {
+            return;
+        }
+        let Triangle {
+            data,
+            children,
+            was_updated,
+            ..
+        } = self;
+        for child in children.iter_mut().flatten() {
+            child.collect_dug(craters, out);
+        }
+        out.push((data, was_updated));
+    }
+
+    /// can any of the craters change the terrain of this triangle. the craters are
+    /// on the zero-height surface, so they're checked against the footprint, not
+    /// the bounding sphere up at the terrain height
+    pub fn dug_by(&self, craters: &[Crater]) -> bool {
+        let (center, radius) = self.data.footprint();
+        craters.iter().any(|crater| crater.touches(&center, radius))
+    }
+
     pub fn tri_count(&self) -> usize {
         self.all_data.len()
 
@@ -398,7 +773,8 @@ impl Triangle {
         // }
     }
 
-    fn should_split(&self, dist: f32, settings: &TerrainSettings) -> bool {
+    /// `dist` to the closest split probe, `screen_size` of the longest edge in pixels
+    fn should_split(&self, dist: f32, screen_size: f32, settings: &TerrainSettings) -> bool {
         if self.level < settings.MIN_SPLIT_LEVEL || self.level < BASE_SPLIT_LEVEL {
             return true;
         }
@@ -409,10 +785,11 @@ impl Triangle {
         } else {
             dist / self.data.min_edge_len
                 < (1.0 - settings.SPLIT_LAZY_COEF) * settings.TESSELATION_VALUE
+                || screen_size > (1.0 + settings.SPLIT_LAZY_COEF) * settings.SCREEN_SPACE_ERROR
         }
     }
 
-    fn should_merge(&self, dist: f32, settings: &TerrainSettings) -> bool {
+    fn should_merge(&self, dist: f32, screen_size: f32, settings: &TerrainSettings) -> bool {
         if self.level <= settings.MIN_SPLIT_LEVEL || self.level <= BASE_SPLIT_LEVEL {
             return false;
         }
@@ -422,6 +799,305 @@ impl Triangle {
         } else {
             dist / self.data.min_edge_len
                 > (1.0 + settings.SPLIT_LAZY_COEF) * settings.TESSELATION_VALUE
+                && screen_size < (1.0 - settings.SPLIT_LAZY_COEF) * settings.SCREEN_SPACE_ERROR
+        }
+    }
+}
+
+/// splits the base triangles around `probe` and checks that their meshes fit together:
+/// every edge is walked once each way, except along `on_outline`
+#[cfg(test)]
+fn assert_watertight(tris: &mut [Triangle], probe: Vec3, on_outline: impl Fn(&VertKey) -> bool) {
+    use crate::lod::LodProbe;
+
+    let settings = TerrainSettings {
+        MAX_SPLIT_LEVEL: 9,
+        ..Default::default()
+    };
+    let views = LodViews {
+        probes: vec![LodProbe {
+            position: probe,
+            radius: f32::INFINITY,
+            max_level: u8::MAX,
+            priority: 1.0,
+        }],
+        ..default()
+    };
+    let mut leaf_verts = LeafVerts::default();
+    let cache = Mutex::new(TerrainCache::default());
+    for tri in tris.iter_mut() {
+        tri.update_split(&views, &cache, &settings);
+        tri.collect_leaf_verts(&mut leaf_verts);
+    }
+    let max_level = tris.iter().map(|t| t.max_leaf_level).max().unwrap();
+    let min_level = tris.iter().map(|t| t.min_leaf_level).min().unwrap();
+    assert!(max_level > min_level + 1, "test needs a few LOD levels");
+
+    // count the directed edges of all the meshes
+    let mut edges = HashMap::<(VertKey, VertKey), usize>::new();
+    for tri in tris.iter() {
+        let (mesh, _) = tri.generate_mesh(&settings, &leaf_verts);
+        let positions = mesh
+            .attribute(Mesh::ATTRIBUTE_POSITION)
+            .unwrap()
+            .as_float3()
+            .unwrap();
+        let indices: Vec<usize> = mesh.indices().unwrap().iter().collect();
+        assert!(
+            positions.len() < indices.len(),
+            "leaf corners should be shared"
+        );
+        for t in indices.chunks(3) {
+            for i in 0..3 {
+                let a = vert_key(&Vec3::from(positions[t[i]]));
+                let b = vert_key(&Vec3::from(positions[t[(i + 1) % 3]]));
+                *edges.entry((a, b)).or_default() += 1;
+            }
+        }
+    }
+
+    for ((a, b), count) in edges.iter() {
+        assert_eq!(*count, 1, "edge used twice in the same direction");
+        assert!(
+            edges.contains_key(&(*b, *a)) || (on_outline(a) && on_outline(b)),
+            "open edge (crack or T-junction) inside the map"
+        );
+    }
+}
+
+#[test]
+fn test_mesh_is_watertight_across_lod_levels() {
+    use crate::piramida::{Piramidesc, Piramidă};
+    use crate::terrain::{PlanetShape, TestTerrain};
+
+    let _terrain = TestTerrain::lock(PlanetShape::Flat);
+
+    let mut piramidă = Piramidă::<1>::new();
+    let outline = piramidă.children[0].base_corners();
+    let mut tris = piramidă.base_tris();
+
+    // probe on a corner shared by a few base triangles, so the LOD steps cross base meshes
+    let probe = tris[37].base_corners()[0];
+    // the flat map has an edge, nothing to stitch to there
+    let on_outline = |key: &VertKey| {
+        let p = Vec3::new(f32::from_bits(key[0]), 0.0, f32::from_bits(key[2]));
+        (0..3).any(|i| {
+            let (a, b) = (outline[i], outline[(i + 1) % 3]);
+            let t = ((p - a).dot(b - a) / (b - a).length_squared()).clamp(0.0, 1.0);
+            (a + (b - a) * t).distance(p) < 0.1
+        })
+    };
+    assert_watertight(&mut tris, probe, on_outline);
+}
+
+#[test]
+fn test_sphere_mesh_is_watertight_across_faces() {
+    use crate::piramida::build_planet;
+    use crate::terrain::{PlanetShape, TestTerrain};
+
+    let _terrain = TestTerrain::lock(PlanetShape::Icosahedron);
+
+    let mut tris = build_planet(PlanetShape::Icosahedron).base_tris();
+
+    // probe on an icosahedron corner: the only base corners with 5 triangles around
+    // them instead of 6, where 5 faces of the planet meet
+    let mut around = HashMap::<VertKey, (Vec3, usize)>::new();
+    for corner in tris.iter().flat_map(|tri| tri.base_corners()) {
+        around.entry(vert_key(&corner)).or_insert((corner, 0)).1 += 1;
+    }
+    let probe = around
+        .values()
+        .filter(|(_, count)| *count == 5)
+        .map(|(corner, _)| *corner)
+        .max_by(|a, b| a.y.total_cmp(&b.y).then(a.x.total_cmp(&b.x)))
+        .unwrap();
+    // a sphere has no outline, every edge has a mesh on both sides
+    assert_watertight(&mut tris, probe, |_| false);
+}
+
+#[test]
+fn test_merged_subtrees_come_back_from_the_cache() {
+    use crate::lod::LodProbe;
+    use crate::piramida::{Piramidesc, Piramidă};
+    use crate::terrain::{PlanetShape, TestTerrain};
+
+    let _terrain = TestTerrain::lock(PlanetShape::Flat);
+
+    let settings = TerrainSettings {
+        MAX_SPLIT_LEVEL: 9,
+        ..Default::default()
+    };
+    let mut tri = Piramidă::<1>::new().base_tris().swap_remove(37);
+    let views_at = |position: Vec3| LodViews {
+        probes: vec![LodProbe {
+            position,
+            radius: f32::INFINITY,
+            max_level: u8::MAX,
+            priority: 1.0,
+        }],
+        ..default()
+    };
+    let near = views_at(tri.base_corners()[0]);
+    let far = views_at(Vec3::splat(1e6));
+    let cache = Mutex::new(TerrainCache::default());
+
+    tri.update_split(&near, &cache, &settings);
+    let detailed = tri.tri_count();
+    tri.update_split(&far, &cache, &settings);
+    assert!(tri.tri_count() < detailed);
+    let stats = cache.lock().unwrap().subtrees.stats();
+    assert!(stats.entries > 0 && stats.hits == 0);
+
+    tri.update_split(&near, &cache, &settings);
+    assert_eq!(tri.tri_count(), detailed);
+    assert!(cache.lock().unwrap().subtrees.stats().hits > 0);
+}
+
+#[test]
+fn test_cached_subtrees_under_a_new_crater_get_dug() {
+    use crate::lod::LodProbe;
+    use crate::piramida::{Piramidesc, Piramidă};
+    use crate::terrain::{add_crater, PlanetShape, TestTerrain};
+
+    // the crater is taken back out of the globals when the guard is dropped
+    let _terrain = TestTerrain::lock(PlanetShape::Flat);
+
+    let settings = TerrainSettings {
+        MAX_SPLIT_LEVEL: 9,
+        ..Default::default()
+    };
+    let mut tri = Piramidă::<1>::new().base_tris().swap_remove(90);
+    let views_at = |position: Vec3| LodViews {
+        probes: vec![LodProbe {
+            position,
+            radius: f32::INFINITY,
+            max_level: u8::MAX,
+            priority: 1.0,
+        }],
+        ..default()
+    };
+    let near = views_at(tri.base_corners()[0]);
+    let far = views_at(Vec3::splat(1e6));
+    let cache = Mutex::new(TerrainCache::default());
+    let leaf_heights = |tri: &Triangle| {
+        let mut heights = HashMap::new();
+        for data in tri.all_data.iter() {
+            for (base, vert) in data.base_verts.iter().zip(data.verts) {
+                heights.insert(vert_key(base), (*base, vert));
+            }
         }
+        heights
+    };
+
+    tri.update_split(&near, &cache, &settings);
+    let before = leaf_heights(&tri);
+    tri.update_split(&far, &cache, &settings);
+    assert!(cache.lock().unwrap().subtrees.stats().entries > 0);
+
+    // dug while the detail is merged away, the cached children have the old heights
+    let crater = Crater {
+        center: project_to_surface(&tri.base_corners()[0]),
+        radius: 10.0,
+        depth: 4.0,
+    };
+    add_crater(crater);
+    assert!(tri.apply_craters(&[crater]));
+    cache
+        .lock()
+        .unwrap()
+        .invalidate_craters(&[crater], &[tri.coord()]);
+
+    tri.update_split(&near, &cache, &settings);
+    let after = leaf_heights(&tri);
+    let in_crater: Vec<_> = after
+        .iter()
+        .filter(|(_, (base, _))| base.distance(crater.center) < crater.radius * 0.5)
+        .collect();
+    assert!(!in_crater.is_empty(), "the leafs are finer than the crater");
+    for (key, (base, vert)) in in_crater {
+        let (_, old_vert) = before[key];
+        assert!((old_vert - *vert).dot(up(base)) > 1.0, "{old_vert} {vert}");
+    }
+}
+
+#[test]
+fn test_water_mesh_covers_the_leafs_under_water() {
+    use crate::lod::LodProbe;
+    use crate::piramida::{Piramidesc, Piramidă};
+    use crate::terrain::{PlanetShape, TestTerrain};
+
+    let _terrain = TestTerrain::lock(PlanetShape::Flat);
+
+    let settings = TerrainSettings {
+        MAX_SPLIT_LEVEL: 8,
+        ..Default::default()
+    };
+    let mut tri = Piramidă::<1>::new().base_tris().swap_remove(37);
+    let views = LodViews {
+        probes: vec![LodProbe {
+            position: tri.base_corners()[0],
+            radius: f32::INFINITY,
+            max_level: u8::MAX,
+            priority: 1.0,
+        }],
+        ..default()
+    };
+    let cache = Mutex::new(TerrainCache::default());
+    tri.update_split(&views, &cache, &settings);
+    let mut leaf_verts = LeafVerts::default();
+    tri.collect_leaf_verts(&mut leaf_verts);
+
+    // all of it over the mountains: same triangles as the terrain, flat at the water level
+    let level = 10_000.0;
+    let water = tri.generate_water_mesh(&leaf_verts, level).unwrap();
+    let (terrain, _) = tri.generate_mesh(&settings, &leaf_verts);
+    assert_eq!(
+        water.indices().unwrap().len(),
+        terrain.indices().unwrap().len()
+    );
+    let positions = water
+        .attribute(Mesh::ATTRIBUTE_POSITION)
+        .unwrap()
+        .as_float3()
+        .unwrap();
+    assert!(positions.iter().all(|p| (p[1] - level).abs() < 1e-3));
+
+    // all of it under the deepest valleys: no water at all
+    assert!(tri.generate_water_mesh(&leaf_verts, -10_000.0).is_none());
+}
+
+#[test]
+fn test_craters_dig_into_high_ground() {
+    use crate::terrain::{add_crater, height, PlanetShape, TestTerrain};
+
+    let _terrain = TestTerrain::lock(PlanetShape::Flat);
+
+    // the highest spot around, so the terrain is far above the zero-height craters
+    let top = (-20..20)
+        .flat_map(|i| (-20..20).map(move |j| Vec3::new(i as f32, 0.0, j as f32) * 500.0))
+        .map(|pos| project_to_surface(&pos))
+        .max_by(|a, b| height(a).abs().total_cmp(&height(b).abs()))
+        .unwrap();
+    let (t1, t2) = up(&top).any_orthonormal_pair();
+    let corners = [t1 * 2.0, t1 * -1.0 + t2 * 1.7, t1 * -1.0 - t2 * 1.7]
+        .map(|offset| project_to_surface(&(top + offset)));
+    let mut leaf = Triangle::new(corners, TriCoord::default());
+    let crater = Crater {
+        center: top,
+        radius: 3.0,
+        depth: 2.0,
+    };
+    let sphere = leaf.data.bounding_sphere();
+    assert!(
+        !crater.touches(&Vec3::from(sphere.center), sphere.radius),
+        "high enough that the bounding sphere is out of the crater's reach"
+    );
+
+    let before = leaf.data.verts;
+    add_crater(crater);
+    assert!(leaf.dug_by(&[crater]));
+    assert!(leaf.apply_craters(&[crater]));
+    for (before, after) in before.iter().zip(leaf.data.verts) {
+        assert!((*before - after).dot(up(&after)) > 0.1, "{before} {after}");
     }
 }